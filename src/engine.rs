@@ -1,18 +1,43 @@
+use std::sync::Arc;
+
 use eframe::CreationContext;
 use egui::Key;
 
 use crate::math::{Point3D, Vector3D};
-use crate::renderer::{RenderView, Renderer};
+use crate::renderer::{
+    AntiAliasing, BlendMode, CompareFunction, FilterMode, LineCap, LineStyle, OccludedLines, Rect,
+    RenderTarget, RenderView, Renderer, ShadingMode, StencilOp, StencilState, Texture,
+    TransparencyMode, WireframeMode, WrapMode,
+};
 use crate::scene::{Scene, SceneNode};
 
+/// An egui image showing one render target, views render into regions of it
+struct Panel {
+    name: String,
+    target: RenderTarget,
+    texture_handle: Option<egui::TextureHandle>,
+}
+
+impl Panel {
+    fn new(name: &str, width: usize, height: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            target: RenderTarget::new(width, height),
+            texture_handle: None,
+        }
+    }
+}
+
 pub struct EngineApp {
     renderer: Renderer,
     scene: Scene,
 
+    panels: Vec<Panel>,
     views: Vec<RenderView>,
 
     show_panels: bool,
     show_second_viewport: bool,
+    picture_in_picture: bool,
 
     pub orbit_yaw: f64,
     pub orbit_pitch: f64,
@@ -21,6 +46,8 @@ pub struct EngineApp {
     pub draw_axis: bool,
     pub draw_grid: bool,
     pub draw_lights: bool,
+
+    pub anti_aliasing: AntiAliasing,
 }
 
 impl EngineApp {
@@ -28,9 +55,14 @@ impl EngineApp {
         let renderer = Renderer::new();
         let scene = Scene::new();
 
+        let panels = vec![
+            Panel::new("main", window_width, window_height),
+            Panel::new("secondary", window_width, window_height),
+        ];
         let views = vec![
-            RenderView::new("main", "main_camera", window_width, window_height),
-            RenderView::new("secondary", "secondary_camera", window_width, window_height),
+            RenderView::new("main", "main_camera"),
+            RenderView::new("secondary", "secondary_camera"),
+            RenderView::new("inset", "secondary_camera"),
         ];
 
         let orbit_yaw = 180.0;
@@ -44,10 +76,12 @@ impl EngineApp {
         EngineApp {
             renderer,
             scene,
+            panels,
             views,
 
             show_panels: true,
             show_second_viewport: false,
+            picture_in_picture: false,
 
             orbit_yaw,
             orbit_pitch,
@@ -56,6 +90,8 @@ impl EngineApp {
             draw_axis,
             draw_grid,
             draw_lights,
+
+            anti_aliasing: AntiAliasing::Off,
         }
     }
 
@@ -119,10 +155,10 @@ impl eframe::App for EngineApp {
         let orbit_step = 1.5;
 
         if ui.input(|i| i.key_down(Key::ArrowLeft)) {
-            self.orbit_yaw = self.orbit_yaw - orbit_step;
+            self.orbit_yaw -= orbit_step;
         }
         if ui.input(|i| i.key_down(Key::ArrowRight)) {
-            self.orbit_yaw = self.orbit_yaw + orbit_step;
+            self.orbit_yaw += orbit_step;
         }
 
         if self.orbit_yaw >= 360.0 {
@@ -259,11 +295,29 @@ impl eframe::App for EngineApp {
             egui::Panel::left("").show(ui, |ui| {
                 ui.heading("Debug Controls");
                 ui.checkbox(&mut self.show_second_viewport, "show_second_viewport");
+                ui.checkbox(&mut self.picture_in_picture, "picture_in_picture");
                 ui.checkbox(&mut self.draw_axis, "draw_axis");
                 ui.checkbox(&mut self.draw_grid, "draw_grid");
                 ui.checkbox(&mut self.draw_lights, "draw_lights");
                 ui.checkbox(&mut self.renderer.draw_wireframe, "draw_wireframe");
+                egui::ComboBox::from_label("wireframe_mode")
+                    .selected_text(self.renderer.wireframe_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in WireframeMode::ALL {
+                            ui.selectable_value(
+                                &mut self.renderer.wireframe_mode,
+                                mode,
+                                mode.label(),
+                            );
+                        }
+                    });
+                ui.add(
+                    egui::Slider::new(&mut self.renderer.line_depth_bias, 0.0..=0.05)
+                        .logarithmic(true)
+                        .text("line_depth_bias"),
+                );
                 ui.checkbox(&mut self.renderer.draw_z_buffer, "draw_z_buffer");
+                ui.checkbox(&mut self.renderer.draw_mip_levels, "draw_mip_levels");
                 ui.checkbox(&mut self.renderer.draw_vertex, "draw_vertex");
                 ui.checkbox(
                     &mut self.renderer.draw_vertex_normals,
@@ -271,6 +325,184 @@ impl eframe::App for EngineApp {
                 );
                 ui.checkbox(&mut self.renderer.draw_faces, "draw_faces");
                 ui.checkbox(&mut self.renderer.backface_culling, "backface_culling");
+                ui.checkbox(
+                    &mut self.renderer.perspective_correct,
+                    "perspective_correct",
+                );
+                ui.checkbox(&mut self.renderer.frustum_culling, "frustum_culling");
+                ui.checkbox(&mut self.renderer.depth_prepass, "depth_prepass");
+                ui.checkbox(&mut self.renderer.immediate_mode, "immediate_mode");
+                ui.checkbox(&mut self.renderer.depth_test_lines, "depth_test_lines");
+                egui::ComboBox::from_label("occluded_lines")
+                    .selected_text(self.renderer.occluded_lines.label())
+                    .show_ui(ui, |ui| {
+                        for mode in OccludedLines::ALL {
+                            ui.selectable_value(
+                                &mut self.renderer.occluded_lines,
+                                mode,
+                                mode.label(),
+                            );
+                        }
+                    });
+                egui::ComboBox::from_label("transparency")
+                    .selected_text(self.renderer.transparency.label())
+                    .show_ui(ui, |ui| {
+                        for mode in TransparencyMode::ALL {
+                            ui.selectable_value(
+                                &mut self.renderer.transparency,
+                                mode,
+                                mode.label(),
+                            );
+                        }
+                    });
+                ui.add(
+                    egui::Slider::new(
+                        &mut self.renderer.thread_count,
+                        1..=Renderer::available_threads(),
+                    )
+                    .text("thread_count"),
+                );
+
+                egui::ComboBox::from_label("anti_aliasing")
+                    .selected_text(self.anti_aliasing.label())
+                    .show_ui(ui, |ui| {
+                        for mode in AntiAliasing::ALL {
+                            ui.selectable_value(&mut self.anti_aliasing, mode, mode.label());
+                        }
+                    });
+
+                ui.label("");
+                ui.separator();
+                ui.label("");
+
+                ui.heading("Materials");
+                for (_, name, material) in self.scene.materials.iter_mut() {
+                    egui::ComboBox::from_label(format!("material_{}_shading", name))
+                        .selected_text(material.shading.label())
+                        .show_ui(ui, |ui| {
+                            for mode in ShadingMode::ALL {
+                                ui.selectable_value(&mut material.shading, mode, mode.label());
+                            }
+                        });
+                    ui.add(
+                        egui::Slider::new(&mut material.opacity, 0.0..=1.0)
+                            .text(format!("material_{}_opacity", name)),
+                    );
+                    egui::ComboBox::from_label(format!("material_{}_blend_mode", name))
+                        .selected_text(material.blend_mode.label())
+                        .show_ui(ui, |ui| {
+                            for mode in BlendMode::ALL {
+                                ui.selectable_value(&mut material.blend_mode, mode, mode.label());
+                            }
+                        });
+                    color_ui(
+                        ui,
+                        &format!("material_{}_base_color", name),
+                        &mut material.base_color,
+                    );
+                    color_ui(
+                        ui,
+                        &format!("material_{}_specular_color", name),
+                        &mut material.specular_color,
+                    );
+                    color_ui(
+                        ui,
+                        &format!("material_{}_emissive", name),
+                        &mut material.emissive,
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut material.emissive_intensity, 0.0..=4.0)
+                            .text(format!("material_{}_emissive_intensity", name)),
+                    );
+                    ui.checkbox(
+                        &mut material.vertex_colors,
+                        format!("material_{}_vertex_colors", name),
+                    );
+
+                    let mut textured = material.texture.is_some();
+                    if ui
+                        .checkbox(&mut textured, format!("material_{}_checker", name))
+                        .changed()
+                    {
+                        material.texture = textured.then(|| {
+                            Arc::new(Texture::checkerboard(
+                                64,
+                                8,
+                                [1.0, 1.0, 1.0],
+                                [0.2, 0.2, 0.2],
+                            ))
+                        });
+                    }
+                    if let Some(texture) = &mut material.texture {
+                        let texture = Arc::make_mut(texture);
+                        egui::ComboBox::from_label(format!("material_{}_filter", name))
+                            .selected_text(texture.filter.label())
+                            .show_ui(ui, |ui| {
+                                for mode in FilterMode::ALL {
+                                    ui.selectable_value(&mut texture.filter, mode, mode.label());
+                                }
+                            });
+                        egui::ComboBox::from_label(format!("material_{}_wrap", name))
+                            .selected_text(texture.wrap.label())
+                            .show_ui(ui, |ui| {
+                                for mode in WrapMode::ALL {
+                                    ui.selectable_value(&mut texture.wrap, mode, mode.label());
+                                }
+                            });
+                        ui.add(
+                            egui::Slider::new(&mut texture.lod_bias, -4.0..=4.0)
+                                .text(format!("material_{}_lod_bias", name)),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut texture.max_anisotropy, 1..=16)
+                                .text(format!("material_{}_max_anisotropy", name)),
+                        );
+                    }
+                }
+
+                ui.label("");
+                ui.separator();
+                ui.label("");
+
+                ui.heading("Stencil");
+                for node in &mut self.scene.root_node.children {
+                    if node.mesh.is_some() {
+                        stencil_ui(ui, &node.name, &mut node.stencil);
+                    }
+                }
+
+                ui.label("");
+                ui.separator();
+                ui.label("");
+
+                ui.heading("Line Styles");
+                let styles = &mut self.renderer.overlay_styles;
+                line_style_ui(ui, "grid", &mut styles.grid);
+                line_style_ui(ui, "axis", &mut styles.axis);
+                line_style_ui(ui, "lights", &mut styles.lights);
+                line_style_ui(ui, "wireframe", &mut styles.wireframe);
+                line_style_ui(ui, "vertex_normals", &mut styles.vertex_normals);
+
+                ui.label("");
+                ui.separator();
+                ui.label("");
+
+                ui.heading("Render Stats");
+                let stats = self.views[0].stats;
+                ui.label(format!("Draw commands: {}", stats.draw_commands));
+                ui.label(format!(
+                    "Culled draw commands: {}",
+                    stats.culled_draw_commands
+                ));
+                ui.label(format!("Material changes: {}", stats.material_changes));
+                ui.label(format!("Stencil changes: {}", stats.stencil_changes));
+                ui.label(format!("Shaded fragments: {}", stats.shaded_fragments));
+                ui.label(format!("Covered pixels: {}", stats.covered_pixels));
+                ui.label(format!("Overdraw: {:.2}x", stats.overdraw()));
+                ui.label(format!(
+                    "Fragment buffer: {:.1} KiB",
+                    stats.fragment_buffer_bytes as f64 / 1024.0
+                ));
             });
         }
 
@@ -279,13 +511,18 @@ impl eframe::App for EngineApp {
                 .default_size(ui.available_height() / 2.0)
                 .resizable(true)
                 .show(ui, |ui| {
-                    self.show_view(ui, 1);
+                    self.show_panel(ui, 1, &[1]);
                 });
         }
 
         // Center Panel: Standard 3D perspective view
         egui::CentralPanel::no_frame().show(ui, |ui| {
-            self.show_view(ui, 0);
+            // secondary camera in the bottom right quarter of the main view
+            if self.picture_in_picture {
+                self.show_panel(ui, 0, &[0, 2]);
+            } else {
+                self.show_panel(ui, 0, &[0]);
+            }
         });
 
         self.update_camera("main_camera");
@@ -294,56 +531,76 @@ impl eframe::App for EngineApp {
 }
 
 impl EngineApp {
-    fn show_view(&mut self, ui: &mut egui::Ui, view_idx: usize) {
-        let view = &mut self.views[view_idx];
+    /// Renders the views into the panel's target, in order, and displays it
+    fn show_panel(&mut self, ui: &mut egui::Ui, panel_idx: usize, view_indices: &[usize]) {
+        let panel = &mut self.panels[panel_idx];
         let available_size = ui.available_size();
         let width = available_size.x as usize;
         let height = available_size.y as usize;
 
-        // Resize the viewport buffers if egui panel resizes
-        if view.viewport.get_width() != width || view.viewport.get_height() != height {
-            view.resize(width, height);
-            if let Some(camera) = self.scene.find_camera_mut(&view.camera_node_name) {
-                camera.set_projection_params(
-                    camera.fov_in_degrees,
-                    width as f64 / height as f64,
-                    camera.near,
-                    camera.far,
-                );
-            }
-        }
+        panel.target.set_anti_aliasing(self.anti_aliasing);
 
-        self.renderer
-            .draw_background_on_framebuffer(&mut view.target);
+        // Resize the target buffers if egui panel resizes
+        if panel.target.get_display_width() != width || panel.target.get_display_height() != height
+        {
+            panel.target.resize(width, height);
+        }
 
-        let camera = self
-            .scene
-            .get_camera_by_name(&view.camera_node_name)
-            .expect("no camera node with that name found");
+        // the inset follows the size of the main view
+        self.views[2].set_region(Some(Rect::new(
+            width - width / 4,
+            height - height / 4,
+            width / 4,
+            height / 4,
+        )));
+
+        for &view_idx in view_indices {
+            let view = &mut self.views[view_idx];
+            let (view_width, view_height) = view.get_size(&panel.target);
+            if view_width == 0 || view_height == 0 {
+                continue;
+            }
 
-        if self.draw_grid {
-            self.renderer.render_grid(&self.scene, view, &camera);
-        }
+            let mut camera = self
+                .scene
+                .get_camera_by_name(&view.camera_node_name)
+                .expect("no camera node with that name found");
+            camera.set_projection_params(
+                camera.fov_in_degrees,
+                view_width as f64 / view_height as f64,
+                camera.near,
+                camera.far,
+            );
 
-        // Render scene to this view's RenderTarget
-        self.renderer.render_view(&self.scene, view, &camera);
+            self.renderer
+                .draw_background_on_framebuffer(view, &mut panel.target);
 
-        // Debug renders
-        if self.draw_axis {
-            self.renderer.render_axis(&self.scene, view, &camera);
-        }
-        if self.draw_lights {
+            // Render scene to the view's region of the panel's RenderTarget
             self.renderer
-                .render_light_vectors(&self.scene, view, &camera);
+                .render_view(&self.scene, view, &mut panel.target, &camera);
+
+            // Debug renders, after the scene so they can be tested against its depth
+            if self.draw_grid {
+                self.renderer
+                    .render_grid(&self.scene, view, &mut panel.target, &camera);
+            }
+            if self.draw_axis {
+                self.renderer
+                    .render_axis(&self.scene, view, &mut panel.target, &camera);
+            }
+            if self.draw_lights {
+                self.renderer
+                    .render_light_vectors(&self.scene, view, &mut panel.target, &camera);
+            }
         }
 
-        // Upload framebuffer to egui texture
-        let raw_pixels = view.target.framebuffer.get_buffer();
+        // Resolve to display resolution and upload framebuffer to egui texture
+        let raw_pixels = panel.target.resolve().get_buffer();
         let image = egui::ColorImage::from_rgba_premultiplied([width, height], raw_pixels);
 
-        let texture = view.texture_handle.get_or_insert_with(|| {
+        let texture = panel.texture_handle.get_or_insert_with(|| {
             ui.ctx()
-                .load_texture(&view.name, image.clone(), egui::TextureOptions::LINEAR)
+                .load_texture(&panel.name, image.clone(), egui::TextureOptions::LINEAR)
         });
         texture.set(image, egui::TextureOptions::LINEAR);
 
@@ -351,3 +608,55 @@ impl EngineApp {
         ui.image((texture.id(), available_size));
     }
 }
+
+/// Width, anti-aliasing and cap controls for one overlay
+fn line_style_ui(ui: &mut egui::Ui, name: &str, style: &mut LineStyle) {
+    ui.collapsing(name, |ui| {
+        ui.add(egui::Slider::new(&mut style.width, 1.0..=8.0).text("width"));
+        ui.checkbox(&mut style.anti_aliased, "anti_aliased");
+        egui::ComboBox::from_id_salt(name)
+            .selected_text(style.cap.label())
+            .show_ui(ui, |ui| {
+                for cap in LineCap::ALL {
+                    ui.selectable_value(&mut style.cap, cap, cap.label());
+                }
+            });
+    });
+}
+
+/// RGB color button for a color in the 0..1 range
+fn color_ui(ui: &mut egui::Ui, name: &str, color: &mut [f64; 3]) {
+    ui.horizontal(|ui| {
+        let mut rgb = color.map(|channel| channel as f32);
+        if ui.color_edit_button_rgb(&mut rgb).changed() {
+            *color = rgb.map(f64::from);
+        }
+        ui.label(name);
+    });
+}
+
+fn stencil_ui(ui: &mut egui::Ui, name: &str, stencil: &mut StencilState) {
+    ui.collapsing(name, |ui| {
+        ui.add(egui::Slider::new(&mut stencil.reference, 0..=255).text("reference"));
+        egui::ComboBox::from_id_salt((name, "compare"))
+            .selected_text(stencil.compare.label())
+            .show_ui(ui, |ui| {
+                for compare in CompareFunction::ALL {
+                    ui.selectable_value(&mut stencil.compare, compare, compare.label());
+                }
+            });
+        for (label, op) in [
+            ("fail", &mut stencil.fail),
+            ("depth_fail", &mut stencil.depth_fail),
+            ("pass", &mut stencil.pass),
+        ] {
+            egui::ComboBox::from_id_salt((name, label))
+                .selected_text(format!("{label}: {}", op.label()))
+                .show_ui(ui, |ui| {
+                    for value in StencilOp::ALL {
+                        ui.selectable_value(op, value, value.label());
+                    }
+                });
+        }
+    });
+}
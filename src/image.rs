@@ -0,0 +1,145 @@
+#![allow(dead_code)]
+mod bmp; // Windows bitmaps
+mod inflate; // zlib / DEFLATE decompression for PNG
+mod png;
+mod pnm; // PPM and PGM, binary and ASCII
+mod tga; // Truevision TGA, raw and RLE
+
+use std::fmt;
+use std::path::Path;
+
+pub use bmp::BmpError;
+pub use png::PngError;
+pub use pnm::PnmError;
+pub use tga::TgaError;
+
+/// Decoded image, 8 bit RGBA in row major order with the top row first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>, // width * height * 4 bytes
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageError {
+    Io(String),       // the file could not be read
+    NotFound(String), // no embedded file with this name (wasm)
+    Png(PngError),
+    Tga(TgaError),
+    Bmp(BmpError),
+    Pnm(PnmError),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(message) => write!(f, "{message}"),
+            ImageError::NotFound(path) => write!(f, "no embedded image '{path}'"),
+            ImageError::Png(error) => write!(f, "PNG: {error}"),
+            ImageError::Tga(error) => write!(f, "TGA: {error}"),
+            ImageError::Bmp(error) => write!(f, "BMP: {error}"),
+            ImageError::Pnm(error) => write!(f, "PPM/PGM: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<PngError> for ImageError {
+    fn from(error: PngError) -> Self {
+        ImageError::Png(error)
+    }
+}
+
+impl From<TgaError> for ImageError {
+    fn from(error: TgaError) -> Self {
+        ImageError::Tga(error)
+    }
+}
+
+impl From<BmpError> for ImageError {
+    fn from(error: BmpError) -> Self {
+        ImageError::Bmp(error)
+    }
+}
+
+impl From<PnmError> for ImageError {
+    fn from(error: PnmError) -> Self {
+        ImageError::Pnm(error)
+    }
+}
+
+/// Image files compiled into the wasm build, which has no file system
+#[cfg(target_arch = "wasm32")]
+const EMBEDDED: &[(&str, &[u8])] = &[("uv_grid.tga", include_bytes!("../models/uv_grid.tga"))];
+
+/// Decodes an image file, the format is detected from its signature.
+/// TGA files have none, so anything unrecognized is decoded as TGA.
+pub fn decode(data: &[u8]) -> Result<Image, ImageError> {
+    Ok(match data {
+        [0x89, b'P', b'N', b'G', ..] => png::decode(data)?,
+        [b'B', b'M', ..] => bmp::decode(data)?,
+        [b'P', b'2' | b'3' | b'5' | b'6', ..] => pnm::decode(data)?,
+        _ => tga::decode(data)?,
+    })
+}
+
+/// Reads and decodes an image file.
+/// On the web the file is looked up by its file name among the embedded images.
+pub fn load(path: &str) -> Result<Image, ImageError> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let data = std::fs::read(path)
+        .map_err(|e| ImageError::Io(format!("Failed to read image '{}': {}", path, e)))?;
+
+    #[cfg(target_arch = "wasm32")]
+    let data = {
+        let file_name = Path::new(path).file_name().and_then(|name| name.to_str());
+        EMBEDDED
+            .iter()
+            .find(|(name, _)| Some(*name) == file_name)
+            .map(|(_, data)| *data)
+            .ok_or_else(|| ImageError::NotFound(path.to_string()))?
+    };
+
+    decode(&data)
+}
+
+/// Path of a file referenced by another file, e.g. a texture named in an MTL file
+pub fn resolve_path(referenced_by: &str, path: &str) -> String {
+    match Path::new(referenced_by).parent() {
+        Some(directory) => directory.join(path).to_string_lossy().into_owned(),
+        None => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_detects_the_format() {
+        let ppm = decode(b"P3 1 1 255 1 2 3").unwrap();
+        assert_eq!(ppm.pixels, [1, 2, 3, 255]);
+
+        assert!(matches!(decode(b"BMxx"), Err(ImageError::Bmp(_))));
+        assert!(matches!(
+            decode(&[0x89, b'P', b'N', b'G']),
+            Err(ImageError::Png(PngError::InvalidSignature))
+        ));
+        assert!(matches!(decode(&[]), Err(ImageError::Tga(_))));
+
+        let grid = load("models/uv_grid.tga").unwrap();
+        assert_eq!((grid.width, grid.height), (256, 256));
+        assert!(matches!(load("models/missing.png"), Err(ImageError::Io(_))));
+    }
+
+    #[test]
+    fn referenced_paths_are_relative_to_the_referencing_file() {
+        assert_eq!(
+            resolve_path("models/f-16.mtl", "f-16.tga"),
+            "models/f-16.tga"
+        );
+        assert_eq!(resolve_path("f-16.mtl", "f-16.tga"), "f-16.tga");
+    }
+}
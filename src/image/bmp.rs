@@ -0,0 +1,315 @@
+use std::fmt;
+
+use super::Image;
+
+/// Malformed or unsupported BMP files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BmpError {
+    InvalidSignature,
+    UnexpectedEnd,
+    UnsupportedHeader(u32), // DIB header size
+    UnsupportedBitCount(u16),
+    UnsupportedCompression(u32), // only uncompressed and bit fields
+    InvalidPaletteIndex(u8),
+    EmptyImage,
+    ImageTooLarge, // the pixel count overflows
+}
+
+impl fmt::Display for BmpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BmpError::InvalidSignature => write!(f, "not a BMP file"),
+            BmpError::UnexpectedEnd => write!(f, "BMP file ends unexpectedly"),
+            BmpError::UnsupportedHeader(size) => {
+                write!(f, "unsupported BMP header of {size} bytes")
+            }
+            BmpError::UnsupportedBitCount(bits) => {
+                write!(f, "unsupported BMP bit count {bits}")
+            }
+            BmpError::UnsupportedCompression(compression) => {
+                write!(f, "unsupported BMP compression {compression}")
+            }
+            BmpError::InvalidPaletteIndex(index) => {
+                write!(f, "palette index {index} out of range")
+            }
+            BmpError::EmptyImage => write!(f, "BMP image has no pixels"),
+            BmpError::ImageTooLarge => write!(f, "BMP image is too large"),
+        }
+    }
+}
+
+impl std::error::Error for BmpError {}
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+/// Channel stored in a bit mask of a 16 or 32 bit pixel
+#[derive(Clone, Copy)]
+struct BitField {
+    shift: u32,
+    max: u32, // mask >> shift, 0 if the channel is absent
+}
+
+impl BitField {
+    fn new(mask: u32) -> Self {
+        if mask == 0 {
+            return Self { shift: 0, max: 0 };
+        }
+        let shift = mask.trailing_zeros();
+        Self {
+            shift,
+            max: mask >> shift,
+        }
+    }
+
+    fn extract(&self, value: u32, absent: u8) -> u8 {
+        if self.max == 0 {
+            return absent;
+        }
+        (((value >> self.shift) & self.max) as u64 * 255 / self.max as u64) as u8
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, BmpError> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or(BmpError::UnexpectedEnd)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, BmpError> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or(BmpError::UnexpectedEnd)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Decodes an uncompressed BMP file into RGBA8.
+/// Supports OS/2 core and Windows info headers up to V5 with 1, 4, 8, 16, 24 and 32
+/// bits per pixel, bit field masks and top-down images; RLE compression is rejected.
+pub fn decode(data: &[u8]) -> Result<Image, BmpError> {
+    if !data.starts_with(b"BM") {
+        return Err(BmpError::InvalidSignature);
+    }
+    let pixel_offset = u32_at(data, 10)? as usize;
+    let header_size = u32_at(data, 14)?;
+
+    let (width, height, bit_count, compression, palette_entry) = match header_size {
+        12 => (
+            u16_at(data, 18)? as i64,
+            u16_at(data, 20)? as i16 as i64,
+            u16_at(data, 24)?,
+            BI_RGB,
+            3, // core headers store BGR palette entries
+        ),
+        40 | 52 | 56 | 108 | 124 => (
+            u32_at(data, 18)? as i32 as i64,
+            u32_at(data, 22)? as i32 as i64,
+            u16_at(data, 28)?,
+            u32_at(data, 30)?,
+            4,
+        ),
+        _ => return Err(BmpError::UnsupportedHeader(header_size)),
+    };
+
+    // a negative height marks rows stored top down
+    let top_down = height < 0;
+    let (width, height) = (
+        width.unsigned_abs() as usize,
+        height.unsigned_abs() as usize,
+    );
+    if width == 0 || height == 0 {
+        return Err(BmpError::EmptyImage);
+    }
+    if !matches!(bit_count, 1 | 4 | 8 | 16 | 24 | 32) {
+        return Err(BmpError::UnsupportedBitCount(bit_count));
+    }
+
+    // bit fields follow the info header, or are part of the V2+ headers
+    let masks = match compression {
+        BI_RGB => match bit_count {
+            16 => [0x7c00, 0x03e0, 0x001f, 0],
+            _ => [0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0],
+        },
+        BI_BITFIELDS | BI_ALPHABITFIELDS if matches!(bit_count, 16 | 32) => {
+            let alpha = compression == BI_ALPHABITFIELDS || header_size >= 56;
+            [
+                u32_at(data, 54)?,
+                u32_at(data, 58)?,
+                u32_at(data, 62)?,
+                if alpha { u32_at(data, 66)? } else { 0 },
+            ]
+        }
+        _ => return Err(BmpError::UnsupportedCompression(compression)),
+    };
+    let fields = masks.map(BitField::new);
+
+    let palette: Vec<[u8; 4]> = if bit_count <= 8 {
+        let color_count = match header_size {
+            12 => 0,
+            _ => u32_at(data, 46)? as usize,
+        };
+        let color_count = if color_count == 0 {
+            1 << bit_count
+        } else {
+            color_count
+        };
+        // some writers claim more colors than fit before the pixel data
+        let start = 14 + header_size as usize;
+        let color_count = color_count.min(pixel_offset.saturating_sub(start) / palette_entry);
+        data.get(start..start + color_count * palette_entry)
+            .ok_or(BmpError::UnexpectedEnd)?
+            .chunks_exact(palette_entry)
+            .map(|bgr| [bgr[2], bgr[1], bgr[0], 255])
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // rows are padded to 4 bytes
+    let row_bytes = width
+        .checked_mul(bit_count as usize)
+        .map(|row_bits| row_bits.div_ceil(32) * 4);
+    let pixel_bytes = row_bytes.and_then(|row_bytes| row_bytes.checked_mul(height));
+    let rgba_bytes = width
+        .checked_mul(height)
+        .and_then(|count| count.checked_mul(4));
+    let (Some(row_bytes), Some(pixel_bytes), Some(rgba_bytes)) =
+        (row_bytes, pixel_bytes, rgba_bytes)
+    else {
+        return Err(BmpError::ImageTooLarge);
+    };
+    // check the file holds every row before allocating the image
+    if data.len() < pixel_offset.saturating_add(pixel_bytes) {
+        return Err(BmpError::UnexpectedEnd);
+    }
+    let mut image = Image {
+        width,
+        height,
+        pixels: vec![0; rgba_bytes],
+    };
+
+    for row_index in 0..height {
+        let start = pixel_offset + row_index * row_bytes;
+        let row = data
+            .get(start..start + row_bytes)
+            .ok_or(BmpError::UnexpectedEnd)?;
+        let y = if top_down {
+            row_index
+        } else {
+            height - 1 - row_index
+        };
+
+        for x in 0..width {
+            let pixel = match bit_count {
+                1 | 4 | 8 => {
+                    let bits = bit_count as usize;
+                    let bit = x * bits;
+                    let shift = 8 - bits - bit % 8;
+                    let index = (row[bit / 8] >> shift) & ((1u16 << bits) - 1) as u8;
+                    *palette
+                        .get(index as usize)
+                        .ok_or(BmpError::InvalidPaletteIndex(index))?
+                }
+                24 => [row[x * 3 + 2], row[x * 3 + 1], row[x * 3], 255],
+                _ => {
+                    let value = if bit_count == 16 {
+                        u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32
+                    } else {
+                        u32::from_le_bytes([
+                            row[x * 4],
+                            row[x * 4 + 1],
+                            row[x * 4 + 2],
+                            row[x * 4 + 3],
+                        ])
+                    };
+                    [
+                        fields[0].extract(value, 0),
+                        fields[1].extract(value, 0),
+                        fields[2].extract(value, 0),
+                        fields[3].extract(value, 255),
+                    ]
+                }
+            };
+            let index = (y * width + x) * 4;
+            image.pixels[index..index + 4].copy_from_slice(&pixel);
+        }
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// BMP with a 40 byte info header, `extra` is placed between header and pixels
+    fn bmp(
+        width: i32,
+        height: i32,
+        bit_count: u16,
+        compression: u32,
+        extra: &[u8],
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let pixel_offset = 14 + 40 + extra.len() as u32;
+        let mut file = b"BM".to_vec();
+        file.extend_from_slice(&(pixel_offset + pixels.len() as u32).to_le_bytes());
+        file.extend_from_slice(&[0; 4]);
+        file.extend_from_slice(&pixel_offset.to_le_bytes());
+        file.extend_from_slice(&40u32.to_le_bytes());
+        file.extend_from_slice(&width.to_le_bytes());
+        file.extend_from_slice(&height.to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&bit_count.to_le_bytes());
+        file.extend_from_slice(&compression.to_le_bytes());
+        file.extend_from_slice(&[0; 20]);
+        file.extend_from_slice(extra);
+        file.extend_from_slice(pixels);
+        file
+    }
+
+    #[test]
+    fn decodes_padded_bottom_up_24_bit() {
+        // 1x2, rows padded from 3 to 4 bytes, the first row is the bottom one
+        let file = bmp(1, 2, 24, BI_RGB, &[], &[255, 0, 0, 0, 0, 0, 255, 0]);
+        let image = decode(&file).unwrap();
+        assert_eq!(image.pixels, [255, 0, 0, 255, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn decodes_palette_and_bit_field_images() {
+        // 4 bit, 3 pixels wide, top down, two palette entries
+        let palette = [0, 0, 0, 0, 0, 255, 0, 0];
+        let file = bmp(3, -1, 4, BI_RGB, &palette, &[0x01, 0x10, 0, 0]);
+        let image = decode(&file).unwrap();
+        assert_eq!(image.pixels, [0, 0, 0, 255, 0, 255, 0, 255, 0, 255, 0, 255]);
+
+        // 16 bit 5-6-5 bit fields
+        let masks = [0x00, 0xf8, 0, 0, 0xe0, 0x07, 0, 0, 0x1f, 0, 0, 0];
+        let file = bmp(1, 1, 16, BI_BITFIELDS, &masks, &[0xe0, 0x07, 0, 0]);
+        assert_eq!(decode(&file).unwrap().pixels, [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert_eq!(decode(b"PK").unwrap_err(), BmpError::InvalidSignature);
+        assert_eq!(
+            decode(&bmp(1, 1, 8, 1, &[], &[0; 4])).unwrap_err(),
+            BmpError::UnsupportedCompression(1)
+        );
+        assert_eq!(
+            decode(&bmp(1, 1, 2, BI_RGB, &[], &[0; 4])).unwrap_err(),
+            BmpError::UnsupportedBitCount(2)
+        );
+        assert_eq!(
+            decode(&bmp(2, 2, 24, BI_RGB, &[], &[0; 8])).unwrap_err(),
+            BmpError::UnexpectedEnd
+        );
+        assert_eq!(
+            decode(&bmp(i32::MIN, i32::MIN, 32, BI_RGB, &[], &[0; 4])).unwrap_err(),
+            BmpError::ImageTooLarge
+        );
+    }
+}
@@ -0,0 +1,421 @@
+use std::fmt;
+
+/// Malformed zlib / DEFLATE streams
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InflateError {
+    UnexpectedEnd,
+    InvalidHeader,        // bad compression method or header checksum
+    PresetDictionary,     // zlib streams with a preset dictionary are not supported
+    InvalidBlockType,     // reserved block type 3
+    StoredLengthMismatch, // LEN and NLEN of a stored block disagree
+    InvalidCodeLengths,   // over-subscribed or incomplete Huffman code
+    InvalidSymbol,        // bit pattern without a symbol or a reserved symbol
+    InvalidDistance,      // back reference before the start of the output
+    ChecksumMismatch,     // Adler-32 of the output does not match the trailer
+    OutputTooLarge,       // more output than the caller allows
+}
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            InflateError::UnexpectedEnd => "compressed data ends unexpectedly",
+            InflateError::InvalidHeader => "invalid zlib header",
+            InflateError::PresetDictionary => "zlib preset dictionaries are not supported",
+            InflateError::InvalidBlockType => "invalid DEFLATE block type",
+            InflateError::StoredLengthMismatch => "stored block length check failed",
+            InflateError::InvalidCodeLengths => "invalid Huffman code lengths",
+            InflateError::InvalidSymbol => "invalid Huffman symbol",
+            InflateError::InvalidDistance => "back reference distance too far back",
+            InflateError::ChecksumMismatch => "Adler-32 checksum mismatch",
+            InflateError::OutputTooLarge => "decompressed data exceeds the expected size",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for InflateError {}
+
+/// Base lengths and extra bits of the length symbols 257..=285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Base distances and extra bits of the distance symbols 0..=29
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order in which the code length code lengths of a dynamic block are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const MAX_BITS: usize = 15;
+
+/// Reads bits least significant first, as DEFLATE packs them
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize, // in bits
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn bit(&mut self) -> Result<u32, InflateError> {
+        let byte = self
+            .data
+            .get(self.position / 8)
+            .ok_or(InflateError::UnexpectedEnd)?;
+        let bit = (byte >> (self.position % 8)) & 1;
+        self.position += 1;
+        Ok(bit as u32)
+    }
+
+    fn bits(&mut self, count: u8) -> Result<u32, InflateError> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    /// Skips to the next byte boundary and returns its byte offset
+    fn align_to_byte(&mut self) -> usize {
+        self.position = self.position.div_ceil(8) * 8;
+        self.position / 8
+    }
+}
+
+/// Canonical Huffman code, decoded one bit at a time
+struct Huffman {
+    counts: [u16; MAX_BITS + 1], // number of codes per length
+    symbols: Vec<u16>,           // symbols ordered by code
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // reject over-subscribed codes, incomplete ones are allowed (e.g. a single distance)
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err(InflateError::InvalidCodeLengths);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, InflateError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..=MAX_BITS {
+            code |= reader.bit()? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::InvalidSymbol)
+    }
+}
+
+/// Decompresses a zlib stream (RFC 1950) of at most `max_output` bytes and verifies its checksum
+pub fn zlib_decompress(data: &[u8], max_output: usize) -> Result<Vec<u8>, InflateError> {
+    let [cmf, flg, ..] = *data else {
+        return Err(InflateError::UnexpectedEnd);
+    };
+    if cmf & 0x0f != 8 || cmf >> 4 > 7 || !(((cmf as u16) << 8) | flg as u16).is_multiple_of(31) {
+        return Err(InflateError::InvalidHeader);
+    }
+    if flg & 0x20 != 0 {
+        return Err(InflateError::PresetDictionary);
+    }
+
+    let (output, consumed) = inflate(&data[2..], max_output)?;
+    let trailer = data
+        .get(2 + consumed..2 + consumed + 4)
+        .ok_or(InflateError::UnexpectedEnd)?;
+    if u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) != adler32(&output) {
+        return Err(InflateError::ChecksumMismatch);
+    }
+    Ok(output)
+}
+
+/// Decompresses raw DEFLATE data (RFC 1951).
+/// Stops with `OutputTooLarge` once the output would grow past `max_output` bytes.
+///
+/// ### Returns
+///
+/// * The decompressed bytes and the number of input bytes used
+pub fn inflate(data: &[u8], max_output: usize) -> Result<(Vec<u8>, usize), InflateError> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+
+    loop {
+        let last = reader.bit()? == 1;
+        match reader.bits(2)? {
+            0 => stored_block(&mut reader, &mut output, max_output)?,
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                compressed_block(&mut reader, &mut output, max_output, &literals, &distances)?
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                compressed_block(&mut reader, &mut output, max_output, &literals, &distances)?
+            }
+            _ => return Err(InflateError::InvalidBlockType),
+        }
+        if last {
+            break;
+        }
+    }
+
+    Ok((output, reader.align_to_byte()))
+}
+
+fn stored_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_output: usize,
+) -> Result<(), InflateError> {
+    let start = reader.align_to_byte();
+    let header = reader
+        .data
+        .get(start..start + 4)
+        .ok_or(InflateError::UnexpectedEnd)?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    let inverted = u16::from_le_bytes([header[2], header[3]]);
+    if length != !inverted {
+        return Err(InflateError::StoredLengthMismatch);
+    }
+
+    let bytes = reader
+        .data
+        .get(start + 4..start + 4 + length as usize)
+        .ok_or(InflateError::UnexpectedEnd)?;
+    if output.len() + bytes.len() > max_output {
+        return Err(InflateError::OutputTooLarge);
+    }
+    output.extend_from_slice(bytes);
+    reader.position = (start + 4 + length as usize) * 8;
+    Ok(())
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), InflateError> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), InflateError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(InflateError::InvalidCodeLengths);
+    }
+
+    let mut code_length_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths)?;
+
+    // literal/length and distance code lengths form one sequence, repeats may cross over
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or(InflateError::InvalidCodeLengths)?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            18 => (0, 11 + reader.bits(7)?),
+            _ => return Err(InflateError::InvalidSymbol),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count || lengths[256] == 0 {
+        return Err(InflateError::InvalidCodeLengths);
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn compressed_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_output: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), InflateError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 if output.len() == max_output => return Err(InflateError::OutputTooLarge),
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index])? as usize;
+
+                let index = distances.decode(reader)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err(InflateError::InvalidSymbol);
+                }
+                let distance =
+                    DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index])? as usize;
+                if distance > output.len() {
+                    return Err(InflateError::InvalidDistance);
+                }
+                if output.len() + length > max_output {
+                    return Err(InflateError::OutputTooLarge);
+                }
+
+                // byte by byte, the copy may overlap the bytes it produces
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+            _ => return Err(InflateError::InvalidSymbol),
+        }
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompresses_stored_fixed_and_dynamic_blocks() {
+        // zlib.compress(b"hello", level=0)
+        let stored = [
+            0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x06, 0x2c,
+            0x02, 0x15,
+        ];
+        assert_eq!(zlib_decompress(&stored, usize::MAX).unwrap(), b"hello");
+
+        // zlib.compress(b"abcabcabcabcabcabcabcabc"), fixed codes with a back reference
+        let fixed = [
+            0x78, 0x9c, 0x4b, 0x4c, 0x4a, 0x4e, 0xc4, 0x86, 0x00, 0x72, 0xe0, 0x09, 0x31,
+        ];
+        assert_eq!(
+            zlib_decompress(&fixed, usize::MAX).unwrap(),
+            b"abcabcabcabcabcabcabcabc"
+        );
+
+        // skewed letter frequencies make zlib pick dynamic codes
+        let text = b"adabbbabaaceccaaaeadaaeaaaabbbcbbaabaaabcaaeadabbabbcabadebbeeea";
+        let dynamic = [
+            0x78, 0xda, 0x25, 0x8a, 0xc1, 0x0d, 0x00, 0x40, 0x0c, 0x82, 0x66, 0x05, 0xeb, 0xfe,
+            0x2b, 0x5c, 0x9b, 0xf3, 0x61, 0x0c, 0xc8, 0xa0, 0x22, 0xa4, 0x09, 0x50, 0xe6, 0x6a,
+            0xb3, 0x3c, 0xab, 0xce, 0x61, 0xbe, 0xb9, 0xeb, 0x6e, 0x99, 0x6a, 0x5b, 0x1e, 0x1c,
+            0xd5, 0x18, 0x8b,
+        ];
+        assert_eq!(zlib_decompress(&dynamic, usize::MAX).unwrap(), text);
+    }
+
+    #[test]
+    fn rejects_corrupt_streams() {
+        assert_eq!(
+            zlib_decompress(&[0x78], usize::MAX),
+            Err(InflateError::UnexpectedEnd)
+        );
+        assert_eq!(
+            zlib_decompress(&[0x78, 0x00, 0x03, 0x00], usize::MAX),
+            Err(InflateError::InvalidHeader)
+        );
+        // "hello" with a damaged checksum
+        let stored = [
+            0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x06, 0x2c,
+            0x02, 0x16,
+        ];
+        assert_eq!(
+            zlib_decompress(&stored, usize::MAX),
+            Err(InflateError::ChecksumMismatch)
+        );
+        // stored block with a broken NLEN
+        assert_eq!(
+            inflate(&[0x01, 0x05, 0x00, 0xfa, 0xfe], usize::MAX),
+            Err(InflateError::StoredLengthMismatch)
+        );
+    }
+
+    #[test]
+    fn stops_at_the_output_limit() {
+        // zlib.compress(b"hello", level=0)
+        let stored = [
+            0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x06, 0x2c,
+            0x02, 0x15,
+        ];
+        assert_eq!(zlib_decompress(&stored, 5).unwrap(), b"hello");
+        assert_eq!(
+            zlib_decompress(&stored, 4),
+            Err(InflateError::OutputTooLarge)
+        );
+
+        // zlib.compress(b"abcabcabcabcabcabcabcabc"), the back reference passes the limit
+        let fixed = [
+            0x78, 0x9c, 0x4b, 0x4c, 0x4a, 0x4e, 0xc4, 0x86, 0x00, 0x72, 0xe0, 0x09, 0x31,
+        ];
+        assert_eq!(
+            zlib_decompress(&fixed, 10),
+            Err(InflateError::OutputTooLarge)
+        );
+    }
+}
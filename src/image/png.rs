@@ -0,0 +1,518 @@
+use std::fmt;
+
+use super::Image;
+use super::inflate::{InflateError, zlib_decompress};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Malformed or unsupported PNG files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PngError {
+    InvalidSignature,
+    UnexpectedEnd,
+    ChecksumMismatch([u8; 4]), // CRC of the named chunk is wrong
+    MissingChunk(&'static str),
+    InvalidHeader,  // bad or too large size, bit depth / color type pair or method
+    MissingPalette, // indexed image without PLTE
+    InvalidPaletteIndex(u8), // index past the end of the palette
+    InvalidFilter(u8), // unknown scanline filter type
+    DataSizeMismatch, // decompressed data does not fit the image size
+    Inflate(InflateError),
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PngError::InvalidSignature => write!(f, "not a PNG file"),
+            PngError::UnexpectedEnd => write!(f, "PNG file ends unexpectedly"),
+            PngError::ChecksumMismatch(name) => {
+                write!(f, "CRC mismatch in {} chunk", String::from_utf8_lossy(name))
+            }
+            PngError::MissingChunk(name) => write!(f, "missing {name} chunk"),
+            PngError::InvalidHeader => write!(f, "invalid or unsupported IHDR"),
+            PngError::MissingPalette => write!(f, "indexed PNG without a palette"),
+            PngError::InvalidPaletteIndex(index) => {
+                write!(f, "palette index {index} out of range")
+            }
+            PngError::InvalidFilter(filter) => write!(f, "invalid scanline filter {filter}"),
+            PngError::DataSizeMismatch => write!(f, "image data does not match the image size"),
+            PngError::Inflate(error) => write!(f, "PNG image data: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for PngError {}
+
+impl From<InflateError> for PngError {
+    fn from(error: InflateError) -> Self {
+        PngError::Inflate(error)
+    }
+}
+
+/// Starting column/row and step of the seven Adam7 passes
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, PngError> {
+        if data.len() != 13 {
+            return Err(PngError::InvalidHeader);
+        }
+        let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let (bit_depth, color_type) = (data[8], data[9]);
+
+        let valid_depth = match color_type {
+            0 => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            3 => matches!(bit_depth, 1 | 2 | 4 | 8),
+            2 | 4 | 6 => matches!(bit_depth, 8 | 16),
+            _ => false,
+        };
+        // compression and filter method 0 are the only ones defined
+        if width == 0 || height == 0 || !valid_depth || data[10] != 0 || data[11] != 0 {
+            return Err(PngError::InvalidHeader);
+        }
+        // dimensions are limited to 2^31 - 1, the RGBA8 image must fit in memory
+        let rgba_bytes = width
+            .checked_mul(height)
+            .and_then(|count| count.checked_mul(4));
+        if width > i32::MAX as usize || height > i32::MAX as usize || rgba_bytes.is_none() {
+            return Err(PngError::InvalidHeader);
+        }
+
+        let header = Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced: match data[12] {
+                0 => false,
+                1 => true,
+                _ => return Err(PngError::InvalidHeader),
+            },
+        };
+        // `row_bytes` of every pass is at most the one of the full width
+        if width.checked_mul(header.bits_per_pixel()).is_none() {
+            return Err(PngError::InvalidHeader);
+        }
+        Ok(header)
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1, // gray or palette index
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// Filter distance to the corresponding byte of the previous pixel, at least 1
+    fn filter_stride(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+}
+
+/// Decodes a PNG file into RGBA8.
+/// Supports every standard color type and bit depth, tRNS transparency and Adam7
+/// interlacing; 16 bit samples are reduced to their high byte, gamma is ignored.
+pub fn decode(data: &[u8]) -> Result<Image, PngError> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(PngError::InvalidSignature);
+    }
+
+    let mut header = None;
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    let mut transparency = None;
+    let mut compressed = Vec::new();
+
+    let mut position = SIGNATURE.len();
+    loop {
+        let length_bytes = data
+            .get(position..position + 8)
+            .ok_or(PngError::UnexpectedEnd)?;
+        let length = u32::from_be_bytes([
+            length_bytes[0],
+            length_bytes[1],
+            length_bytes[2],
+            length_bytes[3],
+        ]) as usize;
+        let name = [
+            length_bytes[4],
+            length_bytes[5],
+            length_bytes[6],
+            length_bytes[7],
+        ];
+        let chunk_end = length
+            .checked_add(position + 12)
+            .ok_or(PngError::UnexpectedEnd)?;
+        let chunk = data
+            .get(position + 4..chunk_end)
+            .ok_or(PngError::UnexpectedEnd)?;
+        let (typed_data, crc) = chunk.split_at(4 + length);
+        if u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]) != crc32(typed_data) {
+            return Err(PngError::ChecksumMismatch(name));
+        }
+        let chunk_data = &typed_data[4..];
+        position = chunk_end;
+
+        match &name {
+            b"IHDR" => header = Some(Header::parse(chunk_data)?),
+            b"PLTE" => {
+                palette = chunk_data
+                    .chunks_exact(3)
+                    .map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                    .collect()
+            }
+            b"tRNS" => transparency = Some(chunk_data.to_vec()),
+            b"IDAT" => compressed.extend_from_slice(chunk_data),
+            b"IEND" => break,
+            _ => {} // ancillary chunks are skipped
+        }
+    }
+
+    let header = header.ok_or(PngError::MissingChunk("IHDR"))?;
+    if compressed.is_empty() {
+        return Err(PngError::MissingChunk("IDAT"));
+    }
+    if header.color_type == 3 {
+        if palette.is_empty() {
+            return Err(PngError::MissingPalette);
+        }
+        // tRNS holds one alpha value per palette entry, missing ones are opaque
+        for (entry, &alpha) in palette.iter_mut().zip(transparency.iter().flatten()) {
+            entry[3] = alpha;
+        }
+    }
+
+    let passes: Vec<_> = if header.interlaced {
+        ADAM7.to_vec()
+    } else {
+        vec![(0, 0, 1, 1)]
+    };
+    let passes: Vec<_> = passes
+        .into_iter()
+        .map(|(start_x, start_y, step_x, step_y)| {
+            let pass_width = (header.width + step_x - 1 - start_x) / step_x;
+            let pass_height = (header.height + step_y - 1 - start_y) / step_y;
+            (start_x, start_y, step_x, step_y, pass_width, pass_height)
+        })
+        // empty passes have no filter bytes either
+        .filter(|&(.., pass_width, pass_height)| pass_width > 0 && pass_height > 0)
+        .collect();
+
+    // inflate no more than the passes need, and reject truncated image data before
+    // allocating the image
+    let data_size = passes
+        .iter()
+        .try_fold(0usize, |size, &(.., pass_width, pass_height)| {
+            (header.row_bytes(pass_width) + 1)
+                .checked_mul(pass_height)
+                .and_then(|pass_size| pass_size.checked_add(size))
+        })
+        .ok_or(PngError::InvalidHeader)?;
+    let raw = zlib_decompress(&compressed, data_size)?;
+    if raw.len() < data_size {
+        return Err(PngError::DataSizeMismatch);
+    }
+    let mut image = Image {
+        width: header.width,
+        height: header.height,
+        pixels: vec![0; header.width * header.height * 4],
+    };
+
+    let mut offset = 0;
+    for (start_x, start_y, step_x, step_y, pass_width, pass_height) in passes {
+        let row_bytes = header.row_bytes(pass_width);
+        let size = (row_bytes + 1) * pass_height;
+        let filtered = raw
+            .get(offset..offset + size)
+            .ok_or(PngError::DataSizeMismatch)?;
+        offset += size;
+        let rows = unfilter(filtered, row_bytes, header.filter_stride())?;
+
+        for (row_index, row) in rows.chunks_exact(row_bytes).enumerate() {
+            let y = start_y + row_index * step_y;
+            for column in 0..pass_width {
+                let x = start_x + column * step_x;
+                let pixel = decode_pixel(&header, row, column, &palette, transparency.as_deref())?;
+                let index = (y * header.width + x) * 4;
+                image.pixels[index..index + 4].copy_from_slice(&pixel);
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+/// Reverses the per-scanline filters, each row is prefixed by its filter type
+fn unfilter(filtered: &[u8], row_bytes: usize, stride: usize) -> Result<Vec<u8>, PngError> {
+    let mut rows = vec![0u8; filtered.len() / (row_bytes + 1) * row_bytes];
+    let mut previous = vec![0u8; row_bytes];
+
+    for (row_index, line) in filtered.chunks_exact(row_bytes + 1).enumerate() {
+        let (filter, line) = (line[0], &line[1..]);
+        let row = &mut rows[row_index * row_bytes..(row_index + 1) * row_bytes];
+
+        for i in 0..row_bytes {
+            let left = if i >= stride { row[i - stride] } else { 0 };
+            let up = previous[i];
+            let up_left = if i >= stride { previous[i - stride] } else { 0 };
+
+            let predictor = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(PngError::InvalidFilter(filter)),
+            };
+            row[i] = line[i].wrapping_add(predictor);
+        }
+        previous.copy_from_slice(row);
+    }
+
+    Ok(rows)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// RGBA8 of one pixel of an unfiltered row
+fn decode_pixel(
+    header: &Header,
+    row: &[u8],
+    column: usize,
+    palette: &[[u8; 4]],
+    transparency: Option<&[u8]>,
+) -> Result<[u8; 4], PngError> {
+    let depth = header.bit_depth as usize;
+
+    // raw sample values at the image's bit depth
+    let sample = |channel: usize| -> u16 {
+        let index = column * header.channels() + channel;
+        match depth {
+            16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+            8 => row[index] as u16,
+            _ => {
+                let bit = index * depth;
+                let shift = 8 - depth - bit % 8;
+                ((row[bit / 8] >> shift) & ((1 << depth) - 1) as u8) as u16
+            }
+        }
+    };
+    // scaled to 8 bits, 16 bit samples keep their high byte
+    let to_u8 = |value: u16| -> u8 {
+        match depth {
+            16 => (value >> 8) as u8,
+            _ => (value as u32 * 255 / ((1 << depth) - 1)) as u8,
+        }
+    };
+    // tRNS of gray and RGB images marks a single color as fully transparent
+    let transparent_key = |values: &[u16]| {
+        transparency.is_some_and(|key| {
+            key.len() >= values.len() * 2
+                && values
+                    .iter()
+                    .enumerate()
+                    .all(|(i, &v)| u16::from_be_bytes([key[i * 2], key[i * 2 + 1]]) == v)
+        })
+    };
+
+    Ok(match header.color_type {
+        0 => {
+            let gray = sample(0);
+            let alpha = if transparent_key(&[gray]) { 0 } else { 255 };
+            [to_u8(gray), to_u8(gray), to_u8(gray), alpha]
+        }
+        2 => {
+            let rgb = [sample(0), sample(1), sample(2)];
+            let alpha = if transparent_key(&rgb) { 0 } else { 255 };
+            [to_u8(rgb[0]), to_u8(rgb[1]), to_u8(rgb[2]), alpha]
+        }
+        3 => {
+            let index = sample(0) as u8;
+            *palette
+                .get(index as usize)
+                .ok_or(PngError::InvalidPaletteIndex(index))?
+        }
+        4 => {
+            let gray = to_u8(sample(0));
+            [gray, gray, gray, to_u8(sample(1))]
+        }
+        _ => [0, 1, 2, 3].map(|channel| to_u8(sample(channel))),
+    })
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(name: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut typed = name.to_vec();
+        typed.extend_from_slice(data);
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(&typed);
+        chunk.extend_from_slice(&crc32(&typed).to_be_bytes());
+        chunk
+    }
+
+    /// PNG with the image data in a stored (uncompressed) zlib stream
+    fn png(
+        width: u32,
+        height: u32,
+        depth: u8,
+        color: u8,
+        interlace: u8,
+        extra: &[Vec<u8>],
+        raw: &[u8],
+    ) -> Vec<u8> {
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[depth, color, 0, 0, interlace]);
+
+        let mut zlib = vec![0x78, 0x01, 0x01];
+        zlib.extend_from_slice(&(raw.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(raw.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(raw);
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in raw {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        zlib.extend_from_slice(&((b << 16) | a).to_be_bytes());
+
+        let mut file = SIGNATURE.to_vec();
+        file.extend(chunk(b"IHDR", &header));
+        for extra in extra {
+            file.extend_from_slice(extra);
+        }
+        file.extend(chunk(b"IDAT", &zlib));
+        file.extend(chunk(b"IEND", &[]));
+        file
+    }
+
+    #[test]
+    fn decodes_filtered_rgba_and_indexed_images() {
+        // 2x2 RGBA: row 0 unfiltered, row 1 with the up filter
+        let raw = [
+            0, 255, 0, 0, 255, 0, 255, 0, 128, //
+            2, 0, 0, 255, 0, 0, 0, 0, 127,
+        ];
+        let image = decode(&png(2, 2, 8, 6, 0, &[], &raw)).unwrap();
+        assert_eq!(
+            image.pixels,
+            [
+                255, 0, 0, 255, 0, 255, 0, 128, 255, 0, 255, 255, 0, 255, 0, 255
+            ]
+        );
+
+        // 3x1 indexed with 2 bit indices and a transparent first palette entry
+        let palette = chunk(b"PLTE", &[0, 0, 0, 10, 20, 30, 200, 100, 50]);
+        let alpha = chunk(b"tRNS", &[0]);
+        let raw = [0, 0b00_01_10_00];
+        let image = decode(&png(3, 1, 2, 3, 0, &[palette, alpha], &raw)).unwrap();
+        assert_eq!(
+            image.pixels,
+            [0, 0, 0, 0, 10, 20, 30, 255, 200, 100, 50, 255]
+        );
+    }
+
+    #[test]
+    fn decodes_adam7_interlaced_gray() {
+        // 2x2 gray, pass 1 holds (0,0), pass 6 (1,0) and pass 7 the second row
+        let raw = [0, 10, 0, 20, 0, 30, 40];
+        let image = decode(&png(2, 2, 8, 0, 1, &[], &raw)).unwrap();
+        let gray: Vec<u8> = image.pixels.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(gray, [10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert_eq!(decode(b"GIF89a").unwrap_err(), PngError::InvalidSignature);
+
+        let mut file = png(1, 1, 8, 0, 0, &[], &[0, 0]);
+        let crc_index = SIGNATURE.len() + 8 + 13;
+        file[crc_index] ^= 1;
+        assert_eq!(
+            decode(&file).unwrap_err(),
+            PngError::ChecksumMismatch(*b"IHDR")
+        );
+
+        let file = png(1, 1, 8, 0, 0, &[], &[7, 0]);
+        assert_eq!(decode(&file).unwrap_err(), PngError::InvalidFilter(7));
+
+        let file = png(1, 1, 8, 3, 0, &[], &[0, 0]);
+        assert_eq!(decode(&file).unwrap_err(), PngError::MissingPalette);
+
+        let file = png(u32::MAX, u32::MAX, 8, 6, 0, &[], &[0, 0]);
+        assert_eq!(decode(&file).unwrap_err(), PngError::InvalidHeader);
+        let file = png(1 << 20, 1 << 20, 8, 6, 0, &[], &[0, 0]);
+        assert_eq!(decode(&file).unwrap_err(), PngError::DataSizeMismatch);
+
+        // more image data than a 1x1 gray image holds
+        let file = png(1, 1, 8, 0, 0, &[], &[0, 0, 0]);
+        assert_eq!(
+            decode(&file).unwrap_err(),
+            PngError::Inflate(InflateError::OutputTooLarge)
+        );
+
+        // a chunk length that wraps the position around
+        let mut file = png(1, 1, 8, 0, 0, &[], &[0, 0]);
+        file.truncate(SIGNATURE.len());
+        file.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        file.extend_from_slice(b"IHDR");
+        assert_eq!(decode(&file).unwrap_err(), PngError::UnexpectedEnd);
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+
+use super::Image;
+
+/// Malformed or unsupported PPM / PGM files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PnmError {
+    InvalidMagic, // not P2, P3, P5 or P6
+    UnexpectedEnd,
+    InvalidNumber(String), // header field or ASCII sample that is not a number
+    InvalidMaxValue(u32),  // must be 1..=65535
+    SampleOutOfRange(u32), // ASCII sample above the max value
+    EmptyImage,
+    ImageTooLarge, // the sample count overflows
+}
+
+impl fmt::Display for PnmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PnmError::InvalidMagic => write!(f, "not a PPM or PGM file"),
+            PnmError::UnexpectedEnd => write!(f, "PPM/PGM file ends unexpectedly"),
+            PnmError::InvalidNumber(token) => write!(f, "invalid number '{token}'"),
+            PnmError::InvalidMaxValue(value) => write!(f, "invalid max value {value}"),
+            PnmError::SampleOutOfRange(value) => {
+                write!(f, "sample {value} is above the max value")
+            }
+            PnmError::EmptyImage => write!(f, "PPM/PGM image has no pixels"),
+            PnmError::ImageTooLarge => write!(f, "PPM/PGM image is too large"),
+        }
+    }
+}
+
+impl std::error::Error for PnmError {}
+
+/// Reads whitespace separated tokens, skipping `#` comments
+struct Tokens<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Tokens<'_> {
+    fn next_number(&mut self) -> Result<u32, PnmError> {
+        loop {
+            match self.data.get(self.position) {
+                Some(b'#') => {
+                    while self.data.get(self.position).is_some_and(|&c| c != b'\n') {
+                        self.position += 1;
+                    }
+                }
+                Some(c) if c.is_ascii_whitespace() => self.position += 1,
+                Some(_) => break,
+                None => return Err(PnmError::UnexpectedEnd),
+            }
+        }
+
+        let start = self.position;
+        while self
+            .data
+            .get(self.position)
+            .is_some_and(|c| !c.is_ascii_whitespace() && *c != b'#')
+        {
+            self.position += 1;
+        }
+        let token = String::from_utf8_lossy(&self.data[start..self.position]);
+        token
+            .parse()
+            .map_err(|_| PnmError::InvalidNumber(token.into_owned()))
+    }
+}
+
+/// Decodes binary (P5, P6) and ASCII (P2, P3) PGM / PPM files into RGBA8.
+/// Samples are scaled from the file's max value, 16 bit binary samples are big endian.
+pub fn decode(data: &[u8]) -> Result<Image, PnmError> {
+    let (gray, binary) = match data.get(..2) {
+        Some(b"P2") => (true, false),
+        Some(b"P3") => (false, false),
+        Some(b"P5") => (true, true),
+        Some(b"P6") => (false, true),
+        _ => return Err(PnmError::InvalidMagic),
+    };
+
+    let mut tokens = Tokens { data, position: 2 };
+    let width = tokens.next_number()? as usize;
+    let height = tokens.next_number()? as usize;
+    let max_value = tokens.next_number()?;
+    if width == 0 || height == 0 {
+        return Err(PnmError::EmptyImage);
+    }
+    if !(1..=65535).contains(&max_value) {
+        return Err(PnmError::InvalidMaxValue(max_value));
+    }
+
+    let channels = if gray { 1 } else { 3 };
+    let sample_count = width
+        .checked_mul(height)
+        .and_then(|count| count.checked_mul(channels))
+        .ok_or(PnmError::ImageTooLarge)?;
+    let samples: Vec<u32> = if binary {
+        // a single whitespace byte separates the header from the samples
+        let start = tokens.position + 1;
+        let sample_bytes = if max_value > 255 { 2 } else { 1 };
+        let end = sample_count
+            .checked_mul(sample_bytes)
+            .and_then(|length| length.checked_add(start))
+            .ok_or(PnmError::ImageTooLarge)?;
+        let bytes = data.get(start..end).ok_or(PnmError::UnexpectedEnd)?;
+        bytes
+            .chunks_exact(sample_bytes)
+            .map(|sample| match sample {
+                [high, low] => u16::from_be_bytes([*high, *low]) as u32,
+                _ => sample[0] as u32,
+            })
+            .map(|sample| sample.min(max_value))
+            .collect()
+    } else {
+        (0..sample_count)
+            .map(|_| {
+                let sample = tokens.next_number()?;
+                if sample > max_value {
+                    return Err(PnmError::SampleOutOfRange(sample));
+                }
+                Ok(sample)
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    let to_u8 = |sample: u32| (sample * 255 / max_value) as u8;
+    let pixels = samples
+        .chunks_exact(channels)
+        .flat_map(|pixel| match pixel {
+            [gray] => [to_u8(*gray), to_u8(*gray), to_u8(*gray), 255],
+            _ => [to_u8(pixel[0]), to_u8(pixel[1]), to_u8(pixel[2]), 255],
+        })
+        .collect();
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_ascii_and_binary_files() {
+        let ascii = b"P3\n# comment\n2 1\n15\n15 0 0  0 15 0\n";
+        assert_eq!(
+            decode(ascii).unwrap().pixels,
+            [255, 0, 0, 255, 0, 255, 0, 255]
+        );
+
+        let gray = b"P2 1 2 4 0 4";
+        assert_eq!(
+            decode(gray).unwrap().pixels,
+            [0, 0, 0, 255, 255, 255, 255, 255]
+        );
+
+        let mut binary = b"P6\n1 1\n255\n".to_vec();
+        binary.extend_from_slice(&[10, 20, 30]);
+        assert_eq!(decode(&binary).unwrap().pixels, [10, 20, 30, 255]);
+
+        // 16 bit samples, big endian
+        let mut wide = b"P5 2 1 65535\n".to_vec();
+        wide.extend_from_slice(&[0xff, 0xff, 0x80, 0x00]);
+        assert_eq!(
+            decode(&wide).unwrap().pixels,
+            [255, 255, 255, 255, 127, 127, 127, 255]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert_eq!(decode(b"P4 1 1\n").unwrap_err(), PnmError::InvalidMagic);
+        assert_eq!(
+            decode(b"P3 1 x 255").unwrap_err(),
+            PnmError::InvalidNumber("x".to_string())
+        );
+        assert_eq!(
+            decode(b"P2 1 1 0 0").unwrap_err(),
+            PnmError::InvalidMaxValue(0)
+        );
+        assert_eq!(
+            decode(b"P2 1 1 7 9").unwrap_err(),
+            PnmError::SampleOutOfRange(9)
+        );
+        assert_eq!(
+            decode(b"P6 2 2 255\n\x00\x00").unwrap_err(),
+            PnmError::UnexpectedEnd
+        );
+        assert_eq!(
+            decode(b"P6 4294967295 4294967295 255\n\x00").unwrap_err(),
+            PnmError::ImageTooLarge
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use std::fmt;
+
+use super::Image;
+
+/// Malformed or unsupported TGA files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TgaError {
+    UnexpectedEnd,
+    UnsupportedImageType(u8), // only color mapped, true color and gray, raw or RLE
+    UnsupportedPixelDepth(u8), // for the image type
+    InvalidColorMap,          // missing or with an unsupported entry size
+    InvalidColorMapIndex(u16), // index outside of the color map
+    EmptyImage,
+    ImageTooLarge, // the pixel count overflows
+}
+
+impl fmt::Display for TgaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TgaError::UnexpectedEnd => write!(f, "TGA file ends unexpectedly"),
+            TgaError::UnsupportedImageType(image_type) => {
+                write!(f, "unsupported TGA image type {image_type}")
+            }
+            TgaError::UnsupportedPixelDepth(depth) => {
+                write!(f, "unsupported TGA pixel depth {depth}")
+            }
+            TgaError::InvalidColorMap => write!(f, "invalid TGA color map"),
+            TgaError::InvalidColorMapIndex(index) => {
+                write!(f, "color map index {index} out of range")
+            }
+            TgaError::EmptyImage => write!(f, "TGA image has no pixels"),
+            TgaError::ImageTooLarge => write!(f, "TGA image is too large"),
+        }
+    }
+}
+
+impl std::error::Error for TgaError {}
+
+/// RGBA8 of a little endian BGR(A) or 5-5-5(-1) pixel
+fn true_color(bytes: &[u8], alpha_bits: u8) -> [u8; 4] {
+    match bytes.len() {
+        2 => {
+            let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+            let channel = |shift: u16| (((value >> shift) & 0x1f) as u32 * 255 / 31) as u8;
+            let alpha = if alpha_bits > 0 && value & 0x8000 == 0 {
+                0
+            } else {
+                255
+            };
+            [channel(10), channel(5), channel(0), alpha]
+        }
+        3 => [bytes[2], bytes[1], bytes[0], 255],
+        _ => [bytes[2], bytes[1], bytes[0], bytes[3]],
+    }
+}
+
+/// Decodes a TGA file into RGBA8.
+/// Supports color mapped (1, 9), true color (2, 10) and grayscale (3, 11) images,
+/// raw and run-length encoded, with either vertical and horizontal origin.
+pub fn decode(data: &[u8]) -> Result<Image, TgaError> {
+    let header = data.get(..18).ok_or(TgaError::UnexpectedEnd)?;
+    let id_length = header[0] as usize;
+    let color_map_type = header[1];
+    let image_type = header[2];
+    let map_first = u16::from_le_bytes([header[3], header[4]]);
+    let map_length = u16::from_le_bytes([header[5], header[6]]) as usize;
+    let map_depth = header[7];
+    let width = u16::from_le_bytes([header[12], header[13]]) as usize;
+    let height = u16::from_le_bytes([header[14], header[15]]) as usize;
+    let depth = header[16];
+    let descriptor = header[17];
+
+    let run_length = image_type & 8 != 0;
+    let bytes_per_pixel = match (image_type & !8, depth) {
+        (1, 8) | (3, 8) => 1,
+        (1, 16) | (3, 16) => 2, // 16 bit color map indices, gray with alpha
+        (2, 15 | 16) => 2,
+        (2, 24) => 3,
+        (2, 32) => 4,
+        (1..=3, _) => return Err(TgaError::UnsupportedPixelDepth(depth)),
+        _ => return Err(TgaError::UnsupportedImageType(image_type)),
+    };
+    if width == 0 || height == 0 {
+        return Err(TgaError::EmptyImage);
+    }
+
+    // the color map follows the image id and is converted to RGBA once
+    let mut position = 18 + id_length;
+    let mut color_map = Vec::new();
+    if color_map_type == 1 {
+        let entry_bytes = match map_depth {
+            15 | 16 => 2,
+            24 => 3,
+            32 => 4,
+            _ => return Err(TgaError::InvalidColorMap),
+        };
+        let entries = data
+            .get(position..position + map_length * entry_bytes)
+            .ok_or(TgaError::UnexpectedEnd)?;
+        color_map = entries
+            .chunks_exact(entry_bytes)
+            .map(|entry| true_color(entry, (map_depth == 16) as u8))
+            .collect();
+        position += map_length * entry_bytes;
+    } else if image_type & !8 == 1 {
+        return Err(TgaError::InvalidColorMap);
+    }
+
+    let alpha_bits = descriptor & 0x0f;
+    let to_rgba = |bytes: &[u8]| -> Result<[u8; 4], TgaError> {
+        match image_type & !8 {
+            1 => {
+                let index = if bytes.len() == 2 {
+                    u16::from_le_bytes([bytes[0], bytes[1]])
+                } else {
+                    bytes[0] as u16
+                };
+                index
+                    .checked_sub(map_first)
+                    .and_then(|entry| color_map.get(entry as usize))
+                    .copied()
+                    .ok_or(TgaError::InvalidColorMapIndex(index))
+            }
+            3 => {
+                let alpha = bytes.get(1).copied().unwrap_or(255);
+                Ok([bytes[0], bytes[0], bytes[0], alpha])
+            }
+            _ => Ok(true_color(bytes, alpha_bits)),
+        }
+    };
+
+    // pixels in file order
+    let pixel_count = width
+        .checked_mul(height)
+        .filter(|count| count.checked_mul(4).is_some())
+        .ok_or(TgaError::ImageTooLarge)?;
+    // a raw pixel takes at least one byte, run-length packets grow the buffer as needed
+    let mut pixels = Vec::with_capacity(pixel_count.min(data.len()));
+    let mut read = |length: usize| {
+        let bytes = data
+            .get(position..position + length)
+            .ok_or(TgaError::UnexpectedEnd);
+        position += length;
+        bytes
+    };
+
+    if run_length {
+        // packets: a header byte with the repeat flag and count - 1, then one pixel
+        // repeated or count raw pixels
+        while pixels.len() < pixel_count {
+            let packet = read(1)?[0];
+            let count = ((packet & 0x7f) as usize + 1).min(pixel_count - pixels.len());
+            if packet & 0x80 != 0 {
+                let pixel = to_rgba(read(bytes_per_pixel)?)?;
+                pixels.extend(std::iter::repeat_n(pixel, count));
+            } else {
+                for bytes in read(count * bytes_per_pixel)?.chunks_exact(bytes_per_pixel) {
+                    pixels.push(to_rgba(bytes)?);
+                }
+            }
+        }
+    } else {
+        for bytes in read(pixel_count * bytes_per_pixel)?.chunks_exact(bytes_per_pixel) {
+            pixels.push(to_rgba(bytes)?);
+        }
+    }
+
+    // rows are stored bottom up unless bit 5 is set, right to left if bit 4 is set
+    let (top_down, right_to_left) = (descriptor & 0x20 != 0, descriptor & 0x10 != 0);
+    let mut image = Image {
+        width,
+        height,
+        pixels: vec![0; pixel_count * 4],
+    };
+    for (index, pixel) in pixels.iter().enumerate() {
+        let (mut x, mut y) = (index % width, index / width);
+        if !top_down {
+            y = height - 1 - y;
+        }
+        if right_to_left {
+            x = width - 1 - x;
+        }
+        let target = (y * width + x) * 4;
+        image.pixels[target..target + 4].copy_from_slice(pixel);
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(image_type: u8, width: u16, height: u16, depth: u8, descriptor: u8) -> Vec<u8> {
+        let mut header = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        header.extend_from_slice(&[depth, descriptor]);
+        header
+    }
+
+    #[test]
+    fn decodes_raw_and_run_length_true_color() {
+        // 2x2 BGR, bottom up: the first row in the file is the bottom row
+        let mut file = header(2, 2, 2, 24, 0);
+        file.extend_from_slice(&[0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255]);
+        let raw = decode(&file).unwrap();
+        assert_eq!(
+            raw.pixels,
+            [
+                0, 0, 255, 255, 255, 255, 255, 255, 255, 0, 0, 255, 0, 255, 0, 255
+            ]
+        );
+
+        // same image run-length encoded, top down: a raw packet of 2, then a run of 2
+        let mut file = header(10, 2, 2, 32, 0x28);
+        file.extend_from_slice(&[0x01, 0, 0, 255, 255, 0, 255, 0, 255]);
+        file.extend_from_slice(&[0x81, 255, 255, 255, 128]);
+        let rle = decode(&file).unwrap();
+        assert_eq!(
+            rle.pixels,
+            [
+                255, 0, 0, 255, 0, 255, 0, 255, 255, 255, 255, 128, 255, 255, 255, 128
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_color_mapped_images() {
+        // two 24 bit entries starting at index 1, one row of 8 bit indices
+        let mut file = header(1, 3, 1, 8, 0);
+        file[1] = 1;
+        file[3..8].copy_from_slice(&[1, 0, 2, 0, 24]);
+        file.extend_from_slice(&[0, 0, 255, 255, 0, 0]);
+        file.extend_from_slice(&[1, 2, 1]);
+        let image = decode(&file).unwrap();
+        assert_eq!(
+            image.pixels,
+            [255, 0, 0, 255, 0, 0, 255, 255, 255, 0, 0, 255]
+        );
+
+        *file.last_mut().unwrap() = 0;
+        assert_eq!(
+            decode(&file).unwrap_err(),
+            TgaError::InvalidColorMapIndex(0)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert_eq!(decode(&[0; 10]).unwrap_err(), TgaError::UnexpectedEnd);
+        assert_eq!(
+            decode(&header(4, 1, 1, 24, 0)).unwrap_err(),
+            TgaError::UnsupportedImageType(4)
+        );
+        assert_eq!(
+            decode(&header(2, 1, 1, 12, 0)).unwrap_err(),
+            TgaError::UnsupportedPixelDepth(12)
+        );
+        assert_eq!(
+            decode(&header(2, 2, 1, 24, 0)).unwrap_err(),
+            TgaError::UnexpectedEnd
+        );
+        assert_eq!(
+            decode(&header(10, u16::MAX, u16::MAX, 32, 0)).unwrap_err(),
+            TgaError::UnexpectedEnd
+        );
+    }
+}
@@ -1,4 +1,5 @@
 mod engine;
+mod image;
 mod math;
 mod renderer;
 mod scene;
@@ -1,28 +1,44 @@
 #![allow(dead_code)]
+mod antialiasing; // MSAA sample patterns and SSAA factors
 mod buffer; // Frame/pixel buffer management
+mod clipping; // Homogeneous clipping before the perspective divide
 pub mod color;
 mod core;
 mod draw_command;
 mod fragment;
 mod frustum;
+mod line;
 mod passes;
 mod rasterizer; // Drawing algorithms
 pub mod shader;
+mod stats;
+mod stencil; // Stencil test and ops
 mod target;
+mod texture; // Images sampled by materials
+mod tiling; // Screen tiles for binned, multithreaded rasterization
+mod transparency; // Order independent transparency
 mod view;
 mod viewport; //Screen space transformations and mapping
 
-pub use buffer::{FrameBuffer, RenderTarget};
-pub use color::ColorRGB;
+pub use antialiasing::AntiAliasing;
+pub use buffer::FrameBuffer;
+pub use clipping::Clipper;
+pub use color::{BlendMode, ColorRGB};
 pub use core::Renderer;
 pub use draw_command::DrawCommand;
 pub use fragment::Fragment;
 pub use frustum::Frustum;
+pub use line::{LineCap, LineDepthTest, LineStyle, OccludedLines, OverlayStyles};
 pub use passes::{
-    FacePass, RasterizerInput, RasterizerOutput, RenderPass, VertexNormalPass, VertexPass,
-    WireframePass,
+    DepthMode, FacePass, RasterizerInput, RasterizerOutput, RenderPass, VertexNormalPass,
+    VertexPass, WireframeMode, WireframePass,
 };
 pub use rasterizer::Rasterizer;
-pub use shader::{FlatShader, Material, ShadingModel};
+pub use shader::{FlatShader, Material, ShadingMode, ShadingModel};
+pub use stats::RenderStats;
+pub use stencil::{CompareFunction, StencilOp, StencilState};
+pub use target::{ColorBand, RenderTarget};
+pub use texture::{FilterMode, Texture, WrapMode};
+pub use transparency::{TransparencyMode, fragment_list_order};
 pub use view::RenderView;
-pub use viewport::Viewport;
+pub use viewport::{Rect, Viewport};
@@ -0,0 +1,84 @@
+/// Anti-aliasing mode of a `RenderTarget`
+///
+/// * MSAA tests coverage and depth per sample but shades once per pixel
+/// * SSAA renders the whole view at a higher resolution and downsamples it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AntiAliasing {
+    #[default]
+    Off,
+    Msaa2x,
+    Msaa4x,
+    Msaa8x,
+    Ssaa2x, // 2x2 pixels per display pixel
+    Ssaa3x, // 3x3 pixels per display pixel
+}
+
+/// Single sample in the pixel center
+const CENTER_SAMPLE: [[f64; 2]; 1] = [[0.5, 0.5]];
+
+const MSAA_2X_SAMPLES: [[f64; 2]; 2] = [[0.25, 0.25], [0.75, 0.75]];
+
+/// Rotated grid, no two samples share a row or column
+const MSAA_4X_SAMPLES: [[f64; 2]; 4] = [
+    [0.375, 0.125],
+    [0.875, 0.375],
+    [0.125, 0.625],
+    [0.625, 0.875],
+];
+
+/// Standard 8x pattern on a 1/16 pixel grid
+const MSAA_8X_SAMPLES: [[f64; 2]; 8] = [
+    [0.5625, 0.3125],
+    [0.4375, 0.6875],
+    [0.8125, 0.5625],
+    [0.3125, 0.1875],
+    [0.1875, 0.8125],
+    [0.0625, 0.4375],
+    [0.6875, 0.9375],
+    [0.9375, 0.0625],
+];
+
+impl AntiAliasing {
+    pub const ALL: [AntiAliasing; 6] = [
+        AntiAliasing::Off,
+        AntiAliasing::Msaa2x,
+        AntiAliasing::Msaa4x,
+        AntiAliasing::Msaa8x,
+        AntiAliasing::Ssaa2x,
+        AntiAliasing::Ssaa3x,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AntiAliasing::Off => "Off",
+            AntiAliasing::Msaa2x => "MSAA 2x",
+            AntiAliasing::Msaa4x => "MSAA 4x",
+            AntiAliasing::Msaa8x => "MSAA 8x",
+            AntiAliasing::Ssaa2x => "SSAA 2x2",
+            AntiAliasing::Ssaa3x => "SSAA 3x3",
+        }
+    }
+
+    /// Sample positions inside a pixel in the range 0..1, one entry per sample
+    pub fn sample_positions(&self) -> &'static [[f64; 2]] {
+        match self {
+            AntiAliasing::Msaa2x => &MSAA_2X_SAMPLES,
+            AntiAliasing::Msaa4x => &MSAA_4X_SAMPLES,
+            AntiAliasing::Msaa8x => &MSAA_8X_SAMPLES,
+            AntiAliasing::Off | AntiAliasing::Ssaa2x | AntiAliasing::Ssaa3x => &CENTER_SAMPLE,
+        }
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.sample_positions().len()
+    }
+
+    /// Render resolution multiplier per axis
+    pub fn ssaa_factor(&self) -> usize {
+        match self {
+            AntiAliasing::Ssaa2x => 2,
+            AntiAliasing::Ssaa3x => 3,
+            _ => 1,
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::renderer::color::ColorRGB;
+use crate::renderer::color::{BlendMode, ColorRGB};
 
 pub struct FrameBuffer {
     pub buffer: Vec<u8>,
@@ -94,6 +94,32 @@ impl FrameBuffer {
         }
     }
 
+    /// Blends a color onto a pixel, `alpha` is the opacity of the new color (0.0 - 1.0)
+    pub fn blend_pixel(
+        &mut self,
+        x: usize,
+        y: usize,
+        color: ColorRGB,
+        alpha: f64,
+        mode: BlendMode,
+    ) {
+        if alpha >= 1.0 && mode == BlendMode::Over {
+            self.set_pixel(x, y, color);
+            return;
+        }
+
+        if self.is_in_bounds(x, y) {
+            let index = self.get_index(x, y);
+            let pixel = &mut self.buffer[index..index + 4];
+            let dst = ColorRGB::from_rgba(pixel[0], pixel[1], pixel[2], pixel[3]);
+            let blended = dst.blend(color, alpha, mode);
+
+            pixel[0] = blended.get_r();
+            pixel[1] = blended.get_g();
+            pixel[2] = blended.get_b();
+        }
+    }
+
     pub fn get_pixel(&self, x: usize, y: usize) -> u8 {
         if self.is_in_bounds(x, y) {
             let index = self.get_index(x, y);
@@ -108,28 +134,22 @@ impl FrameBuffer {
             pixel.copy_from_slice(&color.as_argb_u8_slice());
         }
     }
-}
 
-pub struct RenderTarget {
-    pub framebuffer: FrameBuffer,
-    pub z_buffer: Vec<f64>,
-}
-
-impl RenderTarget {
-    pub fn new(width: usize, height: usize) -> Self {
-        Self {
-            framebuffer: FrameBuffer::new(width, height),
-            z_buffer: vec![f64::INFINITY; width * height],
+    /// Fills the pixels inside `bounds`, `(min_x, min_y, max_x, max_y)` with exclusive max
+    pub fn fill_rect(&mut self, bounds: (i32, i32, i32, i32), color: ColorRGB) {
+        let min_x = bounds.0.max(0) as usize;
+        let max_x = (bounds.2.max(0) as usize).min(self.width);
+        let min_y = bounds.1.max(0) as usize;
+        let max_y = (bounds.3.max(0) as usize).min(self.height);
+        if min_x >= max_x {
+            return;
         }
-    }
-
-    pub fn resize(&mut self, width: usize, height: usize) {
-        self.framebuffer = FrameBuffer::new(width, height);
-        self.z_buffer = vec![f64::INFINITY; width * height];
-    }
 
-    pub fn clear(&mut self, clear_color: ColorRGB) {
-        self.framebuffer.fill(clear_color);
-        self.z_buffer.fill(f64::INFINITY);
+        for y in min_y..max_y {
+            let row = self.get_index(min_x, y)..self.get_index(max_x - 1, y) + 4;
+            for pixel in self.buffer[row].chunks_exact_mut(4) {
+                pixel.copy_from_slice(&color.as_argb_u8_slice());
+            }
+        }
     }
 }
@@ -0,0 +1,206 @@
+use crate::math::Point3D;
+use crate::scene::Vertex;
+
+/// Clip-space planes the pipeline clips against before the homogeneous divide.
+///
+/// The projection matrix maps the visible depth range to `-w <= z <= w`, so both
+/// planes can be tested without dividing by w.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipPlane {
+    Near,
+    Far,
+}
+
+impl ClipPlane {
+    pub const ALL: [ClipPlane; 2] = [ClipPlane::Near, ClipPlane::Far];
+
+    /// Signed distance of a clip-space position to the plane.
+    /// Positive values are on the visible side.
+    pub fn distance(&self, z: f64, w: f64) -> f64 {
+        match self {
+            ClipPlane::Near => z + w,
+            ClipPlane::Far => w - z,
+        }
+    }
+}
+
+pub struct Clipper;
+
+impl Clipper {
+    /// Returns true if all vertices are on the visible side of every clip plane
+    pub fn triangle_inside(v0: &Vertex, v1: &Vertex, v2: &Vertex) -> bool {
+        ClipPlane::ALL.iter().all(|plane| {
+            [v0, v1, v2]
+                .iter()
+                .all(|v| plane.distance(v.position[2], v.w) >= 0.0)
+        })
+    }
+
+    /// Returns true if all vertices are behind the same clip plane,
+    /// in which case the triangle can be discarded without clipping
+    pub fn triangle_outside(v0: &Vertex, v1: &Vertex, v2: &Vertex) -> bool {
+        ClipPlane::ALL.iter().any(|plane| {
+            [v0, v1, v2]
+                .iter()
+                .all(|v| plane.distance(v.position[2], v.w) < 0.0)
+        })
+    }
+
+    /// Clips a convex polygon in clip space against the near and far planes
+    /// (Sutherland–Hodgman). Attributes of new vertices are interpolated linearly,
+    /// which is correct because clip space has not been divided by w yet.
+    ///
+    /// ### Returns
+    ///
+    /// * The clipped polygon, empty if nothing is left
+    pub fn clip_polygon(polygon: &[Vertex]) -> Vec<Vertex> {
+        let mut input: Vec<Vertex> = polygon.to_vec();
+
+        for plane in ClipPlane::ALL {
+            if input.is_empty() {
+                break;
+            }
+
+            let mut output: Vec<Vertex> = Vec::with_capacity(input.len() + 1);
+
+            for i in 0..input.len() {
+                let current = input[i];
+                let next = input[(i + 1) % input.len()];
+
+                let d_current = plane.distance(current.position[2], current.w);
+                let d_next = plane.distance(next.position[2], next.w);
+
+                if d_current >= 0.0 {
+                    output.push(current);
+                }
+
+                // edge crosses the plane
+                if (d_current >= 0.0) != (d_next >= 0.0) {
+                    let t = d_current / (d_current - d_next);
+                    output.push(current.lerp(&next, t));
+                }
+            }
+
+            input = output;
+        }
+
+        input
+    }
+
+    /// Clips a line segment in clip space against the near and far planes.
+    ///
+    /// ### Returns
+    ///
+    /// * The visible part of the segment, `None` if it is completely clipped
+    pub fn clip_line(p0: Point3D, p1: Point3D) -> Option<(Point3D, Point3D)> {
+        let mut t_enter: f64 = 0.0;
+        let mut t_exit: f64 = 1.0;
+
+        for plane in ClipPlane::ALL {
+            let d0 = plane.distance(p0.z, p0.w);
+            let d1 = plane.distance(p1.z, p1.w);
+
+            if d0 < 0.0 && d1 < 0.0 {
+                return None;
+            }
+
+            if d0 < 0.0 {
+                t_enter = t_enter.max(d0 / (d0 - d1));
+            } else if d1 < 0.0 {
+                t_exit = t_exit.min(d0 / (d0 - d1));
+            }
+        }
+
+        if t_enter > t_exit {
+            return None;
+        }
+
+        let lerp = |t: f64| Point3D {
+            x: p0.x + (p1.x - p0.x) * t,
+            y: p0.y + (p1.y - p0.y) * t,
+            z: p0.z + (p1.z - p0.z) * t,
+            w: p0.w + (p1.w - p0.w) * t,
+        };
+
+        Some((lerp(t_enter), lerp(t_exit)))
+    }
+
+    /// Clips a screen-space segment against a rectangle (Liang–Barsky), so line
+    /// rasterization only steps over the part that can touch the target.
+    ///
+    /// ### Arguments
+    ///
+    /// * `p0`, `p1` - End points of the segment
+    /// * `min`, `max` - Corners of the rectangle, both inclusive
+    ///
+    /// ### Returns
+    ///
+    /// * The part of the segment inside the rectangle, `None` if it misses it
+    pub fn clip_line_to_rect(
+        p0: [f64; 2],
+        p1: [f64; 2],
+        min: [f64; 2],
+        max: [f64; 2],
+    ) -> Option<([f64; 2], [f64; 2])> {
+        let delta = [p1[0] - p0[0], p1[1] - p0[1]];
+        let mut t_enter: f64 = 0.0;
+        let mut t_exit: f64 = 1.0;
+
+        for axis in 0..2 {
+            // (p, q) for the min and max edge of this axis, inside if p * t <= q
+            for (p, q) in [
+                (-delta[axis], p0[axis] - min[axis]),
+                (delta[axis], max[axis] - p0[axis]),
+            ] {
+                if p == 0.0 {
+                    // parallel to the edge
+                    if q < 0.0 {
+                        return None;
+                    }
+                } else {
+                    let t = q / p;
+                    if p < 0.0 {
+                        t_enter = t_enter.max(t);
+                    } else {
+                        t_exit = t_exit.min(t);
+                    }
+                }
+            }
+        }
+
+        if t_enter > t_exit {
+            return None;
+        }
+
+        let lerp = |t: f64| [p0[0] + delta[0] * t, p0[1] + delta[1] * t];
+
+        Some((lerp(t_enter), lerp(t_exit)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_is_clipped_to_rect() {
+        let min = [0.0, 0.0];
+        let max = [99.0, 49.0];
+
+        // crosses the whole rectangle horizontally
+        let (a, b) = Clipper::clip_line_to_rect([-1e9, 10.0], [1e9, 10.0], min, max).unwrap();
+        assert_eq!((a, b), ([0.0, 10.0], [99.0, 10.0]));
+
+        // diagonal entering through the left and leaving through the bottom
+        let (a, b) = Clipper::clip_line_to_rect([-10.0, 0.0], [90.0, 100.0], min, max).unwrap();
+        assert_eq!((a, b), ([0.0, 10.0], [39.0, 49.0]));
+
+        // inside segments are unchanged
+        let (a, b) = Clipper::clip_line_to_rect([5.0, 5.0], [6.0, 7.0], min, max).unwrap();
+        assert_eq!((a, b), ([5.0, 5.0], [6.0, 7.0]));
+
+        // misses the rectangle
+        assert!(Clipper::clip_line_to_rect([-10.0, 60.0], [200.0, 55.0], min, max).is_none());
+        assert!(Clipper::clip_line_to_rect([120.0, -5.0], [120.0, 80.0], min, max).is_none());
+    }
+}
@@ -1,5 +1,26 @@
 use crate::math::Vector3D;
 
+/// How a color is composited onto the color already in the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    #[default]
+    Over, // src * alpha + dst * (1 - alpha)
+    Additive, // dst + src * alpha
+    Multiply, // dst * (src * alpha + 1 - alpha)
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 3] = [BlendMode::Over, BlendMode::Additive, BlendMode::Multiply];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BlendMode::Over => "Over",
+            BlendMode::Additive => "Additive",
+            BlendMode::Multiply => "Multiply",
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ColorRGB {
@@ -108,6 +129,26 @@ impl ColorRGB {
         self.b
     }
 
+    /// Composites `src` with opacity `alpha` onto this color, keeping this color's alpha
+    pub fn blend(self, src: ColorRGB, alpha: f64, mode: BlendMode) -> ColorRGB {
+        let channel = |dst: u8, src: u8| {
+            let (dst, src) = (dst as f64, src as f64);
+            let value = match mode {
+                BlendMode::Over => dst + (src - dst) * alpha,
+                BlendMode::Additive => dst + src * alpha,
+                BlendMode::Multiply => dst * (src / 255.0 * alpha + 1.0 - alpha),
+            };
+            value.clamp(0.0, 255.0) as u8
+        };
+
+        ColorRGB::from_rgba(
+            channel(self.r, src.r),
+            channel(self.g, src.g),
+            channel(self.b, src.b),
+            self.a,
+        )
+    }
+
     pub fn to_vector(self) -> Vector3D {
         Vector3D::new(
             self.get_r() as f64 / 255.0,
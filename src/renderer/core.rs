@@ -1,12 +1,14 @@
 use super::{
-    ColorRGB, DrawCommand, FacePass, FlatShader, Fragment, Frustum, Material, Rasterizer,
-    RasterizerInput, RasterizerOutput, RenderPass, RenderTarget, ShadingModel, VertexNormalPass,
-    VertexPass, WireframePass,
+    BlendMode, Clipper, ColorBand, ColorRGB, DepthMode, DrawCommand, FacePass, FlatShader,
+    Fragment, Frustum, LineDepthTest, LineStyle, OccludedLines, OverlayStyles, Rasterizer,
+    RasterizerInput, RasterizerOutput, RenderPass, RenderStats, RenderTarget, ShadingMode,
+    ShadingModel, Texture, TransparencyMode, VertexNormalPass, VertexPass, WireframeMode,
+    WireframePass, fragment_list_order,
 };
 use crate::{
-    math::{Mat4x4, Point3D, ScreenPoint},
+    math::{Mat4x4, Point3D, Vector3D},
     renderer::view::RenderView,
-    scene::{Camera, PointLight, Scene, Vertex},
+    scene::{Camera, MaterialHandle, MaterialLibrary, PointLight, Scene, Vertex},
 };
 
 pub struct Renderer {
@@ -23,7 +25,8 @@ pub struct Renderer {
     pub(crate) fragment_buffer: Vec<Fragment>, // Output of rasterization
 
     // Pipeline state
-    pub(crate) material_cache: Vec<Material>,
+    pub(crate) view_lights: Vec<PointLight>, // lights transformed to view space
+    pub(crate) view_vector: Vector3D,
 
     // Matrices (could also be per-frame data)
     pub(crate) look_at_matrix: Mat4x4,
@@ -35,13 +38,26 @@ pub struct Renderer {
 
     pub rasterizer: Rasterizer,
     pub shader: FlatShader,
+    stats: RenderStats, // counters of the view being rendered, copied to `RenderView::stats`
+    pub overlay_styles: OverlayStyles,
 
     pub draw_z_buffer: bool,
+    pub draw_mip_levels: bool, // color textured surfaces by the mip level they sample
     pub draw_wireframe: bool,
     pub draw_vertex: bool,
     pub draw_vertex_normals: bool,
     pub draw_faces: bool,
     pub backface_culling: bool,
+    pub perspective_correct: bool,
+    pub frustum_culling: bool,
+    pub thread_count: usize,
+    pub depth_test_lines: bool,
+    pub occluded_lines: OccludedLines,
+    pub line_depth_bias: f64, // polygon offset of lines against faces, fraction of the view distance
+    pub wireframe_mode: WireframeMode,
+    pub depth_prepass: bool, // rasterize depth first, then shade only visible fragments
+    pub immediate_mode: bool, // shade faces while rasterizing instead of buffering fragments
+    pub transparency: TransparencyMode,
 }
 
 impl Renderer {
@@ -54,7 +70,8 @@ impl Renderer {
         let debug_lines: Vec<[i32; 4]> = Vec::new();
 
         let fragment_buffer: Vec<Fragment> = Vec::new();
-        let material_cache: Vec<Material> = Material::MATERIAL_ARRAY.to_vec();
+        let view_lights: Vec<PointLight> = Vec::new();
+        let view_vector: Vector3D = Vector3D::new(0.0, 0.0, 1.0);
 
         let look_at_matrix: Mat4x4 = Mat4x4::identity();
         let projection_matrix: Mat4x4 = Mat4x4::identity();
@@ -64,11 +81,22 @@ impl Renderer {
         let view_frustum: Frustum = Frustum::new();
 
         let draw_z_buffer = false;
+        let draw_mip_levels = false;
         let draw_wireframe = false;
         let draw_vertex = false;
         let draw_vertex_normals = false;
         let draw_faces = true;
         let backface_culling = true;
+        let perspective_correct = true;
+        let frustum_culling = true;
+        let thread_count = Self::available_threads();
+        let depth_test_lines = true;
+        let occluded_lines = OccludedLines::Hidden;
+        let line_depth_bias = LineDepthTest::DEFAULT_DEPTH_BIAS;
+        let wireframe_mode = WireframeMode::Overlay;
+        let depth_prepass = false;
+        let immediate_mode = false;
+        let transparency = TransparencyMode::Sorted;
 
         Self {
             vertex_buffer,
@@ -80,7 +108,8 @@ impl Renderer {
 
             fragment_buffer,
 
-            material_cache,
+            view_lights,
+            view_vector,
 
             look_at_matrix,
             projection_matrix,
@@ -91,32 +120,113 @@ impl Renderer {
 
             rasterizer: Rasterizer::new(),
             shader: FlatShader,
+            stats: RenderStats::default(),
+            overlay_styles: OverlayStyles::default(),
 
             draw_z_buffer,
+            draw_mip_levels,
             draw_wireframe,
             draw_vertex,
             draw_vertex_normals,
             draw_faces,
             backface_culling,
+            perspective_correct,
+            frustum_culling,
+            thread_count,
+            depth_test_lines,
+            occluded_lines,
+            line_depth_bias,
+            wireframe_mode,
+            depth_prepass,
+            immediate_mode,
+            transparency,
         }
     }
 
-    fn project_point(point: Point3D, matrix: &Mat4x4, viewport_matrix: &Mat4x4) -> ScreenPoint {
-        let mut projected = *matrix * point;
-        projected.dehomogen();
-        projected = *viewport_matrix * projected;
-        ScreenPoint {
-            x: projected.x as i32,
-            y: projected.y as i32,
+    /// Number of worker threads the renderer can use.
+    /// The wasm build has no threads, so it always renders single threaded.
+    pub fn available_threads() -> usize {
+        #[cfg(target_arch = "wasm32")]
+        {
+            1
         }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            std::thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1)
+        }
+    }
+
+    /// Projects a line into screen space, clipping it against the near and far
+    /// planes first so segments behind the camera are not inverted by the divide.
+    /// The returned points keep their screen-space depth in `z`.
+    fn project_line(
+        start: Point3D,
+        end: Point3D,
+        matrix: &Mat4x4,
+        viewport_matrix: &Mat4x4,
+    ) -> Option<(Point3D, Point3D)> {
+        let (mut clip_start, mut clip_end) = Clipper::clip_line(*matrix * start, *matrix * end)?;
+
+        clip_start.dehomogen();
+        clip_end.dehomogen();
+
+        Some((*viewport_matrix * clip_start, *viewport_matrix * clip_end))
+    }
+
+    /// Draws a debug overlay line, testing it against the depth of the rendered scene
+    /// if `depth_test_lines` is set
+    fn draw_overlay_line(
+        &self,
+        start: Point3D,
+        end: Point3D,
+        color: ColorRGB,
+        style: LineStyle,
+        bounds: (i32, i32, i32, i32),
+        target: &mut RenderTarget,
+    ) {
+        let p0 = [start.x, start.y];
+        let p1 = [end.x, end.y];
+
+        if !self.depth_test_lines {
+            self.rasterizer
+                .draw_line(p0, p1, color, style, bounds, target);
+            return;
+        }
+
+        let (width, height) = (
+            target.framebuffer.get_width(),
+            target.framebuffer.get_height(),
+        );
+        let depth_test = LineDepthTest::new(
+            &target.z_buffer,
+            width,
+            height,
+            self.occluded_lines,
+            self.line_depth_bias,
+        );
+        let length = (p1[0] - p0[0]).hypot(p1[1] - p0[1]);
+
+        self.rasterizer
+            .for_each_line_fragment(p0, p1, style, bounds, |x, y, coverage, t| {
+                let z = start.z + (end.z - start.z) * t;
+                let alpha = depth_test.alpha(x, y, z, t * length, coverage);
+                if alpha > 0.0 {
+                    target.framebuffer.blend_pixel(
+                        x as usize,
+                        y as usize,
+                        color,
+                        alpha,
+                        BlendMode::Over,
+                    );
+                }
+            });
     }
 
     /// Command Stream - Collect and prepare draw calls
     fn process_commands(&mut self, scene: &Scene) {
-        // - Set up vertex/index buffer ranges
-        // - Track material changes
-        // - Handle state changes
-
         // collection stage: here we need to collect
         // - vetices in the self.vertex_buffer
         // - triangle indices in the self.triangle_index_buffer
@@ -130,100 +240,314 @@ impl Renderer {
             self.draw_commands,
         ) = scene.collect();
 
+        // skip draw commands whose bounding sphere is outside the view frustum
+        self.stats.draw_commands = self.draw_commands.len();
+        if self.frustum_culling {
+            let frustum = &self.view_frustum;
+            self.draw_commands
+                .retain(|draw_command| frustum.sphere_in_bounds(&draw_command.bounds));
+        }
+        self.stats.culled_draw_commands = self.stats.draw_commands - self.draw_commands.len();
+
+        // order independent transparency sorts per pixel and keeps the submission order
+        if self.transparency == TransparencyMode::Sorted {
+            self.sort_draw_commands(&scene.materials);
+        }
+
+        // state changes between consecutive draws in submission order, the first draw sets both
+        let mut previous: Option<&DrawCommand> = None;
+        for draw_command in &self.draw_commands {
+            if previous.is_none_or(|previous| previous.material != draw_command.material) {
+                self.stats.material_changes += 1;
+            }
+            if previous.is_none_or(|previous| previous.stencil != draw_command.stencil) {
+                self.stats.stencil_changes += 1;
+            }
+            previous = Some(draw_command);
+        }
+
         //clone vertices so we can still access original vertices
         self.transformed_vertices = self.vertex_buffer.clone();
     }
 
+    /// Opaque draw commands keep their order and go first, transparent ones follow
+    /// back to front by the view depth of their bounding sphere center
+    fn sort_draw_commands(&mut self, materials: &MaterialLibrary) {
+        let look_at_matrix = self.look_at_matrix;
+        let is_transparent =
+            |draw_command: &DrawCommand| materials.get(draw_command.material).is_transparent();
+        let view_depth = |draw_command: &DrawCommand| {
+            let center = look_at_matrix * draw_command.bounds.center;
+            center.x.hypot(center.y).hypot(center.z)
+        };
+        self.draw_commands
+            .sort_by(|a, b| match (is_transparent(a), is_transparent(b)) {
+                (false, false) => std::cmp::Ordering::Equal,
+                (false, true) => std::cmp::Ordering::Less,
+                (true, false) => std::cmp::Ordering::Greater,
+                (true, true) => view_depth(b).total_cmp(&view_depth(a)),
+            });
+    }
+
     /// Vertex Processing Stage
-    fn process_vertices(&mut self, scene: &Scene, active_camera: &Camera) {
-        let transformed_lights: Vec<PointLight> = scene
-            .collect_lights()
-            .iter()
-            .map(|light| PointLight::new_transformed_light(light, self.look_at_matrix))
-            .collect();
+    ///
+    /// Vertices are independent of each other, so every mesh is split into chunks
+    /// that are processed on worker threads. Debug normal lines are collected per
+    /// chunk and appended in chunk order, keeping the output deterministic.
+    fn process_vertices(&mut self, materials: &MaterialLibrary) {
+        let stage = VertexStage {
+            look_at_matrix: self.look_at_matrix,
+            projection_matrix: self.projection_matrix,
+            viewport_matrix: self.viewport_matrix,
+            view_vector: self.view_vector,
+            lights: &self.view_lights,
+            materials,
+            shader: &self.shader,
+            draw_vertex_normals: self.draw_vertex_normals,
+        };
 
-        for draw_command in &self.draw_commands {
-            for vertex_idx in 0..draw_command.vertex_count {
-                // 1. Model to World transform (Model space -> World space)
-                self.transformed_vertices[draw_command.first_vertex_offset + vertex_idx]
-                    .transform(draw_command.transform);
+        // split the vertex buffer into disjoint chunks, each belonging to one draw command
+        let mut draw_commands: Vec<&DrawCommand> = self.draw_commands.iter().collect();
+        draw_commands.sort_by_key(|draw_command| draw_command.first_vertex_offset);
 
-                // 2. World to look_at transform (world space -> view/camera space)
-                self.transformed_vertices[draw_command.first_vertex_offset + vertex_idx]
-                    .transform(self.look_at_matrix);
+        let mut jobs: Vec<(&DrawCommand, &mut [Vertex])> = Vec::new();
+        let mut remaining: &mut [Vertex] = &mut self.transformed_vertices;
+        let mut consumed = 0;
 
-                // 3. Lighting calculations (in view space)
-                let vertex =
-                    &mut self.transformed_vertices[draw_command.first_vertex_offset + vertex_idx];
+        for draw_command in draw_commands {
+            let (_, tail) = std::mem::take(&mut remaining)
+                .split_at_mut(draw_command.first_vertex_offset - consumed);
+            let (mesh_vertices, tail) = tail.split_at_mut(draw_command.vertex_count);
+            remaining = tail;
+            consumed = draw_command.first_vertex_offset + draw_command.vertex_count;
 
-                vertex.color = self.shader.calc_color(
-                    &vertex.position_to_point(),
-                    &vertex.normal_to_vector(),
-                    &vertex.color,
-                    &active_camera.direction.normalize(),
-                    &self.material_cache[draw_command.material_id],
-                    &transformed_lights,
-                );
+            for chunk in mesh_vertices.chunks_mut(VERTEX_CHUNK_SIZE) {
+                jobs.push((draw_command, chunk));
+            }
+        }
+
+        let thread_count = self
+            .thread_count
+            .clamp(1, Self::available_threads())
+            .min(jobs.len());
+
+        if thread_count <= 1 {
+            for (draw_command, vertices) in jobs {
+                stage.process(draw_command, vertices, &mut self.debug_lines);
+            }
+            return;
+        }
+
+        // hand out chunks round robin
+        let mut worker_jobs: Vec<Vec<(usize, &DrawCommand, &mut [Vertex])>> =
+            (0..thread_count).map(|_| Vec::new()).collect();
+        for (job_idx, (draw_command, vertices)) in jobs.into_iter().enumerate() {
+            worker_jobs[job_idx % thread_count].push((job_idx, draw_command, vertices));
+        }
 
-                if self.draw_vertex_normals && vertex.has_normal() {
-                    let line_len = 0.075;
+        let stage = &stage;
+        let mut chunk_lines: Vec<(usize, Vec<[i32; 4]>)> = std::thread::scope(|scope| {
+            let workers: Vec<_> = worker_jobs
+                .into_iter()
+                .map(|jobs| {
+                    scope.spawn(move || {
+                        jobs.into_iter()
+                            .map(|(job_idx, draw_command, vertices)| {
+                                let mut lines = Vec::new();
+                                stage.process(draw_command, vertices, &mut lines);
+                                (job_idx, lines)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("vertex worker panicked"))
+                .collect()
+        });
+
+        chunk_lines.sort_by_key(|(job_idx, _)| *job_idx);
+        for (_, lines) in chunk_lines {
+            self.debug_lines.extend(lines);
+        }
+    }
 
-                    let start_point_view: Point3D = vertex.position_to_point();
+    /// Clipping Stage
+    ///
+    /// Clips every triangle against the near and far planes in clip space.
+    /// Triangles that are partly visible are replaced by a fan of new triangles
+    /// whose vertices are appended to `transformed_vertices`.
+    fn clip_primitives(&mut self) {
+        let mut clipped_index_buffer: Vec<u32> =
+            Vec::with_capacity(self.triangle_index_buffer.len());
+
+        for draw_command in &mut self.draw_commands {
+            let index_start = draw_command.first_triangle_index_offset;
+            let index_end = index_start + draw_command.triangle_index_count;
+
+            draw_command.first_triangle_index_offset = clipped_index_buffer.len();
+
+            for triangle in self.triangle_index_buffer[index_start..index_end].chunks_exact(3) {
+                let v0 = &self.transformed_vertices[triangle[0] as usize];
+                let v1 = &self.transformed_vertices[triangle[1] as usize];
+                let v2 = &self.transformed_vertices[triangle[2] as usize];
+
+                if Clipper::triangle_inside(v0, v1, v2) {
+                    clipped_index_buffer.extend_from_slice(triangle);
+                    continue;
+                }
 
-                    let end_point_view: Point3D =
-                        start_point_view + vertex.normal_to_vector() * line_len;
+                if Clipper::triangle_outside(v0, v1, v2) {
+                    continue;
+                }
 
-                    let start_screen = Self::project_point(
-                        start_point_view,
-                        &self.projection_matrix,
-                        &self.viewport_matrix,
-                    );
-                    let end_screen = Self::project_point(
-                        end_point_view,
-                        &self.projection_matrix,
-                        &self.viewport_matrix,
-                    );
+                let polygon = Clipper::clip_polygon(&[*v0, *v1, *v2]);
+                if polygon.len() < 3 {
+                    continue;
+                }
 
-                    self.debug_lines.push([
-                        start_screen.x,
-                        start_screen.y,
-                        end_screen.x,
-                        end_screen.y,
+                // append the clipped polygon and triangulate it as a fan around its first vertex
+                let first_index = self.transformed_vertices.len() as u32;
+                self.transformed_vertices.extend(polygon.iter());
+
+                for i in 1..polygon.len() as u32 - 1 {
+                    clipped_index_buffer.extend_from_slice(&[
+                        first_index,
+                        first_index + i,
+                        first_index + i + 1,
                     ]);
                 }
+            }
 
-                // 4. Projection transform (View space -> Clip space)
-                let mut vertex_pos = self.projection_matrix * vertex.position_to_point();
+            draw_command.triangle_index_count =
+                clipped_index_buffer.len() - draw_command.first_triangle_index_offset;
+        }
 
-                // 5. Homogeneous divide (w)
-                vertex_pos.dehomogen();
+        self.triangle_index_buffer = clipped_index_buffer;
+    }
 
-                //6. Viewport transformation (Clip Space -> Screen space)
-                vertex_pos = self.viewport_matrix * vertex_pos;
-                vertex.position = [vertex_pos.x, vertex_pos.y, vertex_pos.z];
-            }
+    /// Homogeneous divide and viewport transform for all vertices that survived clipping
+    fn project_to_screen(&mut self) {
+        for vertex in &mut self.transformed_vertices {
+            let mut vertex_pos = Point3D {
+                x: vertex.position[0],
+                y: vertex.position[1],
+                z: vertex.position[2],
+                w: vertex.w,
+            };
+
+            // 5. Homogeneous divide (w), w stays on the vertex for perspective-correct interpolation
+            vertex_pos.dehomogen();
+
+            // 6. Viewport transformation (Clip Space -> Screen space)
+            vertex_pos = self.viewport_matrix * vertex_pos;
+            vertex.position = [vertex_pos.x, vertex_pos.y, vertex_pos.z];
         }
     }
 
     /// Rasterization Stage
-    fn rasterize(&mut self, target: &mut RenderTarget) {
+    fn rasterize(
+        &mut self,
+        target: &mut RenderTarget,
+        scissor: (i32, i32, i32, i32),
+        materials: &MaterialLibrary,
+    ) {
+        // keep overlay lines the same size on screen when rendering at a higher resolution
+        let ssaa_factor = target.get_anti_aliasing().ssaa_factor() as f64;
+
+        let stage = FragmentStage {
+            view_vector: self.view_vector,
+            lights: &self.view_lights,
+            materials,
+            shader: &self.shader,
+            mip_levels: self.draw_mip_levels,
+        };
+
         let input = RasterizerInput {
             draw_commands: &self.draw_commands,
+            materials,
             triangle_index_buffer: &self.triangle_index_buffer,
             transformed_vertices: &self.transformed_vertices,
             backface_culling: self.backface_culling,
+            perspective_correct: self.perspective_correct,
+            thread_count: self.thread_count.clamp(1, Self::available_threads()),
+            wireframe_style: self.overlay_styles.wireframe.scaled(ssaa_factor),
+            vertex_normal_style: self.overlay_styles.vertex_normals.scaled(ssaa_factor),
+            depth_test_lines: self.depth_test_lines,
+            occluded_lines: self.occluded_lines,
+            wireframe_mode: self.wireframe_mode,
+            line_depth_bias: self.line_depth_bias,
+            buffer_transparent: self.transparency == TransparencyMode::OrderIndependent,
+            fragment_shader: &|fragment| Self::fragment_color(stage.shade(fragment)),
         };
 
+        let sample_positions = target.get_anti_aliasing().sample_positions();
+        let (target_width, target_height) = (
+            target.framebuffer.get_width(),
+            target.framebuffer.get_height(),
+        );
+        let (z_buffer, stencil_buffer, primitive_buffer, color) = target.depth_stencil_and_color();
+
         let mut output = RasterizerOutput {
             fragment_buffer: &mut self.fragment_buffer,
-            z_buffer: &mut target.z_buffer,
+            color: self.immediate_mode.then_some(color),
+            z_buffer,
+            stencil_buffer,
+            primitive_buffer,
+            sample_positions,
             debug_lines: &mut self.debug_lines,
-            target_width: target.framebuffer.get_width(),
-            target_height: target.framebuffer.get_height(),
+            target_width,
+            target_height,
+            scissor,
+            stats: &mut self.stats,
         };
 
-        if self.draw_faces {
-            FacePass.execute(&self.rasterizer, &input, &mut output);
+        // stencil ops depend on the draw order, so stenciled draws cannot be resolved
+        // against the final depth of a pre-pass
+        let depth_prepass = self.depth_prepass
+            && !self
+                .draw_commands
+                .iter()
+                .any(|draw_command| draw_command.stencil.is_enabled());
+
+        // hidden-line wireframes only need the faces in the z-buffer,
+        // shaded + wire draws the faces even when they are switched off
+        let wireframe_mode = self.draw_wireframe.then_some(self.wireframe_mode);
+
+        if wireframe_mode == Some(WireframeMode::HiddenLine) {
+            FacePass {
+                depth_mode: DepthMode::DepthOnly,
+            }
+            .execute(&self.rasterizer, &input, &mut output);
+        } else if self.draw_faces || wireframe_mode == Some(WireframeMode::ShadedWire) {
+            if depth_prepass {
+                FacePass {
+                    depth_mode: DepthMode::DepthOnly,
+                }
+                .execute(&self.rasterizer, &input, &mut output);
+            }
+
+            let depth_mode = if depth_prepass {
+                DepthMode::Equal
+            } else {
+                DepthMode::Less
+            };
+            FacePass { depth_mode }.execute(&self.rasterizer, &input, &mut output);
+
+            // fragments were never buffered, so the depth view is drawn from the z-buffer
+            if self.draw_z_buffer
+                && let Some(color) = &mut output.color
+            {
+                Self::shade_depth(
+                    output.z_buffer,
+                    sample_positions.len(),
+                    target_width,
+                    scissor,
+                    color,
+                );
+            }
         }
         if self.draw_vertex {
             VertexPass.execute(&self.rasterizer, &input, &mut output);
@@ -234,23 +558,117 @@ impl Renderer {
         if self.draw_vertex_normals {
             VertexNormalPass.execute(&self.rasterizer, &input, &mut output);
         }
+
+        // only pixels inside the scissor rectangle were cleared and written this frame
+        let sample_count = sample_positions.len();
+        let (min_x, min_y, max_x, max_y) = scissor;
+        self.stats.covered_pixels = (min_y..max_y)
+            .flat_map(|y| {
+                let row_start = (y as usize * target_width + min_x as usize) * sample_count;
+                let row_end = (y as usize * target_width + max_x as usize) * sample_count;
+                target.z_buffer[row_start..row_end].chunks_exact(sample_count)
+            })
+            .filter(|samples| samples.iter().any(|z| z.is_finite()))
+            .count();
+        self.stats.fragment_buffer_bytes =
+            self.fragment_buffer.capacity() * std::mem::size_of::<Fragment>();
+    }
+
+    /// Converts a shaded fragment color into the framebuffer format
+    fn fragment_color(color: [f64; 3]) -> ColorRGB {
+        ColorRGB::from_rgb(
+            ColorRGB::f64_to_color_component(color[0]),
+            ColorRGB::f64_to_color_component(color[1]),
+            ColorRGB::f64_to_color_component(color[2]),
+        )
+    }
+
+    /// Writes the depth of every covered sample inside `bounds` as a gray value, near is bright
+    fn shade_depth(
+        z_buffer: &[f64],
+        sample_count: usize,
+        width: usize,
+        bounds: (i32, i32, i32, i32),
+        color: &mut ColorBand,
+    ) {
+        let (min_x, min_y, max_x, max_y) = bounds;
+        let pixels = || {
+            (min_y..max_y).flat_map(move |y| (min_x..max_x).map(move |x| (x as usize, y as usize)))
+        };
+        let samples = |(x, y): (usize, usize)| {
+            let first_sample = (y * width + x) * sample_count;
+            &z_buffer[first_sample..first_sample + sample_count]
+        };
+
+        let (z_min, z_max) = pixels()
+            .flat_map(samples)
+            .filter(|z| z.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &z| {
+                (min.min(z), max.max(z))
+            });
+        let z_range = z_min - z_max;
+
+        for (x, y) in pixels() {
+            for (sample, z) in samples((x, y)).iter().enumerate() {
+                if z.is_finite() {
+                    let z_norm = (z - z_max) / z_range;
+                    let color_u8 = (z_norm.clamp(0.0, 1.0) * 255.0) as u8;
+                    color.write(
+                        x,
+                        y,
+                        1 << sample,
+                        ColorRGB::from_rgb(color_u8, color_u8, color_u8),
+                    );
+                }
+            }
+        }
     }
 
     /// Fragment Processing Stage
-    fn process_fragments(&mut self) {
-        // Process each fragment in the fragment buffer
-        //
-        //     // Apply any per-fragment effects
-        //     // Could include:
-        //     // - Alpha testing
-        //     // - Additional material effects
-        //     // - Special effects
-        //     // - Framebuffer updates
+    ///
+    /// Lights all buffered fragments of flat and Phong shaded materials, split into
+    /// chunks across the worker threads. Gouraud fragments were lit per vertex already.
+    fn process_fragments(&mut self, materials: &MaterialLibrary) {
+        let stage = FragmentStage {
+            view_vector: self.view_vector,
+            lights: &self.view_lights,
+            materials,
+            shader: &self.shader,
+            mip_levels: self.draw_mip_levels,
+        };
+        let thread_count = self.thread_count.clamp(1, Self::available_threads());
+        let chunk_size = self.fragment_buffer.len().div_ceil(thread_count).max(1);
+
+        if thread_count <= 1 {
+            for fragment in &mut self.fragment_buffer {
+                fragment.color = stage.shade(fragment);
+            }
+            return;
+        }
+
+        let stage = &stage;
+        std::thread::scope(|scope| {
+            for fragments in self.fragment_buffer.chunks_mut(chunk_size) {
+                scope.spawn(move || {
+                    for fragment in fragments {
+                        fragment.color = stage.shade(fragment);
+                    }
+                });
+            }
+        });
     }
 
     /// Blending Stage
-    fn blend(&mut self, target: &mut RenderTarget) {
-        //nothing to do so far, since transparency is not added yet
+    fn blend(
+        &mut self,
+        target: &mut RenderTarget,
+        scissor: (i32, i32, i32, i32),
+        materials: &MaterialLibrary,
+    ) {
+        // Fragments arrive in draw order per pixel: with sorted transparency opaque
+        // surfaces come first, then transparent ones back to front. Order independent
+        // transparency sorts the per pixel fragment lists by depth here instead.
+        // Either way they are composited with their material's blend mode
         //
         // - Color blending
         // - Final color output
@@ -271,66 +689,146 @@ impl Renderer {
 
         let z_range = z_far - z_near; // Pre-calculate the denominator
 
+        // with MSAA fragments are written into their covered samples and resolved afterwards
+        let multisampled = target.sample_count() > 1;
+
+        let order: Vec<usize> = match self.transparency {
+            TransparencyMode::Sorted => (0..self.fragment_buffer.len()).collect(),
+            TransparencyMode::OrderIndependent => fragment_list_order(
+                &mut self.fragment_buffer,
+                materials,
+                &target.z_buffer,
+                target.framebuffer.get_width(),
+                target.sample_count(),
+            ),
+        };
+
         // Write final color to framebuffer
-        for fragment in &self.fragment_buffer {
+        for fragment in order.into_iter().map(|index| &self.fragment_buffer[index]) {
+            if fragment.coverage == 0 {
+                continue; // transparent fragment hidden by a later opaque surface
+            }
+
             let final_color = if self.draw_z_buffer {
                 let z_norm = (fragment.z - z_near) / z_range;
                 let color_u8 = (z_norm.clamp(0.0, 1.0) * 255.0) as u8;
                 ColorRGB::from_rgb(color_u8, color_u8, color_u8)
             } else {
                 // Standard fragment color calculation
-                ColorRGB::from_rgb(
-                    ColorRGB::f64_to_color_component(fragment.color[0]),
-                    ColorRGB::f64_to_color_component(fragment.color[1]),
-                    ColorRGB::f64_to_color_component(fragment.color[2]),
-                )
+                Self::fragment_color(fragment.color)
+            };
+
+            // debug fragments are composited over the scene
+            let blend_mode = if fragment.material_id == Fragment::UNLIT {
+                BlendMode::Over
+            } else {
+                materials
+                    .get(MaterialHandle::from_index(fragment.material_id))
+                    .blend_mode
             };
 
-            target
-                .framebuffer
-                .set_pixel(fragment.x as usize, fragment.y as usize, final_color);
+            let (x, y) = (fragment.x as usize, fragment.y as usize);
+            let blended = fragment.alpha < 1.0 || blend_mode != BlendMode::Over;
+            match (multisampled, blended) {
+                (true, false) => target.set_samples(x, y, fragment.coverage, final_color),
+                (true, true) => target.blend_samples(
+                    x,
+                    y,
+                    fragment.coverage,
+                    final_color,
+                    fragment.alpha,
+                    blend_mode,
+                ),
+                (false, false) => target.framebuffer.set_pixel(x, y, final_color),
+                (false, true) => {
+                    target
+                        .framebuffer
+                        .blend_pixel(x, y, final_color, fragment.alpha, blend_mode)
+                }
+            }
+        }
+
+        if multisampled {
+            target.resolve_samples(scissor);
         }
     }
 
-    pub fn draw_background_on_framebuffer(&mut self, target: &mut RenderTarget) {
-        target.framebuffer.fill(ColorRGB::from_u32(0x101010));
+    /// Fills the area the view renders into (its viewport clipped by its scissor)
+    pub fn draw_background_on_framebuffer(&mut self, view: &RenderView, target: &mut RenderTarget) {
+        target
+            .framebuffer
+            .fill_rect(view.scissor_bounds(target), ColorRGB::from_u32(0x101010));
     }
 
-    pub fn render_view(&mut self, scene: &Scene, view: &mut RenderView, camera: &Camera) {
+    pub fn render_view(
+        &mut self,
+        scene: &Scene,
+        view: &mut RenderView,
+        target: &mut RenderTarget,
+        camera: &Camera,
+    ) {
         // Get camera matrices once
         self.look_at_matrix = camera.get_look_at_matrix();
         self.projection_matrix = camera.get_projection_matrix();
-        self.viewport_matrix = view.viewport.get_matrix();
+        self.viewport_matrix = view.viewport(target).get_matrix();
         self.frustum_matrix = camera.get_frustum_matrix();
 
         // Create frustum from frustum matrix
         self.view_frustum = Frustum::from_matrix(&self.frustum_matrix);
 
+        // Lighting happens in view space
+        self.view_lights = scene
+            .collect_lights()
+            .iter()
+            .map(|light| PointLight::new_transformed_light(light, self.look_at_matrix))
+            .collect();
+        self.view_vector = camera.direction.normalize();
+
+        self.stats = RenderStats::default();
+
+        // passes only touch the view's viewport clipped by its scissor rectangle
+        let scissor = view.scissor_bounds(target);
+
         // set zbuffer
-        let width = view.target.framebuffer.get_width();
-        let height = view.target.framebuffer.get_height();
-        if view.target.z_buffer.len() != width * height {
-            view.target.z_buffer = vec![f64::INFINITY; width * height];
-        } else {
-            view.target.z_buffer.fill(f64::INFINITY);
+        target.clear_depth_stencil_in(scissor, 0);
+
+        // with MSAA faces may be written to the samples while rasterizing (immediate mode)
+        if target.sample_count() > 1 {
+            target.begin_samples(scissor);
         }
 
         self.process_commands(scene);
-        self.process_vertices(scene, camera);
-        self.rasterize(&mut view.target);
-        self.process_fragments();
-        self.blend(&mut view.target);
-
-        // clear buffer afterwards
+        self.process_vertices(&scene.materials);
+        self.clip_primitives();
+        self.project_to_screen();
+        self.rasterize(target, scissor, &scene.materials);
+        self.process_fragments(&scene.materials);
+        self.blend(target, scissor, &scene.materials);
+        view.stats = self.stats;
+
+        // clear buffer afterwards, memory of a frame with heavy overdraw is given back
+        if self.fragment_buffer.capacity() > 2 * self.fragment_buffer.len() {
+            self.fragment_buffer.shrink_to(self.fragment_buffer.len());
+        }
         self.fragment_buffer.clear();
         self.vertex_buffer.clear();
         self.transformed_vertices.clear();
         self.draw_commands.clear();
     }
 
-    pub fn render_axis(&mut self, _scene: &Scene, view: &mut RenderView, camera: &Camera) {
+    pub fn render_axis(
+        &mut self,
+        _scene: &Scene,
+        view: &RenderView,
+        target: &mut RenderTarget,
+        camera: &Camera,
+    ) {
         let frustum_matrix = camera.get_frustum_matrix();
-        let viewport_matrix = view.viewport.get_matrix();
+        let viewport_matrix = view.viewport(target).get_matrix();
+        let style = self
+            .overlay_styles
+            .axis
+            .scaled(target.get_anti_aliasing().ssaa_factor() as f64);
 
         let origin = Point3D::new(0.0, 0.0, 0.0);
         let x_end = Point3D::new(1.0, 0.0, 0.0); // X axis in red
@@ -344,17 +842,34 @@ impl Renderer {
         ];
 
         for (start, end, color) in axes {
-            let screen_start = Self::project_point(start, &frustum_matrix, &viewport_matrix);
-            let screen_end = Self::project_point(end, &frustum_matrix, &viewport_matrix);
-
-            self.rasterizer
-                .draw_line(screen_start, screen_end, color, &mut view.target);
+            if let Some((screen_start, screen_end)) =
+                Self::project_line(start, end, &frustum_matrix, &viewport_matrix)
+            {
+                self.draw_overlay_line(
+                    screen_start,
+                    screen_end,
+                    color,
+                    style,
+                    view.scissor_bounds(target),
+                    target,
+                );
+            }
         }
     }
 
-    pub fn render_grid(&mut self, _scene: &Scene, view: &mut RenderView, camera: &Camera) {
+    pub fn render_grid(
+        &mut self,
+        _scene: &Scene,
+        view: &RenderView,
+        target: &mut RenderTarget,
+        camera: &Camera,
+    ) {
         let frustum_matrix = camera.get_frustum_matrix();
-        let viewport_matrix = view.viewport.get_matrix();
+        let viewport_matrix = view.viewport(target).get_matrix();
+        let style = self
+            .overlay_styles
+            .grid
+            .scaled(target.get_anti_aliasing().ssaa_factor() as f64);
 
         let line_color = ColorRGB::from_rgb(32, 32, 32);
         let start_dist = 5.0;
@@ -397,17 +912,34 @@ impl Renderer {
         }
 
         for (start, end, color) in axes {
-            let screen_start = Self::project_point(start, &frustum_matrix, &viewport_matrix);
-            let screen_end = Self::project_point(end, &frustum_matrix, &viewport_matrix);
-
-            self.rasterizer
-                .draw_line(screen_start, screen_end, color, &mut view.target);
+            if let Some((screen_start, screen_end)) =
+                Self::project_line(start, end, &frustum_matrix, &viewport_matrix)
+            {
+                self.draw_overlay_line(
+                    screen_start,
+                    screen_end,
+                    color,
+                    style,
+                    view.scissor_bounds(target),
+                    target,
+                );
+            }
         }
     }
 
-    pub fn render_light_vectors(&mut self, scene: &Scene, view: &mut RenderView, camera: &Camera) {
+    pub fn render_light_vectors(
+        &mut self,
+        scene: &Scene,
+        view: &RenderView,
+        target: &mut RenderTarget,
+        camera: &Camera,
+    ) {
         let frustum_matrix = camera.get_frustum_matrix();
-        let viewport_matrix = view.viewport.get_matrix();
+        let viewport_matrix = view.viewport(target).get_matrix();
+        let style = self
+            .overlay_styles
+            .lights
+            .scaled(target.get_anti_aliasing().ssaa_factor() as f64);
 
         let origin = Point3D::new(0.0, 0.0, 0.0);
 
@@ -415,11 +947,153 @@ impl Renderer {
             let start_point = lights.get_position();
             let end_point = origin;
 
-            let screen_start = Self::project_point(start_point, &frustum_matrix, &viewport_matrix);
-            let screen_end = Self::project_point(end_point, &frustum_matrix, &viewport_matrix);
+            if let Some((screen_start, screen_end)) =
+                Self::project_line(start_point, end_point, &frustum_matrix, &viewport_matrix)
+            {
+                self.draw_overlay_line(
+                    screen_start,
+                    screen_end,
+                    ColorRGB::YELLOW,
+                    style,
+                    view.scissor_bounds(target),
+                    target,
+                );
+            }
+        }
+    }
+}
+
+/// Number of vertices handed to a worker at once
+const VERTEX_CHUNK_SIZE: usize = 1024;
+
+/// Per-frame state shared by all vertex processing workers
+struct VertexStage<'a> {
+    look_at_matrix: Mat4x4,
+    projection_matrix: Mat4x4,
+    viewport_matrix: Mat4x4,
+    view_vector: Vector3D,
+    lights: &'a [PointLight],
+    materials: &'a MaterialLibrary,
+    shader: &'a FlatShader,
+    draw_vertex_normals: bool,
+}
 
-            self.rasterizer
-                .draw_line(screen_start, screen_end, ColorRGB::YELLOW, &mut view.target);
+impl VertexStage<'_> {
+    /// Runs model, view, lighting and projection transforms on a chunk of one mesh
+    fn process(
+        &self,
+        draw_command: &DrawCommand,
+        vertices: &mut [Vertex],
+        debug_lines: &mut Vec<[i32; 4]>,
+    ) {
+        let material = self.materials.get(draw_command.material);
+
+        for vertex in vertices {
+            // 1. Model to World transform (Model space -> World space)
+            vertex.transform(draw_command.transform);
+
+            // 2. World to look_at transform (world space -> view/camera space)
+            vertex.transform(self.look_at_matrix);
+
+            vertex.view_position = vertex.position;
+
+            // 3. Lighting calculations (in view space), flat and Phong materials are lit per fragment
+            if material.shading == ShadingMode::Gouraud {
+                vertex.color = self.shader.calc_color(
+                    &vertex.position_to_point(),
+                    &vertex.normal_to_vector(),
+                    &material.vertex_color_factor(vertex.color),
+                    &self.view_vector,
+                    material,
+                    self.lights,
+                );
+            }
+
+            if self.draw_vertex_normals && vertex.has_normal() {
+                let line_len = 0.075;
+
+                let start_point_view: Point3D = vertex.position_to_point();
+
+                let end_point_view: Point3D =
+                    start_point_view + vertex.normal_to_vector() * line_len;
+
+                if let Some((start_screen, end_screen)) = Renderer::project_line(
+                    start_point_view,
+                    end_point_view,
+                    &self.projection_matrix,
+                    &self.viewport_matrix,
+                ) {
+                    debug_lines.push([
+                        start_screen.x as i32,
+                        start_screen.y as i32,
+                        end_screen.x as i32,
+                        end_screen.y as i32,
+                    ]);
+                }
+            }
+
+            // 4. Projection transform (View space -> Clip space)
+            let vertex_pos = self.projection_matrix * vertex.position_to_point();
+            vertex.position = [vertex_pos.x, vertex_pos.y, vertex_pos.z];
+            vertex.w = vertex_pos.w;
+        }
+    }
+}
+
+/// Per-frame state of the fragment shading stage
+struct FragmentStage<'a> {
+    view_vector: Vector3D,
+    lights: &'a [PointLight],
+    materials: &'a MaterialLibrary,
+    shader: &'a FlatShader,
+    mip_levels: bool, // replace texels by the color of their mip level
+}
+
+impl FragmentStage<'_> {
+    /// Lit color of a fragment, the material's base color is tinted by the interpolated
+    /// vertex color and the texture. Fragments of Gouraud materials are already lit and
+    /// only tinted by the texture, debug overlays keep their interpolated color.
+    fn shade(&self, fragment: &Fragment) -> [f64; 3] {
+        if fragment.material_id == Fragment::UNLIT {
+            return fragment.color;
         }
+        let material = self
+            .materials
+            .get(MaterialHandle::from_index(fragment.material_id));
+
+        let color = if material.shading == ShadingMode::Gouraud {
+            fragment.color
+        } else {
+            material.vertex_color_factor(fragment.color)
+        };
+
+        // the texture modulates the vertex color, for Gouraud the already lit color
+        let color_factor = match &material.texture {
+            Some(texture) => {
+                let texel = if self.mip_levels {
+                    Texture::mip_color(texture.lod(fragment.uv_ddx, fragment.uv_ddy))
+                } else {
+                    texture.sample(fragment.uv, fragment.uv_ddx, fragment.uv_ddy)
+                };
+                [0, 1, 2].map(|i| color[i] * texel[i])
+            }
+            None => color,
+        };
+
+        if material.shading == ShadingMode::Gouraud {
+            return color_factor;
+        }
+
+        let [x, y, z] = fragment.view_position;
+        let [nx, ny, nz] = fragment.normal;
+
+        self.shader.calc_color(
+            &Point3D::new(x, y, z),
+            &Vector3D::new(nx, ny, nz).normalize(),
+            &color_factor,
+            &self.view_vector,
+            material,
+            self.lights,
+        )
     }
 }
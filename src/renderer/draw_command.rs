@@ -1,4 +1,6 @@
 use crate::math::Mat4x4;
+use crate::renderer::StencilState;
+use crate::scene::{BoundingSphere, MaterialHandle};
 
 #[derive(Debug)]
 pub struct DrawCommand {
@@ -6,6 +8,8 @@ pub struct DrawCommand {
     pub vertex_count: usize,        // how many vertices are in the mesh
     pub first_triangle_index_offset: usize, // where do the triangle_indices start in the index buffer
     pub triangle_index_count: usize, // how many triangle_indices are there in the mesh (N triangles = N * 3 indices)
-    pub material_id: usize,          // which material does the mesh have
+    pub material: MaterialHandle,    // which material do the triangles have
     pub transform: Mat4x4,           // transformation of the mesh to world coordinates
+    pub bounds: BoundingSphere,      // bounding sphere of the mesh in world coordinates
+    pub stencil: StencilState,       // stencil test and ops used while rasterizing the mesh
 }
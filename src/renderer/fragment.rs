@@ -1,14 +1,25 @@
 pub struct Fragment {
     // Screen position
-    pub x: i32, // screen x coordinate
-    pub y: i32, // screen y coordinate
-    pub z: f64, // depth value for z-buffer
+    pub x: i32,        // screen x coordinate
+    pub y: i32,        // screen y coordinate
+    pub z: f64,        // depth value for z-buffer
+    pub w: f64,        // interpolated clip-space w (1.0 for debug overlays)
+    pub coverage: u32, // bitmask of covered MSAA samples, all bits set if not multisampled
+    pub alpha: f64,    // opacity, < 1.0 for partially covered pixels of anti-aliased lines
 
     // Interpolated vertex attributes
-    pub color: [f64; 3],  // interpolated vertex colors
-    pub normal: [f64; 3], // interpolated normal
-    // pub uv: [f64; 2],            // texture coordinates (if using textures)
+    pub color: [f64; 3],         // interpolated vertex colors
+    pub normal: [f64; 3],        // interpolated normal
+    pub view_position: [f64; 3], // interpolated view-space position
+    pub uv: [f64; 2],            // perspective-correct texture coordinates
+    pub uv_ddx: [f64; 2],        // change of uv one pixel right, shared by the 2x2 quad
+    pub uv_ddy: [f64; 2],        // change of uv one pixel down, shared by the 2x2 quad
 
     // Material info
     pub material_id: usize, // which material to use
 }
+
+impl Fragment {
+    /// Material id of debug overlay fragments, which keep their color in the fragment stage
+    pub const UNLIT: usize = usize::MAX;
+}
@@ -1,5 +1,5 @@
 use crate::math::{Mat4x4, Point3D, Vector3D};
-use crate::scene::Vertex;
+use crate::scene::{BoundingSphere, Vertex};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Plane {
@@ -149,4 +149,13 @@ impl Frustum {
             .iter()
             .all(|vertex| self.point_in_bounds(Point3D::from_array(vertex.position)))
     }
+
+    /// Returns false only if the sphere lies completely outside one of the planes.
+    /// Spheres crossing a corner of the frustum may be kept even though they are
+    /// not visible, which is fine for culling.
+    pub fn sphere_in_bounds(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.distance(sphere.center) <= sphere.radius)
+    }
 }
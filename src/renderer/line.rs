@@ -0,0 +1,221 @@
+/// Shape of the line ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+    Butt,   // ends exactly at the end points
+    Square, // extended by half the width
+    Round,  // half circle around the end points
+}
+
+impl LineCap {
+    pub const ALL: [LineCap; 3] = [LineCap::Butt, LineCap::Square, LineCap::Round];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LineCap::Butt => "Butt",
+            LineCap::Square => "Square",
+            LineCap::Round => "Round",
+        }
+    }
+}
+
+/// How a line is rasterized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineStyle {
+    pub width: f64,         // in pixels
+    pub anti_aliased: bool, // blend edge coverage instead of hard pixel steps
+    pub cap: LineCap,
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        Self::THIN
+    }
+}
+
+impl LineStyle {
+    /// Single pixel Bresenham line
+    pub const THIN: LineStyle = LineStyle {
+        width: 1.0,
+        anti_aliased: false,
+        cap: LineCap::Butt,
+    };
+
+    pub fn new(width: f64, anti_aliased: bool, cap: LineCap) -> Self {
+        Self {
+            width,
+            anti_aliased,
+            cap,
+        }
+    }
+
+    /// Same style with the width multiplied, e.g. to keep lines the same size with SSAA
+    pub fn scaled(&self, factor: f64) -> LineStyle {
+        LineStyle {
+            width: self.width * factor,
+            ..*self
+        }
+    }
+}
+
+/// Line styles of the debug overlays, selectable per overlay
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OverlayStyles {
+    pub grid: LineStyle,
+    pub axis: LineStyle,
+    pub lights: LineStyle,
+    pub wireframe: LineStyle,
+    pub vertex_normals: LineStyle,
+}
+
+/// What happens to parts of a line that are behind geometry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OccludedLines {
+    #[default]
+    Hidden, // discarded
+    Dimmed, // drawn at reduced opacity
+    Dashed, // drawn with gaps
+}
+
+impl OccludedLines {
+    pub const ALL: [OccludedLines; 3] = [
+        OccludedLines::Hidden,
+        OccludedLines::Dimmed,
+        OccludedLines::Dashed,
+    ];
+
+    /// Opacity of occluded segments in `Dimmed` mode
+    const DIM_ALPHA: f64 = 0.25;
+    /// Length of a dash and of the gap after it in `Dashed` mode, in pixels
+    const DASH_LENGTH: f64 = 4.0;
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OccludedLines::Hidden => "Hidden",
+            OccludedLines::Dimmed => "Dimmed",
+            OccludedLines::Dashed => "Dashed",
+        }
+    }
+
+    /// Opacity of an occluded pixel `distance` pixels from the start of its line
+    fn alpha(&self, distance: f64) -> f64 {
+        match self {
+            OccludedLines::Hidden => 0.0,
+            OccludedLines::Dimmed => Self::DIM_ALPHA,
+            OccludedLines::Dashed => {
+                if (distance / Self::DASH_LENGTH) as i64 % 2 == 0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Tests line pixels against a depth buffer filled by the face pass
+pub struct LineDepthTest<'a> {
+    z_buffer: &'a [f64], // one depth value per sample
+    width: usize,
+    height: usize,
+    samples: usize,
+    occluded: OccludedLines,
+    depth_bias: f64,
+}
+
+impl<'a> LineDepthTest<'a> {
+    /// Lines lying on a surface may be this fraction of the view distance behind it
+    /// and still count as visible, so wireframes do not flicker on their own faces
+    pub const DEFAULT_DEPTH_BIAS: f64 = 1e-3;
+
+    /// `depth_bias` works like a polygon offset, as a fraction of the view distance
+    pub fn new(
+        z_buffer: &'a [f64],
+        width: usize,
+        height: usize,
+        occluded: OccludedLines,
+        depth_bias: f64,
+    ) -> Self {
+        Self {
+            z_buffer,
+            width,
+            height,
+            samples: (z_buffer.len() / (width * height).max(1)).max(1),
+            occluded,
+            depth_bias,
+        }
+    }
+
+    /// Opacity of a line pixel after the depth test
+    ///
+    /// ### Arguments
+    ///
+    /// * `x`, `y` - Pixel coordinates
+    /// * `z` - Screen-space depth of the line at the pixel
+    /// * `distance` - Distance from the start of the line in pixels, used for dashes
+    /// * `coverage` - Opacity of the pixel before the depth test
+    ///
+    /// ### Notes
+    ///
+    /// * With MSAA the pixel is split into its visible and occluded samples, so lines
+    ///   fade smoothly along silhouettes
+    pub fn alpha(&self, x: i32, y: i32, z: f64, distance: f64, coverage: f64) -> f64 {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return 0.0;
+        }
+
+        let first_sample = (y as usize * self.width + x as usize) * self.samples;
+        let depths = &self.z_buffer[first_sample..first_sample + self.samples];
+
+        // screen-space depth is 1 - 2 * near / distance away from the near plane,
+        // so a bias proportional to (1 - depth) is proportional to the view distance
+        let visible = depths
+            .iter()
+            .filter(|&&depth| {
+                depth == f64::INFINITY || z <= depth + (1.0 - depth).abs() * self.depth_bias
+            })
+            .count() as f64
+            / self.samples as f64;
+
+        coverage * (visible + (1.0 - visible) * self.occluded.alpha(distance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_test_splits_pixels_into_visible_and_occluded_samples() {
+        // 2x1 target with 4 samples per pixel, the second pixel is half covered by geometry at z = 0
+        const BIAS: f64 = LineDepthTest::DEFAULT_DEPTH_BIAS;
+        let z_buffer = [
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::INFINITY,
+            0.0,
+            0.0,
+            f64::INFINITY,
+            f64::INFINITY,
+        ];
+
+        let hidden = LineDepthTest::new(&z_buffer, 2, 1, OccludedLines::Hidden, BIAS);
+        assert_eq!(hidden.alpha(0, 0, 0.5, 0.0, 1.0), 1.0);
+        assert_eq!(hidden.alpha(1, 0, 0.5, 0.0, 1.0), 0.5);
+        assert_eq!(hidden.alpha(1, 0, -0.5, 0.0, 1.0), 1.0);
+        assert_eq!(hidden.alpha(2, 0, 0.5, 0.0, 1.0), 0.0);
+
+        let dimmed = LineDepthTest::new(&z_buffer, 2, 1, OccludedLines::Dimmed, BIAS);
+        assert_eq!(dimmed.alpha(1, 0, 0.5, 0.0, 1.0), 0.5 + 0.5 * 0.25);
+
+        let dashed = LineDepthTest::new(&z_buffer, 2, 1, OccludedLines::Dashed, BIAS);
+        assert_eq!(dashed.alpha(1, 0, 0.5, 1.0, 1.0), 1.0);
+        assert_eq!(dashed.alpha(1, 0, 0.5, 5.0, 1.0), 0.5);
+
+        // a line slightly behind the surface is only visible with enough bias
+        let unbiased = LineDepthTest::new(&z_buffer, 2, 1, OccludedLines::Hidden, 0.0);
+        let biased = LineDepthTest::new(&z_buffer, 2, 1, OccludedLines::Hidden, 0.01);
+        assert_eq!(unbiased.alpha(1, 0, 0.005, 0.0, 1.0), 0.5);
+        assert_eq!(biased.alpha(1, 0, 0.005, 0.0, 1.0), 1.0);
+    }
+}
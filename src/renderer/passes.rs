@@ -1,20 +1,40 @@
-use crate::math::ScreenPoint;
-use crate::renderer::{DrawCommand, Fragment, Rasterizer};
-use crate::scene::Vertex;
+use crate::renderer::tiling::{TILE_SIZE, TileGrid, intersect_bounds};
+use crate::renderer::{
+    ColorBand, ColorRGB, DrawCommand, Fragment, LineDepthTest, LineStyle, OccludedLines,
+    Rasterizer, RenderStats, ShadingMode,
+};
+use crate::scene::{MaterialLibrary, Vertex};
 
 pub struct RasterizerInput<'a> {
     pub draw_commands: &'a [DrawCommand],
     pub triangle_index_buffer: &'a [u32],
     pub transformed_vertices: &'a [Vertex],
+    pub materials: &'a MaterialLibrary,
     pub backface_culling: bool,
+    pub perspective_correct: bool,
+    pub thread_count: usize, // worker threads for the face pass, 1 = single threaded
+    pub wireframe_style: LineStyle,
+    pub vertex_normal_style: LineStyle,
+    pub depth_test_lines: bool, // test wireframe edges against the z-buffer
+    pub occluded_lines: OccludedLines,
+    pub wireframe_mode: WireframeMode,
+    pub line_depth_bias: f64, // polygon offset of lines against faces, see `LineDepthTest`
+    pub buffer_transparent: bool, // keep transparent fragments for the blend stage in immediate mode
+    pub fragment_shader: &'a (dyn Fn(&Fragment) -> ColorRGB + Sync), // colors immediate-mode fragments
 }
 
 pub struct RasterizerOutput<'a> {
     pub fragment_buffer: &'a mut Vec<Fragment>,
-    pub z_buffer: &'a mut [f64],
+    pub color: Option<ColorBand<'a>>, // set in immediate mode, faces are shaded and written here
+    pub z_buffer: &'a mut [f64],      // one depth value per sample
+    pub stencil_buffer: &'a mut [u8], // one stencil value per sample
+    pub primitive_buffer: &'a mut [u32], // triangle that wrote the depth of each sample
+    pub sample_positions: &'a [[f64; 2]], // sample offsets inside a pixel, one entry per sample
     pub debug_lines: &'a mut Vec<[i32; 4]>,
     pub target_width: usize,
     pub target_height: usize,
+    pub scissor: (i32, i32, i32, i32), // pixels the passes may write, (min_x, min_y, max_x, max_y) with exclusive max
+    pub stats: &'a mut RenderStats,
 }
 
 pub trait RenderPass {
@@ -26,67 +46,106 @@ pub trait RenderPass {
     );
 }
 
-pub struct FacePass;
+/// How the face pass uses the z-buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthMode {
+    Less,      // keep fragments in front of the stored depth and write their depth
+    DepthOnly, // depth pre-pass, writes depth without producing fragments
+    Equal, // after a pre-pass, only the fragments of the triangle that produced the stored depth are kept
+}
 
-impl RenderPass for FacePass {
-    fn execute(
-        &self,
+pub struct FacePass {
+    pub depth_mode: DepthMode,
+}
+
+/// How the wireframe relates to the faces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireframeMode {
+    #[default]
+    Overlay, // all edges on top of the scene, occlusion follows `depth_test_lines`
+    HiddenLine, // faces only fill the z-buffer, edges behind them and of back faces are hidden
+    ShadedWire, // shaded faces with their visible edges on top
+}
+
+impl WireframeMode {
+    pub const ALL: [WireframeMode; 3] = [
+        WireframeMode::Overlay,
+        WireframeMode::HiddenLine,
+        WireframeMode::ShadedWire,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WireframeMode::Overlay => "Overlay",
+            WireframeMode::HiddenLine => "Hidden line",
+            WireframeMode::ShadedWire => "Shaded + wire",
+        }
+    }
+
+    /// Hidden-line and shaded modes always test edges against the faces
+    pub fn depth_tested(&self) -> bool {
+        *self != WireframeMode::Overlay
+    }
+}
+
+/// Rows of the target a face pass worker owns
+struct TargetBand<'b, 'c> {
+    z_buffer: &'b mut [f64],
+    stencil_buffer: &'b mut [u8],
+    primitive_buffer: &'b mut [u32],
+    width: usize,
+    origin_y: i32,                        // target row of the first row in the band
+    color: Option<&'b mut ColorBand<'c>>, // immediate mode
+    fragments: &'b mut Vec<Fragment>,     // buffered mode
+    shaded_fragments: usize,
+}
+
+/// Triangle that passed setup (on screen, not degenerate, not culled)
+#[derive(Debug, Clone, Copy)]
+struct SetupTriangle {
+    draw_command_idx: usize,
+    first_index: usize,           // offset of the triangle in the index buffer
+    bounds: (i32, i32, i32, i32), // clamped screen-space bounding box
+}
+
+impl FacePass {
+    /// Runs triangle setup in submission order and returns all triangles that need rasterization
+    fn setup_triangles(
         rasterizer: &Rasterizer,
         input: &RasterizerInput,
-        output: &mut RasterizerOutput,
-    ) {
+        target_width: usize,
+        target_height: usize,
+        scissor: (i32, i32, i32, i32),
+    ) -> Vec<SetupTriangle> {
+        let mut triangles = Vec::new();
+
         // For each draw command/mesh
-        for draw_command in input.draw_commands {
+        for (draw_command_idx, draw_command) in input.draw_commands.iter().enumerate() {
             let index_start = draw_command.first_triangle_index_offset;
             let index_length = draw_command.triangle_index_count;
             let index_end = index_length + index_start;
 
             // Process indices in groups of 3 to form triangles
             for i in (index_start..index_end).step_by(3) {
-                // Get vertex indices
-                let i0 = input.triangle_index_buffer[i];
-                let i1 = input.triangle_index_buffer[i + 1];
-                let i2 = input.triangle_index_buffer[i + 2];
+                let [v0, v1, v2] = Self::triangle_vertices(input, i);
 
-                // Get transformed vertices
-                let v0: &Vertex = &input.transformed_vertices[i0 as usize];
-                let v1 = &input.transformed_vertices[i1 as usize];
-                let v2 = &input.transformed_vertices[i2 as usize];
-
-                // Check if triangle is partly on screen
-                if !rasterizer.is_triangle_on_screen(
-                    v0,
-                    v1,
-                    v2,
-                    output.target_width,
-                    output.target_height,
-                ) {
+                // create boundingbox from v0, v1, v2, limited to the scissor rectangle
+                let bounds = intersect_bounds(
+                    rasterizer.calculate_bounding_box(v0, v1, v2, target_width, target_height),
+                    scissor,
+                );
+                if bounds.0 >= bounds.2 || bounds.1 >= bounds.3 {
                     continue;
                 }
 
-                let bounds_min_x: i32;
-                let bounds_min_y: i32;
-                let bounds_max_x: i32;
-                let bounds_max_y: i32;
-
-                // create boundingbox from v0, v1, v2
-                (bounds_min_x, bounds_min_y, bounds_max_x, bounds_max_y) = rasterizer
-                    .calculate_bounding_box(v0, v1, v2, output.target_width, output.target_height);
-
-                // 1. PRE-CALCULATION
                 // Create aliases for positions to make math cleaner (p = position)
                 let p0 = &v0.position;
                 let p1 = &v1.position;
                 let p2 = &v2.position;
 
-                // Calculate triangle vectors
-                let v0_to_v1_x = p1[0] - p0[0];
-                let v0_to_v1_y = p1[1] - p0[1];
-                let v0_to_v2_x = p2[0] - p0[0];
-                let v0_to_v2_y = p2[1] - p0[1];
-
                 // Calculate denominator once (cross product Z component)
-                let denominator = v0_to_v1_x * v0_to_v2_y - v0_to_v2_x * v0_to_v1_y;
+                let denominator =
+                    (p1[0] - p0[0]) * (p2[1] - p0[1]) - (p2[0] - p0[0]) * (p1[1] - p0[1]);
 
                 // OPTIMIZATION: Skip degenerate triangles (zero area)
                 if denominator.abs() < f64::EPSILON {
@@ -97,72 +156,467 @@ impl RenderPass for FacePass {
                     continue;
                 }
 
-                // Calculate inverse once to replace division with multiplication
-                let inv_denominator = 1.0 / denominator;
-
-                // For each pixel in triangle's bounding box:
-                // traverse the bounding box in scanline
-                for y in (bounds_min_y)..(bounds_max_y) {
-                    for x in (bounds_min_x)..(bounds_max_x) {
-                        let fx = x as f64;
-                        let fy = y as f64;
-
-                        // Vector from Point to p0
-                        let p_to_v0_x = fx - p0[0];
-                        let p_to_v0_y = fy - p0[1];
-
-                        // Calculate Beta and Gamma using Multiplications
-                        let beta =
-                            (p_to_v0_x * v0_to_v2_y - v0_to_v2_x * p_to_v0_y) * inv_denominator;
-                        let gamma =
-                            (v0_to_v1_x * p_to_v0_y - p_to_v0_x * v0_to_v1_y) * inv_denominator;
-                        let alpha = 1.0 - beta - gamma;
-
-                        // Test if pixel is inside triangle
-                        if (0.0..=1.0).contains(&alpha)
-                            && (0.0..=1.0).contains(&beta)
-                            && (0.0..=1.0).contains(&gamma)
-                        {
-                            // Interpolate Z, color, normal using barycentric
-                            let interpolated_z = alpha * v0.position[2]
-                                + beta * v1.position[2]
-                                + gamma * v2.position[2];
-
-                            let interpolated_color = [
-                                alpha * v0.color[0] + beta * v1.color[0] + gamma * v2.color[0],
-                                alpha * v0.color[1] + beta * v1.color[1] + gamma * v2.color[1],
-                                alpha * v0.color[2] + beta * v1.color[2] + gamma * v2.color[2],
-                            ];
-
-                            let interpolated_normal = [
-                                alpha * v0.normal[0] + beta * v1.normal[0] + gamma * v2.normal[0],
-                                alpha * v0.normal[1] + beta * v1.normal[1] + gamma * v2.normal[1],
-                                alpha * v0.normal[2] + beta * v1.normal[2] + gamma * v2.normal[2],
-                            ];
-
-                            // setup z index to access right place in buffer
-                            let z_buffer_idx = y as usize * output.target_width + x as usize;
-
-                            // Create and store fragment if Z-test passes
-                            // Z-test before creating fragment
-                            if interpolated_z < output.z_buffer[z_buffer_idx] {
-                                // Only if closer than what's in zbuffer at coordinates
-                                output.z_buffer[z_buffer_idx] = interpolated_z; // Update z-buffer
-
-                                output.fragment_buffer.push(Fragment {
-                                    x,
-                                    y,
-                                    z: interpolated_z,
-                                    color: interpolated_color,
-                                    normal: interpolated_normal,
-                                    material_id: draw_command.material_id,
-                                });
+                triangles.push(SetupTriangle {
+                    draw_command_idx,
+                    first_index: i,
+                    bounds,
+                });
+            }
+        }
+
+        triangles
+    }
+
+    fn triangle_vertices<'a>(input: &RasterizerInput<'a>, first_index: usize) -> [&'a Vertex; 3] {
+        let i0 = input.triangle_index_buffer[first_index] as usize;
+        let i1 = input.triangle_index_buffer[first_index + 1] as usize;
+        let i2 = input.triangle_index_buffer[first_index + 2] as usize;
+
+        [
+            &input.transformed_vertices[i0],
+            &input.transformed_vertices[i1],
+            &input.transformed_vertices[i2],
+        ]
+    }
+
+    /// Color, face normal and centroid of a triangle in view space for flat shading.
+    /// The face normal is flipped to the side the vertex normals point to.
+    fn flat_attributes(v0: &Vertex, v1: &Vertex, v2: &Vertex) -> ([f64; 3], [f64; 3], [f64; 3]) {
+        let [p0, p1, p2] = [v0.view_position, v1.view_position, v2.view_position];
+        let edge_1 = [p1[0] - p0[0], p1[1] - p0[1], p1[2] - p0[2]];
+        let edge_2 = [p2[0] - p0[0], p2[1] - p0[1], p2[2] - p0[2]];
+
+        let mut normal = [
+            edge_1[1] * edge_2[2] - edge_1[2] * edge_2[1],
+            edge_1[2] * edge_2[0] - edge_1[0] * edge_2[2],
+            edge_1[0] * edge_2[1] - edge_1[1] * edge_2[0],
+        ];
+
+        let vertex_normal_sum: f64 = (0..3)
+            .map(|i| normal[i] * (v0.normal[i] + v1.normal[i] + v2.normal[i]))
+            .sum();
+        if vertex_normal_sum < 0.0 {
+            normal = normal.map(|n| -n);
+        }
+
+        let average =
+            |a: [f64; 3], b: [f64; 3], c: [f64; 3]| [0, 1, 2].map(|i| (a[i] + b[i] + c[i]) / 3.0);
+
+        (
+            average(v0.color, v1.color, v2.color),
+            normal,
+            average(p0, p1, p2),
+        )
+    }
+
+    /// Texture coordinate derivatives of the 2x2 pixel quad containing `(x, y)`.
+    ///
+    /// Like the coarse derivatives of a GPU, the uv is evaluated at the centers of the
+    /// top-left, top-right and bottom-left pixel of the quad, extrapolated past the
+    /// triangle edges where those pixels are not covered, so all four pixels share them.
+    fn quad_uv_derivatives(
+        [v0, v1, v2]: [&Vertex; 3],
+        (x, y): (i32, i32),
+        weights: [f64; 3],
+        (weights_ddx, weights_ddy): ([f64; 3], [f64; 3]),
+        perspective_correct: bool,
+    ) -> ([f64; 2], [f64; 2]) {
+        let uv_at = |[alpha, beta, gamma]: [f64; 3]| {
+            let (a, b, c) = if perspective_correct {
+                let (a, b, c, _) = Rasterizer::perspective_correct_barycentric(
+                    (alpha, beta, gamma),
+                    (v0.w, v1.w, v2.w),
+                );
+                (a, b, c)
+            } else {
+                (alpha, beta, gamma)
+            };
+            [0, 1].map(|i| a * v0.uv[i] + b * v1.uv[i] + c * v2.uv[i])
+        };
+
+        let (quad_x, quad_y) = ((x & 1) as f64, (y & 1) as f64);
+        let origin =
+            [0, 1, 2].map(|i| weights[i] - weights_ddx[i] * quad_x - weights_ddy[i] * quad_y);
+        let right = [0, 1, 2].map(|i| origin[i] + weights_ddx[i]);
+        let below = [0, 1, 2].map(|i| origin[i] + weights_ddy[i]);
+
+        let (uv, uv_right, uv_below) = (uv_at(origin), uv_at(right), uv_at(below));
+        (
+            [uv_right[0] - uv[0], uv_right[1] - uv[1]],
+            [uv_below[0] - uv[0], uv_below[1] - uv[1]],
+        )
+    }
+
+    /// Rasterizes one triangle inside `bounds` into a band of the target.
+    /// The band starts at row `origin_y` of the target, so tiles only need
+    /// access to the rows they own. Depth is tested per sample, attributes are
+    /// interpolated once per pixel.
+    fn rasterize_triangle(
+        &self,
+        rasterizer: &Rasterizer,
+        input: &RasterizerInput,
+        triangle: &SetupTriangle,
+        bounds: (i32, i32, i32, i32),
+        sample_positions: &[[f64; 2]],
+        band: &mut TargetBand,
+    ) {
+        let [v0, v1, v2] = Self::triangle_vertices(input, triangle.first_index);
+        let draw_command = &input.draw_commands[triangle.draw_command_idx];
+        let material_id = draw_command.material.index();
+        let sample_count = sample_positions.len();
+        let material = input.materials.get(draw_command.material);
+        // coplanar triangles produce the same depth, the equal pass compares the triangle too
+        let primitive = (triangle.first_index / 3) as u32;
+
+        // transparent surfaces are tested against the opaque depth but never write it
+        let transparent = material.is_transparent();
+        if transparent && self.depth_mode == DepthMode::DepthOnly {
+            return;
+        }
+
+        // the pre-pass only tests the stencil, its ops are applied once by the shading pass
+        let stencil = draw_command.stencil;
+        let stencil_enabled = stencil.is_enabled();
+        let stencil_writes = stencil_enabled && self.depth_mode != DepthMode::DepthOnly;
+
+        // flat shaded triangles are lit once, with the face normal at their centroid
+        let flat =
+            (material.shading == ShadingMode::Flat).then(|| Self::flat_attributes(v0, v1, v2));
+
+        // only textured triangles need uv derivatives, they select the mip level
+        let weight_gradients = material.texture.as_ref().and_then(|_| {
+            Rasterizer::barycentric_gradients(
+                [v0.position[0], v0.position[1]],
+                [v1.position[0], v1.position[1]],
+                [v2.position[0], v2.position[1]],
+            )
+        });
+
+        // For each pixel with a sample covered by the triangle (top-left fill rule)
+        rasterizer.for_each_triangle_coverage(
+            [v0.position[0], v0.position[1]],
+            [v1.position[0], v1.position[1]],
+            [v2.position[0], v2.position[1]],
+            bounds,
+            sample_positions,
+            |x, y, coverage, [alpha, beta, gamma], sample_weights| {
+                // setup z index to access right place in buffer
+                let z_buffer_idx =
+                    ((y - band.origin_y) as usize * band.width + x as usize) * sample_count;
+
+                // Stencil- and z-test every covered sample before creating the fragment
+                let mut passed = 0u32;
+                for (sample, [a, b, c]) in sample_weights.iter().enumerate() {
+                    if coverage & (1 << sample) == 0 {
+                        continue;
+                    }
+
+                    let stored_stencil = &mut band.stencil_buffer[z_buffer_idx + sample];
+                    if stencil_enabled && !stencil.test(*stored_stencil) {
+                        if stencil_writes {
+                            *stored_stencil = stencil.update(stencil.fail, *stored_stencil);
+                        }
+                        continue;
+                    }
+
+                    let sample_z = a * v0.position[2] + b * v1.position[2] + c * v2.position[2];
+                    let stored_z = &mut band.z_buffer[z_buffer_idx + sample];
+                    let stored_primitive = &mut band.primitive_buffer[z_buffer_idx + sample];
+
+                    let depth_passed = if transparent {
+                        sample_z < *stored_z
+                    } else {
+                        match self.depth_mode {
+                            // Only if closer than what's in zbuffer at coordinates
+                            DepthMode::Less | DepthMode::DepthOnly => {
+                                let closer = sample_z < *stored_z;
+                                if closer {
+                                    *stored_z = sample_z; // Update z-buffer
+                                    *stored_primitive = primitive;
+                                }
+                                closer
+                            }
+                            // the pre-pass computed the same value for the visible surface
+                            DepthMode::Equal => {
+                                sample_z == *stored_z && primitive == *stored_primitive
                             }
                         }
+                    };
+
+                    if stencil_writes {
+                        let op = if depth_passed {
+                            stencil.pass
+                        } else {
+                            stencil.depth_fail
+                        };
+                        *stored_stencil = stencil.update(op, *stored_stencil);
                     }
+                    if depth_passed {
+                        passed |= 1 << sample;
+                    }
+                }
+
+                if passed == 0 || self.depth_mode == DepthMode::DepthOnly {
+                    return;
+                }
+
+                // Interpolate Z, color, normal using barycentric
+                let interpolated_z =
+                    alpha * v0.position[2] + beta * v1.position[2] + gamma * v2.position[2];
+
+                // Screen-space z is affine, every other varying needs 1/w correction
+                let (a, b, c, interpolated_w) = if input.perspective_correct {
+                    Rasterizer::perspective_correct_barycentric(
+                        (alpha, beta, gamma),
+                        (v0.w, v1.w, v2.w),
+                    )
+                } else {
+                    (
+                        alpha,
+                        beta,
+                        gamma,
+                        alpha * v0.w + beta * v1.w + gamma * v2.w,
+                    )
+                };
+
+                let interpolated_color = [
+                    a * v0.color[0] + b * v1.color[0] + c * v2.color[0],
+                    a * v0.color[1] + b * v1.color[1] + c * v2.color[1],
+                    a * v0.color[2] + b * v1.color[2] + c * v2.color[2],
+                ];
+
+                let interpolated_normal = [
+                    a * v0.normal[0] + b * v1.normal[0] + c * v2.normal[0],
+                    a * v0.normal[1] + b * v1.normal[1] + c * v2.normal[1],
+                    a * v0.normal[2] + b * v1.normal[2] + c * v2.normal[2],
+                ];
+
+                let interpolated_view_position = [
+                    a * v0.view_position[0] + b * v1.view_position[0] + c * v2.view_position[0],
+                    a * v0.view_position[1] + b * v1.view_position[1] + c * v2.view_position[1],
+                    a * v0.view_position[2] + b * v1.view_position[2] + c * v2.view_position[2],
+                ];
+
+                let uv = [
+                    a * v0.uv[0] + b * v1.uv[0] + c * v2.uv[0],
+                    a * v0.uv[1] + b * v1.uv[1] + c * v2.uv[1],
+                ];
+
+                let (uv_ddx, uv_ddy) = weight_gradients.map_or(([0.0; 2], [0.0; 2]), |gradients| {
+                    Self::quad_uv_derivatives(
+                        [v0, v1, v2],
+                        (x, y),
+                        [alpha, beta, gamma],
+                        gradients,
+                        input.perspective_correct,
+                    )
+                });
+
+                let (color, normal, view_position) = flat.unwrap_or((
+                    interpolated_color,
+                    interpolated_normal,
+                    interpolated_view_position,
+                ));
+
+                let fragment = Fragment {
+                    x,
+                    y,
+                    z: interpolated_z,
+                    w: interpolated_w,
+                    coverage: passed,
+                    alpha: material.opacity,
+                    color,
+                    normal,
+                    view_position,
+                    uv,
+                    uv_ddx,
+                    uv_ddy,
+                    material_id,
+                };
+
+                band.shaded_fragments += 1;
+                match &mut band.color {
+                    Some(color) if !(transparent && input.buffer_transparent) => {
+                        let shaded = (input.fragment_shader)(&fragment);
+                        if transparent {
+                            color.blend(
+                                x as usize,
+                                y as usize,
+                                passed,
+                                shaded,
+                                material.opacity,
+                                material.blend_mode,
+                            )
+                        } else {
+                            color.write(x as usize, y as usize, passed, shaded)
+                        }
+                    }
+                    _ => band.fragments.push(fragment),
+                }
+            },
+        );
+    }
+
+    /// Bins triangles into screen tiles and rasterizes rows of tiles in parallel.
+    ///
+    /// Every worker owns whole tile rows, i.e. disjoint bands of the z-buffer and
+    /// color planes, and walks the triangles of each tile in submission order. Per
+    /// pixel the z-tests happen in the same order as in the single threaded path, so
+    /// the result is identical. Fragments are concatenated in tile row order afterwards.
+    fn execute_tiled(
+        &self,
+        rasterizer: &Rasterizer,
+        input: &RasterizerInput,
+        output: &mut RasterizerOutput,
+        triangles: &[SetupTriangle],
+    ) {
+        let width = output.target_width;
+        let grid = TileGrid::new(width, output.target_height);
+
+        // bin triangles by the tiles their bounding box overlaps
+        let mut bins: Vec<Vec<usize>> = vec![Vec::new(); grid.tile_count()];
+        for (triangle_idx, triangle) in triangles.iter().enumerate() {
+            grid.for_each_overlapping_tile(triangle.bounds, |tile_idx| {
+                bins[tile_idx].push(triangle_idx)
+            });
+        }
+
+        // hand out tile rows (z-buffer and color bands) round robin
+        let thread_count = input.thread_count.min(grid.tiles_y).max(1);
+        let mut worker_bands: Vec<Vec<_>> = (0..thread_count).map(|_| Vec::new()).collect();
+        let sample_positions = output.sample_positions;
+        let band_len = width * TILE_SIZE * sample_positions.len();
+        let mut color_bands = output
+            .color
+            .as_mut()
+            .map(|color| color.split_rows(TILE_SIZE).into_iter());
+        let z_bands = output.z_buffer.chunks_mut(band_len);
+        let stencil_bands = output.stencil_buffer.chunks_mut(band_len);
+        let primitive_bands = output.primitive_buffer.chunks_mut(band_len);
+        for (tile_y, ((z_band, stencil_band), primitive_band)) in
+            z_bands.zip(stencil_bands).zip(primitive_bands).enumerate()
+        {
+            let color_band = color_bands.as_mut().and_then(|bands| bands.next());
+            worker_bands[tile_y % thread_count].push((
+                tile_y,
+                z_band,
+                stencil_band,
+                primitive_band,
+                color_band,
+            ));
+        }
+
+        let mut band_fragments: Vec<Vec<Fragment>> =
+            (0..grid.tiles_y).map(|_| Vec::new()).collect();
+        let mut shaded_fragments = 0;
+        let bins = &bins;
+
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = worker_bands
+                .into_iter()
+                .map(|bands| {
+                    scope.spawn(move || {
+                        let mut results = Vec::with_capacity(bands.len());
+
+                        for (tile_y, z_buffer, stencil_buffer, primitive_buffer, mut color) in bands
+                        {
+                            let mut fragments = Vec::new();
+                            let mut band = TargetBand {
+                                z_buffer,
+                                stencil_buffer,
+                                primitive_buffer,
+                                width,
+                                origin_y: (tile_y * TILE_SIZE) as i32,
+                                color: color.as_mut(),
+                                fragments: &mut fragments,
+                                shaded_fragments: 0,
+                            };
+
+                            for tile_x in 0..grid.tiles_x {
+                                let tile_bounds = grid.tile_bounds(tile_x, tile_y);
+
+                                for &triangle_idx in &bins[tile_y * grid.tiles_x + tile_x] {
+                                    let triangle = &triangles[triangle_idx];
+                                    self.rasterize_triangle(
+                                        rasterizer,
+                                        input,
+                                        triangle,
+                                        intersect_bounds(triangle.bounds, tile_bounds),
+                                        sample_positions,
+                                        &mut band,
+                                    );
+                                }
+                            }
+
+                            let shaded = band.shaded_fragments;
+                            results.push((tile_y, fragments, shaded));
+                        }
+
+                        results
+                    })
+                })
+                .collect();
+
+            for worker in workers {
+                for (tile_y, fragments, shaded) in
+                    worker.join().expect("rasterizer worker panicked")
+                {
+                    band_fragments[tile_y] = fragments;
+                    shaded_fragments += shaded;
                 }
             }
+        });
+
+        for fragments in band_fragments {
+            output.fragment_buffer.extend(fragments);
         }
+        output.stats.shaded_fragments += shaded_fragments;
+    }
+}
+
+impl RenderPass for FacePass {
+    fn execute(
+        &self,
+        rasterizer: &Rasterizer,
+        input: &RasterizerInput,
+        output: &mut RasterizerOutput,
+    ) {
+        let triangles = Self::setup_triangles(
+            rasterizer,
+            input,
+            output.target_width,
+            output.target_height,
+            output.scissor,
+        );
+
+        if input.thread_count > 1 && !triangles.is_empty() {
+            self.execute_tiled(rasterizer, input, output, &triangles);
+            return;
+        }
+
+        let mut band = TargetBand {
+            z_buffer: output.z_buffer,
+            stencil_buffer: output.stencil_buffer,
+            primitive_buffer: output.primitive_buffer,
+            width: output.target_width,
+            origin_y: 0,
+            color: output.color.as_mut(),
+            fragments: output.fragment_buffer,
+            shaded_fragments: 0,
+        };
+
+        for triangle in &triangles {
+            self.rasterize_triangle(
+                rasterizer,
+                input,
+                triangle,
+                triangle.bounds,
+                output.sample_positions,
+                &mut band,
+            );
+        }
+
+        output.stats.shaded_fragments += band.shaded_fragments;
     }
 }
 
@@ -171,7 +625,7 @@ pub struct VertexPass;
 impl RenderPass for VertexPass {
     fn execute(
         &self,
-        rasterizer: &Rasterizer,
+        _rasterizer: &Rasterizer,
         input: &RasterizerInput,
         output: &mut RasterizerOutput,
     ) {
@@ -193,31 +647,33 @@ impl RenderPass for VertexPass {
                 let v1 = &input.transformed_vertices[i1 as usize];
                 let v2 = &input.transformed_vertices[i2 as usize];
 
-                // Check if triangle is partly on screen
-                if !rasterizer.is_triangle_on_screen(
-                    v0,
-                    v1,
-                    v2,
-                    output.target_width,
-                    output.target_height,
-                ) {
-                    continue;
-                }
-
                 let fragment_storage = [
                     [v0.position[0] as i32, v0.position[1] as i32],
                     [v1.position[0] as i32, v1.position[1] as i32],
                     [v2.position[0] as i32, v2.position[1] as i32],
                 ];
 
+                let (min_x, min_y, max_x, max_y) = output.scissor;
                 for fragment_chunk in fragment_storage {
+                    let [x, y] = fragment_chunk;
+                    if x < min_x || x >= max_x || y < min_y || y >= max_y {
+                        continue;
+                    }
+
                     output.fragment_buffer.push(Fragment {
                         x: fragment_chunk[0],
                         y: fragment_chunk[1],
                         z: 0.0,
+                        w: 1.0,
+                        coverage: u32::MAX,
+                        alpha: 1.0,
                         color: [1.0, 1.0, 1.0],
                         normal: [0.0, 0.0, 0.0],
-                        material_id: 0,
+                        view_position: [0.0, 0.0, 0.0],
+                        uv: [0.0, 0.0],
+                        uv_ddx: [0.0, 0.0],
+                        uv_ddy: [0.0, 0.0],
+                        material_id: Fragment::UNLIT,
                     });
                 }
             }
@@ -227,6 +683,55 @@ impl RenderPass for VertexPass {
 
 pub struct WireframePass;
 
+impl WireframePass {
+    /// Rasterizes one triangle edge, interpolating its depth for the depth test
+    fn draw_edge(
+        rasterizer: &Rasterizer,
+        input: &RasterizerInput,
+        depth_test: &Option<LineDepthTest>,
+        (v0, v1): (&Vertex, &Vertex),
+        bounds: (i32, i32, i32, i32),
+        fragment_buffer: &mut Vec<Fragment>,
+    ) {
+        let p0 = [v0.position[0], v0.position[1]];
+        let p1 = [v1.position[0], v1.position[1]];
+        let (z0, z1) = (v0.position[2], v1.position[2]);
+        let length = (p1[0] - p0[0]).hypot(p1[1] - p0[1]);
+
+        rasterizer.for_each_line_fragment(
+            p0,
+            p1,
+            input.wireframe_style,
+            bounds,
+            |x, y, coverage, t| {
+                let z = z0 + (z1 - z0) * t;
+                let alpha = match depth_test {
+                    Some(depth_test) => depth_test.alpha(x, y, z, t * length, coverage),
+                    None => coverage,
+                };
+
+                if alpha > 0.0 {
+                    fragment_buffer.push(Fragment {
+                        x,
+                        y,
+                        z,
+                        w: 1.0,
+                        coverage: u32::MAX,
+                        alpha,
+                        color: [1.0, 1.0, 1.0],
+                        normal: [0.0, 0.0, 0.0],
+                        view_position: [0.0, 0.0, 0.0],
+                        uv: [0.0, 0.0],
+                        uv_ddx: [0.0, 0.0],
+                        uv_ddy: [0.0, 0.0],
+                        material_id: Fragment::UNLIT,
+                    });
+                }
+            },
+        );
+    }
+}
+
 impl RenderPass for WireframePass {
     fn execute(
         &self,
@@ -234,7 +739,23 @@ impl RenderPass for WireframePass {
         input: &RasterizerInput,
         output: &mut RasterizerOutput,
     ) {
-        // For each draw command/mesh
+        let bounds = output.scissor;
+
+        // edges are tested against the depth the face pass left behind
+        let depth_tested = input.depth_test_lines || input.wireframe_mode.depth_tested();
+        let depth_test = depth_tested.then(|| {
+            LineDepthTest::new(
+                output.z_buffer,
+                output.target_width,
+                output.target_height,
+                input.occluded_lines,
+                input.line_depth_bias,
+            )
+        });
+
+        // with faces filling the z-buffer the edges of culled back faces are not drawn either
+        let cull_back_faces = input.backface_culling && input.wireframe_mode.depth_tested();
+
         for draw_command in input.draw_commands {
             let index_start = draw_command.first_triangle_index_offset;
             let index_length = draw_command.triangle_index_count;
@@ -252,35 +773,25 @@ impl RenderPass for WireframePass {
                 let v1 = &input.transformed_vertices[i1 as usize];
                 let v2 = &input.transformed_vertices[i2 as usize];
 
-                // Check if triangle is partly on screen
-                if !rasterizer.is_triangle_on_screen(
-                    v0,
-                    v1,
-                    v2,
-                    output.target_width,
-                    output.target_height,
-                ) {
-                    continue;
+                if cull_back_faces {
+                    let [p0, p1, p2] = [v0.position, v1.position, v2.position];
+                    let denominator =
+                        (p1[0] - p0[0]) * (p2[1] - p0[1]) - (p2[0] - p0[0]) * (p1[1] - p0[1]);
+                    if denominator >= 0.0 {
+                        continue;
+                    }
                 }
 
-                let p0 = ScreenPoint::new(v0.position[0] as i32, v0.position[1] as i32);
-                let p1 = ScreenPoint::new(v1.position[0] as i32, v1.position[1] as i32);
-                let p2 = ScreenPoint::new(v2.position[0] as i32, v2.position[1] as i32);
-
-                let mut push_fragment = |x, y| {
-                    output.fragment_buffer.push(Fragment {
-                        x,
-                        y,
-                        z: 0.0,
-                        color: [1.0, 1.0, 1.0],
-                        normal: [0.0, 0.0, 0.0],
-                        material_id: 0,
-                    });
-                };
-
-                rasterizer.for_each_line_point(p0, p1, &mut push_fragment);
-                rasterizer.for_each_line_point(p1, p2, &mut push_fragment);
-                rasterizer.for_each_line_point(p0, p2, &mut push_fragment);
+                for (a, b) in [(v0, v1), (v1, v2), (v0, v2)] {
+                    Self::draw_edge(
+                        rasterizer,
+                        input,
+                        &depth_test,
+                        (a, b),
+                        bounds,
+                        output.fragment_buffer,
+                    );
+                }
             }
         }
     }
@@ -292,22 +803,515 @@ impl RenderPass for VertexNormalPass {
     fn execute(
         &self,
         rasterizer: &Rasterizer,
-        _input: &RasterizerInput,
+        input: &RasterizerInput,
         output: &mut RasterizerOutput,
     ) {
+        let bounds = output.scissor;
+
         for [x1, y1, x2, y2] in output.debug_lines.drain(..) {
-            let p0 = ScreenPoint::new(x1, y1);
-            let p1 = ScreenPoint::new(x2, y2);
-            rasterizer.for_each_line_point(p0, p1, |x, y| {
-                output.fragment_buffer.push(Fragment {
-                    x,
-                    y,
-                    z: 0.0,
-                    color: [1.0, 1.0, 1.0],
-                    normal: [0.0, 0.0, 0.0],
-                    material_id: 0,
-                });
-            });
+            // debug lines hold pixel coordinates, drawn from pixel center to pixel center
+            let p0 = [x1 as f64 + 0.5, y1 as f64 + 0.5];
+            let p1 = [x2 as f64 + 0.5, y2 as f64 + 0.5];
+            rasterizer.for_each_line_coverage(
+                p0,
+                p1,
+                input.vertex_normal_style,
+                bounds,
+                |x, y, alpha| {
+                    output.fragment_buffer.push(Fragment {
+                        x,
+                        y,
+                        z: 0.0,
+                        w: 1.0,
+                        coverage: u32::MAX,
+                        alpha,
+                        color: [1.0, 1.0, 1.0],
+                        normal: [0.0, 0.0, 0.0],
+                        view_position: [0.0, 0.0, 0.0],
+                        uv: [0.0, 0.0],
+                        uv_ddx: [0.0, 0.0],
+                        uv_ddy: [0.0, 0.0],
+                        material_id: Fragment::UNLIT,
+                    });
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Mat4x4;
+    use crate::renderer::{Material, RenderTarget, StencilState};
+    use crate::scene::{BoundingSphere, MaterialHandle};
+
+    const WIDTH: usize = 150;
+    const HEIGHT: usize = 110;
+    const FULL: (i32, i32, i32, i32) = (0, 0, WIDTH as i32, HEIGHT as i32);
+
+    fn vertex(x: f64, y: f64, z: f64, w: f64) -> Vertex {
+        let mut vertex = Vertex::new(
+            [x, y, z],
+            [0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [x / 150.0, y / 110.0, z],
+        );
+        vertex.w = w;
+        vertex
+    }
+
+    fn shade(fragment: &Fragment) -> ColorRGB {
+        let [r, g, b] = fragment.color.map(ColorRGB::f64_to_color_component);
+        ColorRGB::from_rgb(r, g, b)
+    }
+
+    /// Pass settings of a test render
+    #[derive(Clone, Copy)]
+    struct Setup {
+        thread_count: usize,
+        depth_prepass: bool,
+        immediate: bool,
+        scissor: (i32, i32, i32, i32),
+        backface_culling: bool,
+        wireframe_mode: Option<WireframeMode>, // draw the edges over the depth of the faces
+    }
+
+    impl Default for Setup {
+        fn default() -> Self {
+            Self {
+                thread_count: 1,
+                depth_prepass: false,
+                immediate: false,
+                scissor: FULL,
+                backface_culling: false,
+                wireframe_mode: None,
+            }
+        }
+    }
+
+    /// Buffers of a test render
+    struct Rendered {
+        z_buffer: Vec<f64>,
+        image: Vec<u8>,
+        stencil_buffer: Vec<u8>,
+        fragments: Vec<Fragment>,
+        stats: RenderStats,
+    }
+
+    fn draw_command(
+        first_vertex: usize,
+        vertex_count: usize,
+        material: MaterialHandle,
+        stencil: StencilState,
+    ) -> DrawCommand {
+        DrawCommand {
+            first_vertex_offset: first_vertex,
+            vertex_count,
+            first_triangle_index_offset: first_vertex,
+            triangle_index_count: vertex_count,
+            material,
+            transform: Mat4x4::identity(),
+            bounds: BoundingSphere::default(),
+            stencil,
         }
     }
+
+    /// Renders unindexed triangles, buffered fragments are shaded into the image afterwards
+    fn render(
+        vertices: &[Vertex],
+        draw_commands: &[DrawCommand],
+        materials: &MaterialLibrary,
+        setup: Setup,
+    ) -> Rendered {
+        let triangle_index_buffer: Vec<u32> = (0..vertices.len() as u32).collect();
+        let input = RasterizerInput {
+            draw_commands,
+            triangle_index_buffer: &triangle_index_buffer,
+            transformed_vertices: vertices,
+            materials,
+            backface_culling: setup.backface_culling,
+            perspective_correct: true,
+            thread_count: setup.thread_count,
+            wireframe_style: LineStyle::THIN,
+            vertex_normal_style: LineStyle::THIN,
+            depth_test_lines: false,
+            occluded_lines: OccludedLines::Hidden,
+            buffer_transparent: false,
+            wireframe_mode: setup.wireframe_mode.unwrap_or(WireframeMode::Overlay),
+            line_depth_bias: LineDepthTest::DEFAULT_DEPTH_BIAS,
+            fragment_shader: &shade,
+        };
+
+        let mut fragment_buffer = Vec::new();
+        let mut target = RenderTarget::new(WIDTH, HEIGHT);
+        let mut debug_lines = Vec::new();
+        let mut stats = RenderStats::default();
+
+        let (z_buffer, stencil_buffer, primitive_buffer, color) = target.depth_stencil_and_color();
+        let mut output = RasterizerOutput {
+            fragment_buffer: &mut fragment_buffer,
+            color: setup.immediate.then_some(color),
+            z_buffer,
+            stencil_buffer,
+            primitive_buffer,
+            sample_positions: &[[0.5, 0.5]],
+            debug_lines: &mut debug_lines,
+            target_width: WIDTH,
+            target_height: HEIGHT,
+            scissor: setup.scissor,
+            stats: &mut stats,
+        };
+
+        let rasterizer = Rasterizer::new();
+        if setup.depth_prepass || setup.wireframe_mode.is_some() {
+            FacePass {
+                depth_mode: DepthMode::DepthOnly,
+            }
+            .execute(&rasterizer, &input, &mut output);
+        }
+        if setup.wireframe_mode.is_some() {
+            WireframePass.execute(&rasterizer, &input, &mut output);
+        } else {
+            let depth_mode = if setup.depth_prepass {
+                DepthMode::Equal
+            } else {
+                DepthMode::Less
+            };
+            FacePass { depth_mode }.execute(&rasterizer, &input, &mut output);
+        }
+
+        for fragment in &fragment_buffer {
+            let color = shade(fragment);
+            target
+                .framebuffer
+                .set_pixel(fragment.x as usize, fragment.y as usize, color);
+        }
+
+        Rendered {
+            z_buffer: target.z_buffer,
+            image: target.framebuffer.buffer,
+            stencil_buffer: target.stencil_buffer,
+            fragments: fragment_buffer,
+            stats,
+        }
+    }
+
+    /// Renders overlapping triangles
+    fn render_overlapping(
+        thread_count: usize,
+        depth_prepass: bool,
+        immediate: bool,
+        scissor: (i32, i32, i32, i32),
+    ) -> Rendered {
+        let vertices = [
+            vertex(-20.0, 5.0, 0.5, 1.0),
+            vertex(140.0, 30.0, 0.2, 2.0),
+            vertex(30.0, 120.0, 0.9, 3.0),
+            vertex(10.0, 100.0, 0.1, 1.5),
+            vertex(70.0, -10.0, 0.7, 1.0),
+            vertex(160.0, 90.0, 0.4, 4.0),
+            vertex(33.3, 33.3, 0.0, 1.0),
+            vertex(35.1, 80.7, 0.95, 1.0),
+            vertex(99.9, 50.5, 0.05, 1.0),
+        ];
+        let draw_commands = [draw_command(
+            0,
+            vertices.len(),
+            MaterialHandle::from_index(0),
+            StencilState::DISABLED,
+        )];
+        let setup = Setup {
+            thread_count,
+            depth_prepass,
+            immediate,
+            scissor,
+            ..Setup::default()
+        };
+        render(&vertices, &draw_commands, &MaterialLibrary::new(), setup)
+    }
+
+    #[test]
+    fn tiled_rasterization_matches_single_threaded() {
+        let single = render_overlapping(1, false, false, FULL);
+        assert!(single.image.iter().any(|&channel| channel != 0));
+
+        for thread_count in [2, 3, 8] {
+            let tiled = render_overlapping(thread_count, false, false, FULL);
+            assert_eq!(single.z_buffer, tiled.z_buffer);
+            assert_eq!(single.image, tiled.image);
+        }
+    }
+
+    #[test]
+    fn prepass_and_immediate_mode_match_buffered_rendering() {
+        let buffered = render_overlapping(1, false, false, FULL);
+
+        for thread_count in [1, 3] {
+            for (depth_prepass, immediate) in [(true, false), (false, true), (true, true)] {
+                let rendered = render_overlapping(thread_count, depth_prepass, immediate, FULL);
+                assert_eq!(buffered.z_buffer, rendered.z_buffer);
+                assert_eq!(buffered.image, rendered.image);
+
+                if depth_prepass {
+                    // every visible pixel is shaded exactly once
+                    let covered = rendered.z_buffer.iter().filter(|z| z.is_finite()).count();
+                    assert_eq!(rendered.stats.shaded_fragments, covered);
+                    assert!(rendered.stats.shaded_fragments < buffered.stats.shaded_fragments);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn prepass_shades_coplanar_triangles_once() {
+        // the same triangle twice, red first, covering the target
+        let mut vertices = Vec::new();
+        for color in [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]] {
+            for [x, y] in [[0.0, 0.0], [300.0, 0.0], [0.0, 300.0]] {
+                let mut vertex = vertex(x, y, 0.5, 1.0);
+                vertex.color = color;
+                vertices.push(vertex);
+            }
+        }
+        let draw_commands = [draw_command(
+            0,
+            6,
+            MaterialHandle::from_index(0),
+            StencilState::DISABLED,
+        )];
+        let materials = MaterialLibrary::new();
+
+        let less = render(&vertices, &draw_commands, &materials, Setup::default());
+        for thread_count in [1, 3] {
+            let setup = Setup {
+                thread_count,
+                depth_prepass: true,
+                ..Setup::default()
+            };
+            let prepass = render(&vertices, &draw_commands, &materials, setup);
+            assert_eq!(prepass.stats.shaded_fragments, WIDTH * HEIGHT);
+            assert_eq!(prepass.image, less.image);
+            assert_eq!(prepass.image[..3], [255, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn scissor_limits_writes() {
+        let scissor = (20, 10, 90, 70);
+        let full_z = render_overlapping(1, false, false, FULL).z_buffer;
+
+        for (thread_count, immediate) in [(1, false), (3, true)] {
+            let Rendered {
+                z_buffer: z, image, ..
+            } = render_overlapping(thread_count, false, immediate, scissor);
+            for y in 0..HEIGHT as i32 {
+                for x in 0..WIDTH as i32 {
+                    let pixel = y as usize * WIDTH + x as usize;
+                    let inside = (20..90).contains(&x) && (10..70).contains(&y);
+                    if inside {
+                        assert_eq!(z[pixel], full_z[pixel]);
+                    } else {
+                        assert_eq!(z[pixel], f64::INFINITY);
+                        assert_eq!(image[pixel * 4..pixel * 4 + 4], [0; 4]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn close_up_triangle_with_every_vertex_off_screen_fills_the_view() {
+        let vertices = [
+            vertex(-200.0, -200.0, 0.5, 1.0),
+            vertex(600.0, -200.0, 0.5, 1.0),
+            vertex(-200.0, 600.0, 0.5, 1.0),
+        ];
+        let draw_commands = [draw_command(
+            0,
+            3,
+            MaterialHandle::from_index(0),
+            StencilState::DISABLED,
+        )];
+
+        for scissor in [FULL, (20, 10, 90, 70)] {
+            let setup = Setup {
+                scissor,
+                ..Setup::default()
+            };
+            let rendered = render(&vertices, &draw_commands, &MaterialLibrary::new(), setup);
+            let (min_x, min_y, max_x, max_y) = scissor;
+            let covered = ((max_x - min_x) * (max_y - min_y)) as usize;
+            assert_eq!(rendered.fragments.len(), covered);
+            assert_eq!(
+                rendered.z_buffer.iter().filter(|z| z.is_finite()).count(),
+                covered
+            );
+        }
+    }
+
+    #[test]
+    fn flat_normal_faces_the_vertex_normals() {
+        let mut v0 = vertex(0.0, 0.0, 0.0, 1.0);
+        let mut v1 = vertex(1.0, 0.0, 0.0, 1.0);
+        let mut v2 = vertex(0.0, 1.0, 0.0, 1.0);
+        v0.view_position = [0.0, 0.0, -2.0];
+        v1.view_position = [0.0, 3.0, -2.0];
+        v2.view_position = [3.0, 0.0, -2.0];
+
+        // clockwise winding gives -z, the vertex normals point to +z
+        let (color, normal, centroid) = FacePass::flat_attributes(&v0, &v1, &v2);
+        assert!(normal[0] == 0.0 && normal[1] == 0.0 && normal[2] > 0.0);
+        assert_eq!(centroid, [1.0, 1.0, -2.0]);
+        assert_eq!(
+            color,
+            [0, 1, 2].map(|i| (v0.color[i] + v1.color[i] + v2.color[i]) / 3.0)
+        );
+    }
+
+    #[test]
+    fn transparent_faces_blend_without_writing_depth() {
+        // an opaque red triangle behind a half transparent blue one, both covering the target
+        let mut vertices = Vec::new();
+        for (z, color) in [(0.5, [1.0, 0.0, 0.0]), (0.2, [0.0, 0.0, 1.0])] {
+            for [x, y] in [[0.0, 0.0], [300.0, 0.0], [0.0, 300.0]] {
+                let mut vertex = vertex(x, y, z, 1.0);
+                vertex.color = color;
+                vertices.push(vertex);
+            }
+        }
+        let mut materials = MaterialLibrary::new();
+        let opaque = materials.add("opaque", Material::new(0.1, 0.5, 1.0, 50.0));
+        let glass = materials.add(
+            "glass",
+            Material {
+                opacity: 0.5,
+                ..Material::new(0.2, 0.7, 0.4, 20.0)
+            },
+        );
+
+        let draw_commands = [
+            draw_command(0, 3, opaque, StencilState::DISABLED),
+            draw_command(3, 3, glass, StencilState::DISABLED),
+        ];
+
+        for (depth_prepass, immediate) in [(false, true), (true, true), (false, false)] {
+            let setup = Setup {
+                depth_prepass,
+                immediate,
+                ..Setup::default()
+            };
+            let rendered = render(&vertices, &draw_commands, &materials, setup);
+
+            if !immediate {
+                assert_eq!(rendered.fragments.len(), 2 * WIDTH * HEIGHT);
+                assert!(
+                    rendered.fragments[WIDTH * HEIGHT..]
+                        .iter()
+                        .all(|fragment| fragment.alpha == 0.5)
+                );
+                continue;
+            }
+
+            assert!(rendered.z_buffer.iter().all(|&z| (z - 0.5).abs() < 1e-9));
+            let index = (10 * WIDTH + 10) * 4;
+            assert_eq!(rendered.image[index..index + 3], [127, 0, 127]);
+        }
+    }
+
+    #[test]
+    fn stencil_masks_later_draws() {
+        use crate::renderer::{CompareFunction, StencilOp};
+
+        // a near mask writing 1, a layer drawn outside the mask and a layer behind both
+        let mask = StencilState {
+            reference: 1,
+            pass: StencilOp::Replace,
+            ..StencilState::DISABLED
+        };
+        let outside = StencilState {
+            compare: CompareFunction::Equal,
+            fail: StencilOp::Invert,
+            pass: StencilOp::Increment,
+            ..StencilState::DISABLED
+        };
+        let behind = StencilState {
+            depth_fail: StencilOp::Increment,
+            ..StencilState::DISABLED
+        };
+        let layers = [
+            (0.2, 100.0, mask),
+            (0.5, 300.0, outside),
+            (0.7, 300.0, behind),
+        ];
+
+        let mut vertices = Vec::new();
+        let mut draw_commands = Vec::new();
+        for (z, size, stencil) in layers {
+            draw_commands.push(draw_command(
+                vertices.len(),
+                3,
+                MaterialHandle::from_index(0),
+                stencil,
+            ));
+            for [x, y] in [[0.0, 0.0], [size, 0.0], [0.0, size]] {
+                vertices.push(vertex(x, y, z, 1.0));
+            }
+        }
+
+        for thread_count in [1, 3] {
+            let setup = Setup {
+                thread_count,
+                ..Setup::default()
+            };
+            let rendered = render(&vertices, &draw_commands, &MaterialLibrary::new(), setup);
+
+            let inside = 10 * WIDTH + 10;
+            let outside = 100 * WIDTH + 100;
+            // inside: mask replaced 0 with 1, the equal test failed and inverted it,
+            // then the layer behind failed the depth test and incremented it
+            assert_eq!(rendered.stencil_buffer[inside], 255);
+            assert!((rendered.z_buffer[inside] - 0.2).abs() < 1e-9);
+            // outside: the equal test passed and incremented, then the depth fail did
+            assert_eq!(rendered.stencil_buffer[outside], 2);
+            assert!((rendered.z_buffer[outside] - 0.5).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn hidden_line_wireframe_skips_hidden_and_back_facing_edges() {
+        // a front facing triangle in front of a larger back facing one
+        let vertices = [
+            vertex(10.0, 10.0, 0.2, 1.0),
+            vertex(10.0, 90.0, 0.2, 1.0),
+            vertex(120.0, 10.0, 0.2, 1.0),
+            vertex(5.0, 5.0, 0.5, 1.0),
+            vertex(140.0, 5.0, 0.5, 1.0),
+            vertex(5.0, 100.0, 0.5, 1.0),
+        ];
+        let draw_commands = [draw_command(
+            0,
+            6,
+            MaterialHandle::from_index(0),
+            StencilState::DISABLED,
+        )];
+
+        let edge_fragments = |wireframe_mode| {
+            let setup = Setup {
+                backface_culling: true,
+                wireframe_mode: Some(wireframe_mode),
+                ..Setup::default()
+            };
+            render(&vertices, &draw_commands, &MaterialLibrary::new(), setup).fragments
+        };
+
+        let overlay = edge_fragments(WireframeMode::Overlay);
+        let hidden_line = edge_fragments(WireframeMode::HiddenLine);
+
+        // the overlay also draws the back face, hidden-line only the front triangle
+        assert!(overlay.iter().any(|fragment| fragment.x >= 130));
+        assert!(hidden_line.iter().all(|fragment| fragment.x <= 120));
+        assert!(!hidden_line.is_empty() && hidden_line.len() < overlay.len());
+        assert!(hidden_line.iter().all(|fragment| fragment.alpha > 0.0));
+    }
 }
@@ -1,44 +1,289 @@
 use crate::math::ScreenPoint;
-use crate::renderer::{ColorRGB, RenderTarget};
+use crate::renderer::{BlendMode, Clipper, ColorRGB, LineCap, LineStyle, RenderTarget};
 use crate::scene::Vertex;
 
+/// Number of fractional bits used for screen-space vertex positions
+const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL_SCALE: f64 = (1 << SUBPIXEL_BITS) as f64;
+const SUBPIXEL_HALF: i64 = 1 << (SUBPIXEL_BITS - 1);
+
+/// Screen positions are clamped to ±GUARD_BAND pixels before converting them to fixed
+/// point, so the products in the edge functions stay far below `i64::MAX`
+const GUARD_BAND: f64 = (1 << 21) as f64;
+
+/// Maximum number of coverage samples per pixel
+pub const MAX_SAMPLES: usize = 16;
+
+/// Edge function `E(p) = (b - a) x (p - a)` in 24.8 fixed point
+#[derive(Debug, Clone, Copy)]
+struct EdgeFunction {
+    a: [i64; 2],
+    b: [i64; 2],
+    bias: i64, // 0 for top-left edges, -1 otherwise so pixels exactly on the edge are skipped
+}
+
+impl EdgeFunction {
+    /// Edges are expected in clockwise screen order (y pointing down).
+    /// A top edge is horizontal and runs to the right, a left edge runs upwards.
+    fn new(a: [i64; 2], b: [i64; 2]) -> Self {
+        let dx = b[0] - a[0];
+        let dy = b[1] - a[1];
+        let is_top_left = (dy == 0 && dx > 0) || dy < 0;
+
+        Self {
+            a,
+            b,
+            bias: if is_top_left { 0 } else { -1 },
+        }
+    }
+
+    fn evaluate(&self, p: [i64; 2]) -> i64 {
+        (self.b[0] - self.a[0]) * (p[1] - self.a[1]) - (self.b[1] - self.a[1]) * (p[0] - self.a[0])
+    }
+
+    /// Change of the edge function when moving one pixel to the right
+    fn step_x(&self) -> i64 {
+        -(self.b[1] - self.a[1]) << SUBPIXEL_BITS
+    }
+
+    /// Change of the edge function when moving one pixel down
+    fn step_y(&self) -> i64 {
+        (self.b[0] - self.a[0]) << SUBPIXEL_BITS
+    }
+}
+
+/// Edge functions of a triangle brought into clockwise order
+struct TriangleEdges {
+    edges: [EdgeFunction; 3], // edge opposite to each vertex gives that vertex's weight
+    swapped: bool,            // p1 and p2 were swapped, their weights are swapped back
+    inv_area: f64,
+}
+
+impl TriangleEdges {
+    /// Returns `None` for degenerate triangles
+    fn new(p0: [f64; 2], p1: [f64; 2], p2: [f64; 2]) -> Option<Self> {
+        let f0 = Rasterizer::to_fixed(p0);
+        let mut f1 = Rasterizer::to_fixed(p1);
+        let mut f2 = Rasterizer::to_fixed(p2);
+
+        let mut area = EdgeFunction::new(f0, f1).evaluate(f2);
+        if area == 0 {
+            return None;
+        }
+
+        // bring the triangle into clockwise order so inside means E >= 0
+        let swapped = area < 0;
+        if swapped {
+            std::mem::swap(&mut f1, &mut f2);
+            area = -area;
+        }
+
+        Some(Self {
+            edges: [
+                EdgeFunction::new(f1, f2),
+                EdgeFunction::new(f2, f0),
+                EdgeFunction::new(f0, f1),
+            ],
+            swapped,
+            inv_area: 1.0 / area as f64,
+        })
+    }
+
+    fn evaluate(&self, p: [i64; 2]) -> [i64; 3] {
+        self.edges.map(|edge| edge.evaluate(p))
+    }
+
+    fn inside(&self, values: &[i64; 3]) -> bool {
+        (0..3).all(|i| values[i] + self.edges[i].bias >= 0)
+    }
+
+    /// Barycentric weights of `p0`, `p1` and `p2` from the edge function values
+    fn weights(&self, values: &[i64; 3]) -> [f64; 3] {
+        let alpha = values[0] as f64 * self.inv_area;
+        let (beta, gamma) = if self.swapped {
+            (
+                values[2] as f64 * self.inv_area,
+                values[1] as f64 * self.inv_area,
+            )
+        } else {
+            (
+                values[1] as f64 * self.inv_area,
+                values[2] as f64 * self.inv_area,
+            )
+        };
+
+        [alpha, beta, gamma]
+    }
+}
+
+/// Width and height of the pixel blocks tested against a triangle as a whole
+const BLOCK_SIZE: i32 = 8;
+
 pub struct Rasterizer;
 
 impl Rasterizer {
     pub fn new() -> Self {
         Self
     }
-    /// Draws a line between two points using Bresenham's algorithm.
+    /// Draws a line between two points, blending its coverage into the framebuffer.
     ///
     /// ### Arguments
     ///
-    /// * `p0` - Starting point of the line
-    /// * `p1` - Ending point of the line
+    /// * `p0` - Starting point of the line, in screen space with pixel centers at +0.5
+    /// * `p1` - Ending point of the line, in screen space with pixel centers at +0.5
     /// * `color` - Color value to draw the line with
-    /// * `framebuffer` - Frame buffer to draw onto
+    /// * `style` - Width, anti-aliasing and end caps of the line
+    /// * `bounds` - Pixels that may be written, `(min_x, min_y, max_x, max_y)` with exclusive max
+    /// * `target` - Render target to draw onto
     ///
     /// ### Notes
     ///
     /// * Works with all line angles (horizontal, vertical, shallow, steep)
+    /// * The line is clipped to the bounds, so off-screen end points cost nothing
     pub fn draw_line(
         &self,
-        p0: ScreenPoint,
-        p1: ScreenPoint,
+        p0: [f64; 2],
+        p1: [f64; 2],
         color: ColorRGB,
+        style: LineStyle,
+        bounds: (i32, i32, i32, i32),
         target: &mut RenderTarget,
     ) {
-        Self::for_each_line_point_impl(p0, p1, |x, y| {
-            target.framebuffer.set_pixel(x as usize, y as usize, color);
+        self.for_each_line_coverage(p0, p1, style, bounds, |x, y, coverage| {
+            target.framebuffer.blend_pixel(
+                x as usize,
+                y as usize,
+                color,
+                coverage,
+                BlendMode::Over,
+            );
+        });
+    }
+
+    /// Evaluates all pixels touched by a styled line, invoking a closure with the
+    /// pixel coordinates and the covered fraction of the pixel (0.0 - 1.0].
+    ///
+    /// ### Arguments
+    ///
+    /// * `p0`, `p1` - End points in screen space, pixel centers are at +0.5 like for triangles
+    /// * `bounds` - Pixel rectangle `(min_x, min_y, max_x, max_y)` to draw into, max exclusive
+    ///
+    /// ### Notes
+    ///
+    /// * Thin aliased lines use Bresenham, thin anti-aliased lines Xiaolin Wu's algorithm
+    /// * Wider lines are rasterized as rectangles or capsules around the segment,
+    ///   only visiting pixels within reach of the line
+    /// * The segment is clipped to `bounds` first, widened by the reach of the line,
+    ///   so the cost only depends on its visible length
+    pub fn for_each_line_coverage<F>(
+        &self,
+        p0: [f64; 2],
+        p1: [f64; 2],
+        style: LineStyle,
+        bounds: (i32, i32, i32, i32),
+        mut f: F,
+    ) where
+        F: FnMut(i32, i32, f64),
+    {
+        let Some((start, end)) = Self::clip_line(p0, p1, style.width / 2.0 + 1.0, bounds) else {
+            return;
+        };
+
+        let (min_x, min_y, max_x, max_y) = bounds;
+        let mut f = |x: i32, y: i32, coverage: f64| {
+            if x >= min_x && x < max_x && y >= min_y && y < max_y {
+                f(x, y, coverage);
+            }
+        };
+
+        if style.width <= 1.0 {
+            if style.anti_aliased {
+                Self::for_each_wu_line_point(start, end, f);
+            } else {
+                let round =
+                    |p: [f64; 2]| ScreenPoint::new(p[0].round() as i32, p[1].round() as i32);
+                Self::for_each_line_point_impl(round(start), round(end), |x, y| f(x, y, 1.0));
+            }
+            return;
+        }
+
+        Self::for_each_thick_line_point(start, end, style, f);
+    }
+
+    /// Same as `for_each_line_coverage`, additionally passing the position of every
+    /// pixel along the line, from 0.0 at `p0` to 1.0 at `p1`.
+    ///
+    /// ### Notes
+    ///
+    /// * Screen-space depth is linear along a line, so it can be interpolated with it
+    pub fn for_each_line_fragment<F>(
+        &self,
+        p0: [f64; 2],
+        p1: [f64; 2],
+        style: LineStyle,
+        bounds: (i32, i32, i32, i32),
+        mut f: F,
+    ) where
+        F: FnMut(i32, i32, f64, f64),
+    {
+        let dx = p1[0] - p0[0];
+        let dy = p1[1] - p0[1];
+        let length_squared = dx * dx + dy * dy;
+
+        self.for_each_line_coverage(p0, p1, style, bounds, |x, y, coverage| {
+            // measured from the pixel center
+            let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+            let t = if length_squared > 0.0 {
+                (((px - p0[0]) * dx + (py - p0[1]) * dy) / length_squared).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            f(x, y, coverage, t);
         });
     }
 
     /// Evaluates all screen-space points along a line using Bresenham's algorithm,
     /// invoking a closure for each pixel to avoid heap allocations.
-    pub fn for_each_line_point<F>(&self, p0: ScreenPoint, p1: ScreenPoint, f: F)
-    where
+    /// Only points inside `bounds` `(min_x, min_y, max_x, max_y)`, max exclusive, are visited.
+    pub fn for_each_line_point<F>(
+        &self,
+        p0: ScreenPoint,
+        p1: ScreenPoint,
+        bounds: (i32, i32, i32, i32),
+        mut f: F,
+    ) where
         F: FnMut(i32, i32),
     {
-        Self::for_each_line_point_impl(p0, p1, f);
+        let center = |p: ScreenPoint| [p.x as f64 + 0.5, p.y as f64 + 0.5];
+        self.for_each_line_coverage(
+            center(p0),
+            center(p1),
+            LineStyle::THIN,
+            bounds,
+            |x, y, _| f(x, y),
+        );
+    }
+
+    /// Clips a line to `bounds` widened by `margin` pixels on every side.
+    /// The clipped points are moved so pixel centers lie on whole numbers,
+    /// which is what the line algorithms walk along.
+    fn clip_line(
+        p0: [f64; 2],
+        p1: [f64; 2],
+        margin: f64,
+        bounds: (i32, i32, i32, i32),
+    ) -> Option<([f64; 2], [f64; 2])> {
+        let (min_x, min_y, max_x, max_y) = bounds;
+        if min_x >= max_x || min_y >= max_y {
+            return None;
+        }
+
+        Clipper::clip_line_to_rect(
+            [p0[0] - 0.5, p0[1] - 0.5],
+            [p1[0] - 0.5, p1[1] - 0.5],
+            [min_x as f64 - margin, min_y as f64 - margin],
+            [(max_x - 1) as f64 + margin, (max_y - 1) as f64 + margin],
+        )
     }
 
     fn for_each_line_point_impl<F>(p0: ScreenPoint, p1: ScreenPoint, mut f: F)
@@ -71,6 +316,134 @@ impl Rasterizer {
         }
     }
 
+    /// Xiaolin Wu's line algorithm, splits the coverage of every step between
+    /// the two pixels closest to the ideal line
+    fn for_each_wu_line_point<F>(p0: [f64; 2], p1: [f64; 2], mut f: F)
+    where
+        F: FnMut(i32, i32, f64),
+    {
+        let [mut x0, mut y0] = p0;
+        let [mut x1, mut y1] = p1;
+
+        // walk along the major axis
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+        if steep {
+            std::mem::swap(&mut x0, &mut y0);
+            std::mem::swap(&mut x1, &mut y1);
+        }
+        if x0 > x1 {
+            std::mem::swap(&mut x0, &mut x1);
+            std::mem::swap(&mut y0, &mut y1);
+        }
+
+        let dx = x1 - x0;
+        let gradient = if dx == 0.0 { 0.0 } else { (y1 - y0) / dx };
+
+        let mut plot = |major: i32, minor: i32, coverage: f64| {
+            if coverage <= 0.0 {
+                return;
+            }
+            if steep {
+                f(minor, major, coverage);
+            } else {
+                f(major, minor, coverage);
+            }
+        };
+
+        for major in x0 as i32..=x1 as i32 {
+            let minor = y0 + gradient * (major as f64 - x0);
+            let minor_floor = minor.floor();
+            let fraction = minor - minor_floor;
+
+            plot(major, minor_floor as i32, 1.0 - fraction);
+            plot(major, minor_floor as i32 + 1, fraction);
+        }
+    }
+
+    /// Rasterizes a line wider than one pixel by evaluating the distance of pixel
+    /// centers to the segment. Walks the major axis and only scans the span of the
+    /// minor axis the line can reach, so the cost grows with length * width.
+    fn for_each_thick_line_point<F>(a: [f64; 2], b: [f64; 2], style: LineStyle, mut f: F)
+    where
+        F: FnMut(i32, i32, f64),
+    {
+        let steep = (b[1] - a[1]).abs() > (b[0] - a[0]).abs();
+
+        // (major, minor) coordinates with the major axis increasing from start to end
+        let to_axes = |p: [f64; 2]| if steep { [p[1], p[0]] } else { p };
+        let (mut start, mut end) = (to_axes(a), to_axes(b));
+        if start[0] > end[0] {
+            std::mem::swap(&mut start, &mut end);
+        }
+
+        let half_width = style.width / 2.0;
+        let reach = half_width + 1.0; // includes the anti-aliased fringe
+        let major_length = end[0] - start[0];
+        let slope = if major_length > 0.0 {
+            (end[1] - start[1]) / major_length
+        } else {
+            0.0
+        };
+        let minor_reach = reach * (1.0 + slope * slope).sqrt() + 1.0;
+
+        let length = ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt();
+        let direction = if length > 0.0 {
+            [(b[0] - a[0]) / length, (b[1] - a[1]) / length]
+        } else {
+            [1.0, 0.0]
+        };
+
+        let first_major = (start[0] - reach).floor() as i32;
+        let last_major = (end[0] + reach).ceil() as i32;
+
+        for major in first_major..=last_major {
+            let major_on_line = (major as f64).clamp(start[0], end[0]);
+            let minor_center = start[1] + slope * (major_on_line - start[0]);
+
+            let first_minor = (minor_center - minor_reach).floor() as i32;
+            let last_minor = (minor_center + minor_reach).ceil() as i32;
+
+            for minor in first_minor..=last_minor {
+                let (x, y) = if steep {
+                    (minor, major)
+                } else {
+                    (major, minor)
+                };
+
+                // distance of the pixel to the line, along and across it
+                let offset = [x as f64 - a[0], y as f64 - a[1]];
+                let along = offset[0] * direction[0] + offset[1] * direction[1];
+                let across = (offset[0] * direction[1] - offset[1] * direction[0]).abs();
+
+                // signed distance to the outline, positive inside
+                let inside = match style.cap {
+                    LineCap::Butt => (half_width - across).min(along).min(length - along),
+                    LineCap::Square => (half_width - across)
+                        .min(along + half_width)
+                        .min(length + half_width - along),
+                    LineCap::Round => {
+                        let along_clamped = along.clamp(0.0, length);
+                        let dx = offset[0] - direction[0] * along_clamped;
+                        let dy = offset[1] - direction[1] * along_clamped;
+                        half_width - (dx * dx + dy * dy).sqrt()
+                    }
+                };
+
+                let coverage = if style.anti_aliased {
+                    (inside + 0.5).clamp(0.0, 1.0)
+                } else if inside >= 0.0 {
+                    1.0
+                } else {
+                    0.0
+                };
+
+                if coverage > 0.0 {
+                    f(x, y, coverage);
+                }
+            }
+        }
+    }
+
     pub fn calculate_bounding_box(
         &self,
         v0: &Vertex,
@@ -109,28 +482,6 @@ impl Rasterizer {
         (bounds_min_x, bounds_min_y, bounds_max_x, bounds_max_y)
     }
 
-    /// takes 3 Points and checks of all of them are on screen
-    ///
-    ///
-    pub fn is_triangle_on_screen(
-        &self,
-        v0: &Vertex,
-        v1: &Vertex,
-        v2: &Vertex,
-        target_width: usize,
-        target_height: usize,
-    ) -> bool {
-        // this returns true when one of the vertices is on screen
-        // and false if all are off
-        // cool effect if changed to &&. it then only draws if ALL of them are on screen
-        let is_in_bounds = |x: f32, y: f32| {
-            x >= 0.0 && x < target_width as f32 && y >= 0.0 && y < target_height as f32
-        };
-        is_in_bounds(v0.position[0] as f32, v0.position[1] as f32)
-            || is_in_bounds(v1.position[0] as f32, v1.position[1] as f32)
-            || is_in_bounds(v2.position[0] as f32, v2.position[1] as f32)
-    }
-
     pub fn calculate_barycentric(
         x: f32,
         y: f32,
@@ -155,4 +506,719 @@ impl Rasterizer {
 
         (alpha, beta, gamma)
     }
+
+    /// Converts a screen-space position into 24.8 fixed point.
+    /// Positions past the guard band are moved onto it, only triangles reaching millions
+    /// of pixels off-screen change shape.
+    fn to_fixed(position: [f64; 2]) -> [i64; 2] {
+        position.map(|value| (value.clamp(-GUARD_BAND, GUARD_BAND) * SUBPIXEL_SCALE).round() as i64)
+    }
+
+    /// Evaluates all pixels covered by a triangle, invoking a closure with the
+    /// pixel coordinates and the barycentric weights of `p0`, `p1` and `p2`.
+    ///
+    /// ### Arguments
+    ///
+    /// * `p0`, `p1`, `p2` - Screen-space positions of the triangle, either winding
+    /// * `bounds` - Pixel rectangle `(min_x, min_y, max_x, max_y)` to scan, max exclusive
+    /// * `f` - Called as `f(x, y, alpha, beta, gamma)` for every covered pixel
+    ///
+    /// ### Notes
+    ///
+    /// * Coverage is sampled at pixel centers with sub-pixel precise fixed-point edge functions
+    /// * Pixels on shared edges follow the top-left rule, so adjacent triangles never
+    ///   shade a pixel twice and never leave gaps
+    pub fn for_each_triangle_pixel<F>(
+        &self,
+        p0: [f64; 2],
+        p1: [f64; 2],
+        p2: [f64; 2],
+        bounds: (i32, i32, i32, i32),
+        mut f: F,
+    ) where
+        F: FnMut(i32, i32, f64, f64, f64),
+    {
+        self.for_each_triangle_coverage(
+            p0,
+            p1,
+            p2,
+            bounds,
+            &[[0.5, 0.5]],
+            |x, y, _, [alpha, beta, gamma], _| f(x, y, alpha, beta, gamma),
+        );
+    }
+
+    /// Multisampled version of `for_each_triangle_pixel`.
+    ///
+    /// ### Arguments
+    ///
+    /// * `sample_positions` - Sample offsets inside the pixel in the range 0..1 (at most `MAX_SAMPLES`)
+    /// * `f` - Called as `f(x, y, coverage, center_weights, sample_weights)` for every pixel
+    ///   with at least one covered sample. Bit `i` of `coverage` is set if sample `i` is inside,
+    ///   `center_weights` are the barycentrics at the pixel center (possibly outside the
+    ///   triangle) and `sample_weights[i]` the barycentrics of sample `i`
+    ///
+    /// ### Notes
+    ///
+    /// * `bounds` is walked in 8x8 pixel blocks. Blocks completely outside an edge are
+    ///   skipped, blocks completely inside all edges skip the per-sample inside tests
+    /// * Edge functions are stepped incrementally inside a block instead of being
+    ///   evaluated for every pixel
+    pub fn for_each_triangle_coverage<F>(
+        &self,
+        p0: [f64; 2],
+        p1: [f64; 2],
+        p2: [f64; 2],
+        bounds: (i32, i32, i32, i32),
+        sample_positions: &[[f64; 2]],
+        mut f: F,
+    ) where
+        F: FnMut(i32, i32, u32, [f64; 3], &[[f64; 3]]),
+    {
+        let Some(triangle) = TriangleEdges::new(p0, p1, p2) else {
+            return;
+        };
+
+        // sample offsets followed by the pixel center, which is tracked like an extra sample
+        let sample_count = sample_positions.len().min(MAX_SAMPLES);
+        let mut offsets = [[0i64; 2]; MAX_SAMPLES + 1];
+        for (offset, position) in offsets.iter_mut().zip(&sample_positions[..sample_count]) {
+            *offset = Self::to_fixed(*position);
+        }
+        offsets[sample_count] = [SUBPIXEL_HALF, SUBPIXEL_HALF];
+        let tracked = sample_count + 1;
+        let full_coverage = ((1u64 << sample_count) - 1) as u32;
+
+        let step_x = triangle.edges.map(|edge| edge.step_x());
+        let step_y = triangle.edges.map(|edge| edge.step_y());
+
+        let mut sample_weights = [[0.0; 3]; MAX_SAMPLES];
+        let (min_x, min_y, max_x, max_y) = bounds;
+
+        for block_y in (min_y..max_y).step_by(BLOCK_SIZE as usize) {
+            let block_max_y = (block_y + BLOCK_SIZE).min(max_y);
+
+            'block: for block_x in (min_x..max_x).step_by(BLOCK_SIZE as usize) {
+                let block_max_x = (block_x + BLOCK_SIZE).min(max_x);
+
+                // every sample of the block lies in this fixed-point rectangle, the edge
+                // functions are linear, so their extremes over it are at the corners
+                let (left, top) = (
+                    (block_x as i64) << SUBPIXEL_BITS,
+                    (block_y as i64) << SUBPIXEL_BITS,
+                );
+                let (right, bottom) = (
+                    (block_max_x as i64) << SUBPIXEL_BITS,
+                    (block_max_y as i64) << SUBPIXEL_BITS,
+                );
+
+                let mut fully_covered = true;
+                for edge in &triangle.edges {
+                    let corners = [[left, top], [right, top], [left, bottom], [right, bottom]]
+                        .map(|corner| edge.evaluate(corner) + edge.bias);
+
+                    if corners.iter().all(|&value| value < 0) {
+                        continue 'block; // trivial reject
+                    }
+                    if corners.iter().any(|&value| value < 0) {
+                        fully_covered = false;
+                    }
+                }
+
+                // edge values of every tracked sample in the top-left pixel of the block
+                let mut row = [[0i64; 3]; MAX_SAMPLES + 1];
+                for (values, offset) in row.iter_mut().zip(&offsets).take(tracked) {
+                    *values = triangle.evaluate([left + offset[0], top + offset[1]]);
+                }
+
+                for y in block_y..block_max_y {
+                    let mut values = row;
+
+                    for x in block_x..block_max_x {
+                        let coverage = if fully_covered {
+                            full_coverage
+                        } else {
+                            (0..sample_count)
+                                .filter(|&sample| triangle.inside(&values[sample]))
+                                .fold(0, |coverage, sample| coverage | 1 << sample)
+                        };
+
+                        if coverage != 0 {
+                            for sample in 0..sample_count {
+                                if coverage & (1 << sample) != 0 {
+                                    sample_weights[sample] = triangle.weights(&values[sample]);
+                                }
+                            }
+
+                            // attributes are shaded once per pixel at its center
+                            f(
+                                x,
+                                y,
+                                coverage,
+                                triangle.weights(&values[sample_count]),
+                                &sample_weights[..sample_count],
+                            );
+                        }
+
+                        for sample_values in &mut values[..tracked] {
+                            for edge in 0..3 {
+                                sample_values[edge] += step_x[edge];
+                            }
+                        }
+                    }
+
+                    for row_values in &mut row[..tracked] {
+                        for edge in 0..3 {
+                            row_values[edge] += step_y[edge];
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reference traversal evaluating the edge functions from scratch for every pixel
+    /// of the bounding box, used to verify and benchmark `for_each_triangle_coverage`
+    #[cfg(test)]
+    fn for_each_triangle_coverage_scan<F>(
+        p0: [f64; 2],
+        p1: [f64; 2],
+        p2: [f64; 2],
+        bounds: (i32, i32, i32, i32),
+        sample_positions: &[[f64; 2]],
+        mut f: F,
+    ) where
+        F: FnMut(i32, i32, u32, [f64; 3], &[[f64; 3]]),
+    {
+        let Some(triangle) = TriangleEdges::new(p0, p1, p2) else {
+            return;
+        };
+
+        let sample_count = sample_positions.len().min(MAX_SAMPLES);
+        let mut sample_weights = [[0.0; 3]; MAX_SAMPLES];
+        let (min_x, min_y, max_x, max_y) = bounds;
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let pixel = [(x as i64) << SUBPIXEL_BITS, (y as i64) << SUBPIXEL_BITS];
+
+                let mut coverage = 0u32;
+                for (sample, position) in sample_positions[..sample_count].iter().enumerate() {
+                    let offset = Self::to_fixed(*position);
+                    let values = triangle.evaluate([pixel[0] + offset[0], pixel[1] + offset[1]]);
+                    if triangle.inside(&values) {
+                        coverage |= 1 << sample;
+                        sample_weights[sample] = triangle.weights(&values);
+                    }
+                }
+
+                if coverage == 0 {
+                    continue;
+                }
+
+                let center =
+                    triangle.evaluate([pixel[0] + SUBPIXEL_HALF, pixel[1] + SUBPIXEL_HALF]);
+                f(
+                    x,
+                    y,
+                    coverage,
+                    triangle.weights(&center),
+                    &sample_weights[..sample_count],
+                );
+            }
+        }
+    }
+
+    /// Change of the screen-space barycentrics per pixel in x and in y.
+    ///
+    /// Barycentrics are affine in screen space, so the gradients are constant over the
+    /// triangle and let attributes be evaluated at neighboring pixels, e.g. the other
+    /// pixels of a 2x2 quad. `None` for degenerate triangles.
+    pub fn barycentric_gradients(
+        p0: [f64; 2],
+        p1: [f64; 2],
+        p2: [f64; 2],
+    ) -> Option<([f64; 3], [f64; 3])> {
+        let area = (p2[0] - p1[0]) * (p0[1] - p1[1]) - (p2[1] - p1[1]) * (p0[0] - p1[0]);
+        if area == 0.0 {
+            return None;
+        }
+
+        let ddx = [p1[1] - p2[1], p2[1] - p0[1], p0[1] - p1[1]].map(|d| d / area);
+        let ddy = [p2[0] - p1[0], p0[0] - p2[0], p1[0] - p0[0]].map(|d| d / area);
+        Some((ddx, ddy))
+    }
+
+    /// Turns screen-space barycentrics into perspective-correct weights.
+    ///
+    /// Vertex attributes are linear in clip space, not in screen space, so each
+    /// weight is divided by the clip-space w of its vertex and renormalized.
+    ///
+    /// ### Returns
+    ///
+    /// * The corrected weights and the interpolated clip-space w as `(alpha, beta, gamma, w)`
+    pub fn perspective_correct_barycentric(
+        (alpha, beta, gamma): (f64, f64, f64),
+        (w0, w1, w2): (f64, f64, f64),
+    ) -> (f64, f64, f64, f64) {
+        let alpha_over_w = alpha / w0;
+        let beta_over_w = beta / w1;
+        let gamma_over_w = gamma / w2;
+
+        let one_over_w = alpha_over_w + beta_over_w + gamma_over_w;
+        let w = 1.0 / one_over_w;
+
+        (alpha_over_w * w, beta_over_w * w, gamma_over_w * w, w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 64;
+    const HEIGHT: usize = 48;
+
+    fn coverage(triangles: &[[[f64; 2]; 3]]) -> Vec<u32> {
+        let rasterizer = Rasterizer::new();
+        let mut hits = vec![0; WIDTH * HEIGHT];
+
+        for [p0, p1, p2] in triangles {
+            rasterizer.for_each_triangle_pixel(
+                *p0,
+                *p1,
+                *p2,
+                (0, 0, WIDTH as i32, HEIGHT as i32),
+                |x, y, _, _, _| hits[y as usize * WIDTH + x as usize] += 1,
+            );
+        }
+
+        hits
+    }
+
+    #[test]
+    fn triangulated_quad_covers_every_pixel_once() {
+        let (w, h) = (WIDTH as f64, HEIGHT as f64);
+        let quad = [
+            [[0.0, 0.0], [w, 0.0], [w, h]],
+            [[0.0, 0.0], [w, h], [0.0, h]],
+        ];
+
+        assert!(coverage(&quad).iter().all(|&hits| hits == 1));
+    }
+
+    #[test]
+    fn triangle_fan_with_subpixel_center_is_watertight() {
+        let (w, h) = (WIDTH as f64, HEIGHT as f64);
+        let center = [23.37, 17.81];
+        let rim = [
+            [0.0, 0.0],
+            [w / 2.0, 0.0],
+            [w, 0.0],
+            [w, h / 2.0 + 0.5],
+            [w, h],
+            [w / 3.0, h],
+            [0.0, h],
+            [0.0, h / 2.0],
+        ];
+
+        // mix both windings to make sure the fill rule does not depend on it
+        let fan: Vec<[[f64; 2]; 3]> = (0..rim.len())
+            .map(|i| {
+                let a = rim[i];
+                let b = rim[(i + 1) % rim.len()];
+                if i % 2 == 0 {
+                    [center, a, b]
+                } else {
+                    [center, b, a]
+                }
+            })
+            .collect();
+
+        assert!(coverage(&fan).iter().all(|&hits| hits == 1));
+    }
+
+    #[test]
+    fn quad_with_vertices_far_off_screen_covers_every_pixel_once() {
+        let far = 1e12;
+        let quad = [
+            [[-far, -far], [far, -far], [far, far]],
+            [[-far, -far], [far, far], [-far, far]],
+        ];
+
+        assert!(coverage(&quad).iter().all(|&hits| hits == 1));
+    }
+
+    #[test]
+    fn perspective_correct_weights_sum_to_one() {
+        for screen in [(1.0, 0.0, 0.0), (0.2, 0.3, 0.5), (0.6, 0.1, 0.3)] {
+            let (alpha, beta, gamma, w) =
+                Rasterizer::perspective_correct_barycentric(screen, (1.5, 4.0, 0.25));
+            assert!((alpha + beta + gamma - 1.0).abs() < 1e-12);
+            assert!(w > 0.25 && w <= 4.0);
+        }
+    }
+
+    #[test]
+    fn perspective_correction_with_equal_w_is_affine() {
+        let screen = (0.2, 0.3, 0.5);
+        let (alpha, beta, gamma, w) =
+            Rasterizer::perspective_correct_barycentric(screen, (2.0, 2.0, 2.0));
+        assert!((alpha - 0.2).abs() < 1e-12);
+        assert!((beta - 0.3).abs() < 1e-12);
+        assert!((gamma - 0.5).abs() < 1e-12);
+        assert!((w - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn perspective_correction_shifts_weight_to_the_nearer_vertex() {
+        // halfway between a near vertex (w = 1) and a far one (w = 3)
+        let (alpha, beta, gamma, w) =
+            Rasterizer::perspective_correct_barycentric((0.5, 0.5, 0.0), (1.0, 3.0, 2.0));
+        assert!((alpha - 0.75).abs() < 1e-12);
+        assert!((beta - 0.25).abs() < 1e-12);
+        assert_eq!(gamma, 0.0);
+        assert!((w - 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn barycentric_weights_sum_to_one() {
+        let rasterizer = Rasterizer::new();
+        rasterizer.for_each_triangle_pixel(
+            [2.0, 3.0],
+            [40.5, 10.25],
+            [12.0, 30.0],
+            (0, 0, WIDTH as i32, HEIGHT as i32),
+            |_, _, alpha, beta, gamma| {
+                assert!((alpha + beta + gamma - 1.0).abs() < 1e-9);
+                assert!(alpha >= 0.0 && beta >= 0.0 && gamma >= 0.0);
+            },
+        );
+    }
+
+    #[test]
+    fn barycentric_gradients_match_neighboring_pixels() {
+        let rasterizer = Rasterizer::new();
+        let (p0, p1, p2) = ([2.0, 3.0], [40.5, 10.25], [12.0, 30.0]);
+        let (ddx, ddy) = Rasterizer::barycentric_gradients(p0, p1, p2).unwrap();
+
+        let mut weights = std::collections::HashMap::new();
+        rasterizer.for_each_triangle_pixel(
+            p0,
+            p1,
+            p2,
+            (0, 0, WIDTH as i32, HEIGHT as i32),
+            |x, y, alpha, beta, gamma| {
+                weights.insert((x, y), [alpha, beta, gamma]);
+            },
+        );
+
+        for (&(x, y), w) in &weights {
+            let neighbors = [((x + 1, y), ddx), ((x, y + 1), ddy)];
+            for (neighbor, gradient) in neighbors {
+                if let Some(n) = weights.get(&neighbor) {
+                    for i in 0..3 {
+                        assert!((n[i] - w[i] - gradient[i]).abs() < 1e-9);
+                    }
+                }
+            }
+        }
+        assert!(Rasterizer::barycentric_gradients(p0, p0, p2).is_none());
+    }
+
+    #[test]
+    fn multisampled_quad_covers_every_sample_once() {
+        let rasterizer = Rasterizer::new();
+        let samples = [
+            [0.375, 0.125],
+            [0.875, 0.375],
+            [0.125, 0.625],
+            [0.625, 0.875],
+        ];
+        let mut hits = vec![0; WIDTH * HEIGHT * samples.len()];
+
+        let quad = [
+            [[0.3, 0.2], [63.7, 2.9], [60.1, 47.6]],
+            [[0.3, 0.2], [60.1, 47.6], [1.4, 44.2]],
+        ];
+
+        for [p0, p1, p2] in quad {
+            rasterizer.for_each_triangle_coverage(
+                p0,
+                p1,
+                p2,
+                (0, 0, WIDTH as i32, HEIGHT as i32),
+                &samples,
+                |x, y, coverage, _, _| {
+                    for sample in 0..samples.len() {
+                        if coverage & (1 << sample) != 0 {
+                            hits[(y as usize * WIDTH + x as usize) * samples.len() + sample] += 1;
+                        }
+                    }
+                },
+            );
+        }
+
+        assert!(hits.iter().all(|&hits| hits <= 1));
+        assert!(hits.iter().filter(|&&hits| hits == 1).count() > WIDTH * HEIGHT * 3);
+    }
+
+    #[test]
+    fn thick_butt_line_covers_its_rectangle() {
+        let rasterizer = Rasterizer::new();
+        let style = LineStyle::new(3.0, false, LineCap::Butt);
+        let mut pixels = Vec::new();
+
+        rasterizer.for_each_line_coverage(
+            [10.5, 20.5],
+            [30.5, 20.5],
+            style,
+            (0, 0, WIDTH as i32, HEIGHT as i32),
+            |x, y, coverage| {
+                assert_eq!(coverage, 1.0);
+                pixels.push((x, y));
+            },
+        );
+
+        pixels.sort();
+        let expected: Vec<(i32, i32)> = (10..=30)
+            .flat_map(|x| (19..=21).map(move |y| (x, y)))
+            .collect();
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn line_end_points_keep_their_fraction() {
+        let rasterizer = Rasterizer::new();
+        let style = LineStyle::new(3.0, false, LineCap::Butt);
+        let columns = |end: f64| {
+            let mut columns = Vec::new();
+            rasterizer.for_each_line_coverage(
+                [10.0, 20.5],
+                [end, 20.5],
+                style,
+                (0, 0, WIDTH as i32, HEIGHT as i32),
+                |x, y, _| {
+                    if y == 20 {
+                        columns.push(x);
+                    }
+                },
+            );
+            columns.sort();
+            columns
+        };
+
+        // the line ends before the center of pixel 20 unless it reaches past it
+        assert_eq!(columns(20.4), (10..20).collect::<Vec<_>>());
+        assert_eq!(columns(20.6), (10..=20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn far_off_screen_line_only_visits_visible_pixels() {
+        let rasterizer = Rasterizer::new();
+        let mut visited = 0;
+
+        rasterizer.for_each_line_point(
+            ScreenPoint::new(-1_000_000_000, 10),
+            ScreenPoint::new(1_000_000_000, 10),
+            (0, 0, WIDTH as i32, HEIGHT as i32),
+            |x, y| {
+                assert!((0..WIDTH as i32).contains(&x) && y == 10);
+                visited += 1;
+            },
+        );
+        assert_eq!(visited, WIDTH);
+
+        let style = LineStyle::new(4.0, true, LineCap::Round);
+        let mut visited = 0;
+        rasterizer.for_each_line_coverage(
+            [f64::from(i32::MIN), f64::from(i32::MIN)],
+            [f64::from(i32::MAX), f64::from(i32::MAX)],
+            style,
+            (0, 0, WIDTH as i32, HEIGHT as i32),
+            |_, _, _| visited += 1,
+        );
+        assert!(visited > 0 && visited <= WIDTH * HEIGHT);
+    }
+
+    /// Pixel, coverage, center weights and covered sample weights
+    type CoverageRecord = (i32, i32, u32, [f64; 3], Vec<[f64; 3]>);
+
+    /// Collects everything `for_each_triangle_coverage` reports for a triangle in row order
+    fn coverage_trace(
+        triangle: [[f64; 2]; 3],
+        bounds: (i32, i32, i32, i32),
+        samples: &[[f64; 2]],
+        scan: bool,
+    ) -> Vec<CoverageRecord> {
+        let mut trace = Vec::new();
+        let record = |x, y, coverage, center: [f64; 3], sample_weights: &[[f64; 3]]| {
+            let covered = (0..samples.len())
+                .filter(|&sample| coverage & (1 << sample) != 0)
+                .map(|sample| sample_weights[sample])
+                .collect();
+            trace.push((x, y, coverage, center, covered));
+        };
+
+        let [p0, p1, p2] = triangle;
+        if scan {
+            Rasterizer::for_each_triangle_coverage_scan(p0, p1, p2, bounds, samples, record);
+        } else {
+            Rasterizer::new().for_each_triangle_coverage(p0, p1, p2, bounds, samples, record);
+        }
+
+        // blocks are visited block by block, the scan row by row
+        trace.sort_by_key(|&(x, y, ..)| (y, x));
+        trace
+    }
+
+    #[test]
+    fn block_traversal_matches_bounding_box_scan() {
+        // small linear congruential generator, so the test needs no dependencies
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = |range: f64| {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (seed >> 11) as f64 / (1u64 << 53) as f64 * range
+        };
+
+        let samples = [
+            [0.375, 0.125],
+            [0.875, 0.375],
+            [0.125, 0.625],
+            [0.625, 0.875],
+        ];
+        let bounds = (3, 5, WIDTH as i32 - 2, HEIGHT as i32 - 1);
+
+        for _ in 0..200 {
+            let triangle = [
+                [random(80.0) - 8.0, random(60.0) - 6.0],
+                [random(80.0) - 8.0, random(60.0) - 6.0],
+                [random(80.0) - 8.0, random(60.0) - 6.0],
+            ];
+
+            for samples in [&[[0.5, 0.5]][..], &samples[..]] {
+                assert_eq!(
+                    coverage_trace(triangle, bounds, samples, false),
+                    coverage_trace(triangle, bounds, samples, true)
+                );
+            }
+        }
+    }
+
+    /// Micro-benchmark of the block traversal against the bounding-box scan over the
+    /// bundled models, run with
+    /// `cargo test --release -- --ignored --nocapture triangle_traversal_benchmark`
+    #[test]
+    #[ignore]
+    fn triangle_traversal_benchmark() {
+        use crate::renderer::Material;
+        use crate::scene::{MaterialLibrary, Mesh};
+        use std::time::Instant;
+
+        const TARGET_WIDTH: f64 = 1280.0;
+        const TARGET_HEIGHT: f64 = 720.0;
+        const ITERATIONS: usize = 5;
+
+        let bounds = (0, 0, TARGET_WIDTH as i32, TARGET_HEIGHT as i32);
+        let samples = [[0.5, 0.5]];
+
+        for model in [
+            "cessna", "cow", "dolphin", "f-16", "magnolia", "suzanne", "teapot",
+        ] {
+            let mut materials = MaterialLibrary::new();
+            let default_material = materials.add("default", Material::new(0.1, 0.9, 0.0, 1.0));
+            let mesh = Mesh::load_obj(
+                &format!("models/{model}.obj"),
+                &mut materials,
+                default_material,
+            )
+            .expect("bundled model failed to load");
+
+            // orthographic front view scaled to fill the target
+            let (mut min, mut max) = ([f64::MAX; 2], [f64::MIN; 2]);
+            for vertex in &mesh.vertices {
+                for axis in 0..2 {
+                    min[axis] = min[axis].min(vertex.position[axis]);
+                    max[axis] = max[axis].max(vertex.position[axis]);
+                }
+            }
+            let scale = ((TARGET_WIDTH - 20.0) / (max[0] - min[0]))
+                .min((TARGET_HEIGHT - 20.0) / (max[1] - min[1]));
+            let screen: Vec<[f64; 2]> = mesh
+                .vertices
+                .iter()
+                .map(|vertex| {
+                    [
+                        10.0 + (vertex.position[0] - min[0]) * scale,
+                        TARGET_HEIGHT - 10.0 - (vertex.position[1] - min[1]) * scale,
+                    ]
+                })
+                .collect();
+
+            type Bounds = (i32, i32, i32, i32);
+            let triangles: Vec<([[f64; 2]; 3], Bounds)> = mesh
+                .triangle_indices
+                .chunks_exact(3)
+                .map(|indices| {
+                    let points = [0, 1, 2].map(|i| screen[indices[i] as usize]);
+                    let min_x = points.iter().map(|p| p[0]).fold(f64::MAX, f64::min);
+                    let min_y = points.iter().map(|p| p[1]).fold(f64::MAX, f64::min);
+                    let max_x = points.iter().map(|p| p[0]).fold(f64::MIN, f64::max);
+                    let max_y = points.iter().map(|p| p[1]).fold(f64::MIN, f64::max);
+                    let triangle_bounds = (
+                        (min_x.floor() as i32).max(bounds.0),
+                        (min_y.floor() as i32).max(bounds.1),
+                        (max_x.ceil() as i32 + 1).min(bounds.2),
+                        (max_y.ceil() as i32 + 1).min(bounds.3),
+                    );
+                    (points, triangle_bounds)
+                })
+                .collect();
+
+            let run = |scan: bool| {
+                let mut covered = 0usize;
+                let start = Instant::now();
+                for _ in 0..ITERATIONS {
+                    for ([p0, p1, p2], triangle_bounds) in &triangles {
+                        let count = |_, _, _, _, _: &[[f64; 3]]| covered += 1;
+                        if scan {
+                            Rasterizer::for_each_triangle_coverage_scan(
+                                *p0,
+                                *p1,
+                                *p2,
+                                *triangle_bounds,
+                                &samples,
+                                count,
+                            );
+                        } else {
+                            Rasterizer::new().for_each_triangle_coverage(
+                                *p0,
+                                *p1,
+                                *p2,
+                                *triangle_bounds,
+                                &samples,
+                                count,
+                            );
+                        }
+                    }
+                }
+                (
+                    start.elapsed().as_secs_f64() * 1000.0 / ITERATIONS as f64,
+                    covered,
+                )
+            };
+
+            let (scan_ms, scan_covered) = run(true);
+            let (block_ms, block_covered) = run(false);
+            assert_eq!(scan_covered, block_covered);
+
+            println!(
+                "{model:>10}: {:>7} triangles, scan {scan_ms:>8.2} ms, blocks {block_ms:>8.2} ms, speedup {:.2}x",
+                triangles.len(),
+                scan_ms / block_ms
+            );
+        }
+    }
 }
@@ -1,61 +1,138 @@
 use crate::math::{Point3D, Vector3D};
-use crate::scene::PointLight;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::renderer::{BlendMode, Texture};
+use crate::scene::{MtlMaterial, PointLight};
+use std::sync::Arc;
 
-static MATERIAL_ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
+/// Where a material's lighting is evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadingMode {
+    Flat,    // once per triangle with the face normal
+    Gouraud, // per vertex, the lit colors are interpolated
+    Phong,   // per fragment with the interpolated normal
+}
+
+impl ShadingMode {
+    pub const ALL: [ShadingMode; 3] = [ShadingMode::Flat, ShadingMode::Gouraud, ShadingMode::Phong];
 
-#[derive(Clone, Copy)]
+    pub fn label(&self) -> &'static str {
+        match self {
+            ShadingMode::Flat => "Flat",
+            ShadingMode::Gouraud => "Gouraud",
+            ShadingMode::Phong => "Phong",
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Material {
-    pub id: usize,
     pub ambient: f64,
     pub diffuse: f64,
     pub specular: f64,
     pub shininess: f64,
+    pub base_color: [f64; 3],     // ambient and diffuse color of the surface
+    pub specular_color: [f64; 3], // tint of the highlights
+    pub emissive: [f64; 3],       // color emitted by the surface, added regardless of the lights
+    pub emissive_intensity: f64,
+    pub vertex_colors: bool, // multiply the base color with the vertex colors
+    pub shading: ShadingMode,
+    pub opacity: f64, // 1.0 is opaque
+    pub blend_mode: BlendMode,
+    pub texture: Option<Arc<Texture>>, // multiplies the surface color, sampled with the fragment uv
 }
 
 impl Material {
     pub fn new(ambient: f64, diffuse: f64, specular: f64, shininess: f64) -> Material {
-        let id = MATERIAL_ID_COUNTER.fetch_add(1, Ordering::Relaxed);
         Self {
-            id,
             ambient,
             diffuse,
             specular,
             shininess,
+            base_color: [1.0, 1.0, 1.0],
+            specular_color: [1.0, 1.0, 1.0],
+            emissive: [0.0, 0.0, 0.0],
+            emissive_intensity: 1.0,
+            vertex_colors: true,
+            shading: ShadingMode::Gouraud,
+            opacity: 1.0,
+            blend_mode: BlendMode::Over,
+            texture: None,
         }
     }
 
-    pub const MATERIAL_ARRAY: [Material; 3] = [
-        Material {
-            id: 0,
-            ambient: 0.1,
-            diffuse: 0.5,
-            specular: 1.0,
-            shininess: 50.0,
-        },
-        Material {
-            id: 1,
-            ambient: 0.2,
-            diffuse: 0.7,
-            specular: 0.4,
-            shininess: 20.0,
-        },
-        Material {
-            id: 2,
-            ambient: 0.15,
-            diffuse: 0.7,
-            specular: 0.1,
-            shininess: 5.0,
-        },
-    ];
+    /// Renderer material for an MTL material.
+    /// Loads the diffuse texture, a texture that fails to load is reported and skipped.
+    pub fn from_mtl(mtl: &MtlMaterial) -> Material {
+        let average = |color: [f64; 3]| (color[0] + color[1] + color[2]) / 3.0;
+
+        // Ka is relative to the scene's ambient light, the base color is Kd
+        let ambient =
+            Self::MTL_AMBIENT_LIGHT * average(mtl.ambient) / average(mtl.diffuse).max(1e-6);
+        let (ambient, diffuse, specular) = match mtl.illum {
+            0 => (1.0, 0.0, 0.0), // constant color
+            1 => (ambient, 1.0, 0.0),
+            _ => (ambient, 1.0, 1.0),
+        };
+
+        let texture = mtl
+            .diffuse_map
+            .as_ref()
+            .and_then(|path| match Texture::load(path) {
+                Ok(texture) => Some(Arc::new(texture)),
+                Err(e) => {
+                    log::warn!(
+                        "Failed to load texture '{}' of material '{}': {}",
+                        path,
+                        mtl.name,
+                        e
+                    );
+                    None
+                }
+            });
+
+        Self {
+            ambient,
+            diffuse,
+            specular,
+            shininess: mtl.shininess,
+            base_color: mtl.diffuse,
+            specular_color: mtl.specular,
+            emissive: mtl.emissive,
+            emissive_intensity: 1.0,
+            vertex_colors: true,
+            shading: ShadingMode::Phong,
+            opacity: mtl.opacity.clamp(0.0, 1.0),
+            blend_mode: BlendMode::Over,
+            texture,
+        }
+    }
+
+    /// Intensity of the ambient light MTL ambient colors are multiplied with
+    const MTL_AMBIENT_LIGHT: f64 = 0.2;
+
+    /// Transparent materials are drawn after all opaque ones, back to front,
+    /// and neither write depth nor replace the color behind them
+    pub fn is_transparent(&self) -> bool {
+        self.opacity < 1.0 || self.blend_mode != BlendMode::Over
+    }
+
+    /// Part of an interpolated vertex color that tints the base color, white if disabled
+    pub fn vertex_color_factor(&self, vertex_color: [f64; 3]) -> [f64; 3] {
+        if self.vertex_colors {
+            vertex_color
+        } else {
+            [1.0, 1.0, 1.0]
+        }
+    }
 }
 
 pub trait ShadingModel {
+    /// Lit color of a surface point. `color_factor` multiplies the material's base color,
+    /// e.g. the vertex color and texel.
     fn calc_color(
         &self,
         surface_point: &Point3D,
         surface_normal: &Vector3D,
-        surface_color: &[f64; 3],
+        color_factor: &[f64; 3],
         view_vector: &Vector3D,
         material: &Material,
         lights: &[PointLight],
@@ -70,12 +147,16 @@ impl ShadingModel for FlatShader {
         &self,
         surface_point: &Point3D,
         surface_normal: &Vector3D,
-        surface_color: &[f64; 3],
+        color_factor: &[f64; 3],
         view_vector: &Vector3D,
         material: &Material,
         lights: &[PointLight],
     ) -> [f64; 3] {
-        let material_color = Vector3D::new(surface_color[0], surface_color[1], surface_color[2]);
+        // base color tinted by the vertex color and texture
+        let [r, g, b] = [0, 1, 2].map(|i| material.base_color[i] * color_factor[i]);
+        let material_color = Vector3D::new(r, g, b);
+        let [r, g, b] = material.specular_color;
+        let specular_color = Vector3D::new(r, g, b);
         let mut final_color = Vector3D::new(0.0, 0.0, 0.0);
         let light_count = lights.len() as f64;
 
@@ -95,7 +176,7 @@ impl ShadingModel for FlatShader {
                 .mul(f64::max(light_dir.dot(*surface_normal), 0.0));
 
             // Specular component
-            let cs_specular = Vector3D::new(1.0, 1.0, 1.0)
+            let cs_specular = specular_color
                 .mul(material.specular)
                 .mul(f64::max(halfway.dot(*surface_normal), 0.0).powf(material.shininess));
 
@@ -107,8 +188,76 @@ impl ShadingModel for FlatShader {
 
             final_color = final_color.add(light_contribution);
         }
+
+        // emission does not depend on the lights
+        let [r, g, b] = material.emissive;
+        final_color = final_color.add(Vector3D::new(r, g, b).mul(material.emissive_intensity));
+
         // Clamp and convert back to RGB
         final_color = final_color.clamp(0.0, 1.0);
         [final_color.x, final_color.y, final_color.z]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::color::ColorRGB;
+
+    fn assert_color_eq(actual: [f64; 3], expected: [f64; 3]) {
+        for i in 0..3 {
+            assert!(
+                (actual[i] - expected[i]).abs() < 1e-9,
+                "{actual:?} != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn material_colors_tint_the_lighting() {
+        // light, view and normal all along +z: full diffuse and specular
+        let lights = [PointLight::new(
+            Point3D::new(0.0, 0.0, 5.0),
+            ColorRGB::WHITE,
+            1.0,
+        )];
+        let shade = |material: &Material, vertex_color: [f64; 3]| {
+            FlatShader.calc_color(
+                &Point3D::new(0.0, 0.0, 0.0),
+                &Vector3D::new(0.0, 0.0, 1.0),
+                &material.vertex_color_factor(vertex_color),
+                &Vector3D::new(0.0, 0.0, 1.0),
+                material,
+                &lights,
+            )
+        };
+
+        let mut material = Material {
+            base_color: [1.0, 0.5, 0.0],
+            specular_color: [0.0, 0.0, 1.0],
+            emissive: [0.0, 0.2, 0.0],
+            emissive_intensity: 0.5,
+            ..Material::new(0.0, 0.5, 0.25, 10.0)
+        };
+
+        // diffuse base color + blue highlight + half intensity green emission
+        assert_color_eq(shade(&material, [1.0; 3]), [0.5, 0.35, 0.25]);
+        assert_color_eq(shade(&material, [0.5; 3]), [0.25, 0.225, 0.25]);
+
+        // recoloring the material without vertex colors
+        material.vertex_colors = false;
+        material.base_color = [0.0, 1.0, 0.0];
+        assert_color_eq(shade(&material, [0.5; 3]), [0.0, 0.6, 0.25]);
+
+        // emission is added without any light
+        let unlit = FlatShader.calc_color(
+            &Point3D::new(0.0, 0.0, 0.0),
+            &Vector3D::new(0.0, 0.0, 1.0),
+            &[1.0; 3],
+            &Vector3D::new(0.0, 0.0, 1.0),
+            &material,
+            &[],
+        );
+        assert_color_eq(unlit, [0.0, 0.1, 0.0]);
+    }
+}
@@ -0,0 +1,21 @@
+/// Per-frame counters, reset at the start of every `Renderer::render_view`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderStats {
+    pub draw_commands: usize,         // draw commands collected from the scene
+    pub culled_draw_commands: usize,  // draw commands skipped by frustum culling
+    pub material_changes: usize, // submitted draws whose material differs from the previous draw
+    pub stencil_changes: usize, // submitted draws whose stencil state differs from the previous draw
+    pub shaded_fragments: usize, // face fragments that passed the depth test and were shaded
+    pub covered_pixels: usize,  // pixels covered by at least one face
+    pub fragment_buffer_bytes: usize, // memory held by the fragment buffer after rasterization
+}
+
+impl RenderStats {
+    /// Average number of fragments shaded per covered pixel, 1.0 means no overdraw
+    pub fn overdraw(&self) -> f64 {
+        if self.covered_pixels == 0 {
+            return 0.0;
+        }
+        self.shaded_fragments as f64 / self.covered_pixels as f64
+    }
+}
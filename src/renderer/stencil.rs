@@ -0,0 +1,155 @@
+/// Comparison between a draw's stencil reference and the stored stencil value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompareFunction {
+    Never,
+    Less, // reference < stored
+    LessEqual,
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Greater,
+    #[default]
+    Always,
+}
+
+impl CompareFunction {
+    pub const ALL: [CompareFunction; 8] = [
+        CompareFunction::Never,
+        CompareFunction::Less,
+        CompareFunction::LessEqual,
+        CompareFunction::Equal,
+        CompareFunction::NotEqual,
+        CompareFunction::GreaterEqual,
+        CompareFunction::Greater,
+        CompareFunction::Always,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CompareFunction::Never => "Never",
+            CompareFunction::Less => "Less",
+            CompareFunction::LessEqual => "LessEqual",
+            CompareFunction::Equal => "Equal",
+            CompareFunction::NotEqual => "NotEqual",
+            CompareFunction::GreaterEqual => "GreaterEqual",
+            CompareFunction::Greater => "Greater",
+            CompareFunction::Always => "Always",
+        }
+    }
+
+    pub fn compare(&self, reference: u8, stored: u8) -> bool {
+        match self {
+            CompareFunction::Never => false,
+            CompareFunction::Less => reference < stored,
+            CompareFunction::LessEqual => reference <= stored,
+            CompareFunction::Equal => reference == stored,
+            CompareFunction::NotEqual => reference != stored,
+            CompareFunction::GreaterEqual => reference >= stored,
+            CompareFunction::Greater => reference > stored,
+            CompareFunction::Always => true,
+        }
+    }
+}
+
+/// What happens to the stored stencil value after the stencil and depth tests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StencilOp {
+    #[default]
+    Keep,
+    Zero,
+    Replace,   // write the reference value
+    Increment, // saturates at 255
+    Decrement, // saturates at 0
+    IncrementWrap,
+    DecrementWrap,
+    Invert,
+}
+
+impl StencilOp {
+    pub const ALL: [StencilOp; 8] = [
+        StencilOp::Keep,
+        StencilOp::Zero,
+        StencilOp::Replace,
+        StencilOp::Increment,
+        StencilOp::Decrement,
+        StencilOp::IncrementWrap,
+        StencilOp::DecrementWrap,
+        StencilOp::Invert,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            StencilOp::Keep => "Keep",
+            StencilOp::Zero => "Zero",
+            StencilOp::Replace => "Replace",
+            StencilOp::Increment => "Increment",
+            StencilOp::Decrement => "Decrement",
+            StencilOp::IncrementWrap => "IncrementWrap",
+            StencilOp::DecrementWrap => "DecrementWrap",
+            StencilOp::Invert => "Invert",
+        }
+    }
+
+    pub fn apply(&self, reference: u8, stored: u8) -> u8 {
+        match self {
+            StencilOp::Keep => stored,
+            StencilOp::Zero => 0,
+            StencilOp::Replace => reference,
+            StencilOp::Increment => stored.saturating_add(1),
+            StencilOp::Decrement => stored.saturating_sub(1),
+            StencilOp::IncrementWrap => stored.wrapping_add(1),
+            StencilOp::DecrementWrap => stored.wrapping_sub(1),
+            StencilOp::Invert => !stored,
+        }
+    }
+}
+
+/// Per draw stencil configuration. The test compares `reference & read_mask` with
+/// `stored & read_mask`, the ops only change the bits in `write_mask`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StencilState {
+    pub reference: u8,
+    pub read_mask: u8,
+    pub write_mask: u8,
+    pub compare: CompareFunction,
+    pub fail: StencilOp,       // stencil test failed
+    pub depth_fail: StencilOp, // stencil test passed, depth test failed
+    pub pass: StencilOp,       // both tests passed
+}
+
+impl Default for StencilState {
+    fn default() -> Self {
+        Self::DISABLED
+    }
+}
+
+impl StencilState {
+    /// Always passes and never changes the stencil plane
+    pub const DISABLED: StencilState = StencilState {
+        reference: 0,
+        read_mask: 0xff,
+        write_mask: 0xff,
+        compare: CompareFunction::Always,
+        fail: StencilOp::Keep,
+        depth_fail: StencilOp::Keep,
+        pass: StencilOp::Keep,
+    };
+
+    pub fn is_enabled(&self) -> bool {
+        self.compare != CompareFunction::Always
+            || [self.fail, self.depth_fail, self.pass]
+                .iter()
+                .any(|op| *op != StencilOp::Keep)
+    }
+
+    pub fn test(&self, stored: u8) -> bool {
+        self.compare
+            .compare(self.reference & self.read_mask, stored & self.read_mask)
+    }
+
+    /// Applies `op` to a stored value, keeping the bits outside the write mask
+    pub fn update(&self, op: StencilOp, stored: u8) -> u8 {
+        let value = op.apply(self.reference, stored);
+        (stored & !self.write_mask) | (value & self.write_mask)
+    }
+}
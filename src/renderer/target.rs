@@ -1,25 +1,347 @@
-use crate::renderer::{ColorRGB, FrameBuffer};
+use crate::renderer::{AntiAliasing, BlendMode, ColorRGB, FrameBuffer};
+use std::ops::Range;
 
 pub struct RenderTarget {
-    pub framebuffer: FrameBuffer,
-    pub z_buffer: Vec<f64>,
+    pub framebuffer: FrameBuffer, // render resolution (display size * SSAA factor)
+    pub z_buffer: Vec<f64>,       // one depth value per sample
+    pub stencil_buffer: Vec<u8>,  // one stencil value per sample
+    pub primitive_buffer: Vec<u32>, // triangle that wrote the depth of each sample, see `DepthMode::Equal`
+    pub sample_colors: Vec<ColorRGB>, // one color per sample, only used for MSAA
+    resolved: FrameBuffer,          // downsampled display image, only used for SSAA
+    anti_aliasing: AntiAliasing,
+    display_width: usize,
+    display_height: usize,
 }
 
 impl RenderTarget {
     pub fn new(width: usize, height: usize) -> Self {
+        Self::with_anti_aliasing(width, height, AntiAliasing::Off)
+    }
+
+    pub fn with_anti_aliasing(width: usize, height: usize, anti_aliasing: AntiAliasing) -> Self {
+        let factor = anti_aliasing.ssaa_factor();
+        let samples = anti_aliasing.sample_count();
+        let (render_width, render_height) = (width * factor, height * factor);
+
+        let sample_colors = if samples > 1 {
+            vec![ColorRGB::BLACK; render_width * render_height * samples]
+        } else {
+            Vec::new()
+        };
+
+        let resolved = if factor > 1 {
+            FrameBuffer::new(width, height)
+        } else {
+            FrameBuffer::new(0, 0)
+        };
+
         Self {
-            framebuffer: FrameBuffer::new(width, height),
-            z_buffer: vec![f64::INFINITY; width * height],
+            framebuffer: FrameBuffer::new(render_width, render_height),
+            z_buffer: vec![f64::INFINITY; render_width * render_height * samples],
+            stencil_buffer: vec![0; render_width * render_height * samples],
+            primitive_buffer: vec![0; render_width * render_height * samples],
+            sample_colors,
+            resolved,
+            anti_aliasing,
+            display_width: width,
+            display_height: height,
         }
     }
 
     pub fn resize(&mut self, width: usize, height: usize) {
-        self.framebuffer = FrameBuffer::new(width, height);
-        self.z_buffer = vec![f64::INFINITY; width * height];
+        *self = Self::with_anti_aliasing(width, height, self.anti_aliasing);
+    }
+
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        if self.anti_aliasing != anti_aliasing {
+            *self =
+                Self::with_anti_aliasing(self.display_width, self.display_height, anti_aliasing);
+        }
+    }
+
+    pub fn get_anti_aliasing(&self) -> AntiAliasing {
+        self.anti_aliasing
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.anti_aliasing.sample_count()
+    }
+
+    pub fn get_display_width(&self) -> usize {
+        self.display_width
+    }
+
+    pub fn get_display_height(&self) -> usize {
+        self.display_height
     }
 
     pub fn clear(&mut self, clear_color: ColorRGB) {
         self.framebuffer.fill(clear_color);
+        self.clear_depth();
+        self.clear_stencil(0);
+    }
+
+    pub fn clear_depth(&mut self) {
         self.z_buffer.fill(f64::INFINITY);
     }
+
+    pub fn clear_stencil(&mut self, value: u8) {
+        self.stencil_buffer.fill(value);
+    }
+
+    /// Resets depth and stencil of the pixels inside `bounds`
+    pub fn clear_depth_stencil_in(&mut self, bounds: (i32, i32, i32, i32), stencil: u8) {
+        let samples = self.sample_count();
+        for pixels in self.pixel_rows(bounds) {
+            let samples = pixels.start * samples..pixels.end * samples;
+            self.z_buffer[samples.clone()].fill(f64::INFINITY);
+            self.stencil_buffer[samples].fill(stencil);
+        }
+    }
+
+    /// Index ranges of the pixels inside `bounds`, one per row.
+    /// `bounds` is `(min_x, min_y, max_x, max_y)` with exclusive max.
+    fn pixel_rows(
+        &self,
+        bounds: (i32, i32, i32, i32),
+    ) -> impl Iterator<Item = Range<usize>> + use<> {
+        let width = self.framebuffer.get_width();
+        let height = self.framebuffer.get_height();
+        let (min_x, max_x) = (
+            bounds.0.max(0) as usize,
+            (bounds.2.max(0) as usize).min(width),
+        );
+        let (min_y, max_y) = (
+            bounds.1.max(0) as usize,
+            (bounds.3.max(0) as usize).min(height),
+        );
+
+        (min_y..max_y)
+            .filter(move |_| min_x < max_x)
+            .map(move |y| y * width + min_x..y * width + max_x)
+    }
+
+    /// Broadcasts every framebuffer pixel inside `bounds` into its samples, so everything
+    /// drawn before the scene (background) shows through partially covered pixels
+    pub fn begin_samples(&mut self, bounds: (i32, i32, i32, i32)) {
+        let samples = self.sample_count();
+        for pixels in self.pixel_rows(bounds) {
+            for (pixel, pixel_samples) in self.framebuffer.buffer[pixels.start * 4..pixels.end * 4]
+                .chunks_exact(4)
+                .zip(
+                    self.sample_colors[pixels.start * samples..pixels.end * samples]
+                        .chunks_exact_mut(samples),
+                )
+            {
+                pixel_samples.fill(ColorRGB::from_rgba(pixel[0], pixel[1], pixel[2], pixel[3]));
+            }
+        }
+    }
+
+    /// Writes a color into all samples of a pixel selected by `coverage`
+    pub fn set_samples(&mut self, x: usize, y: usize, coverage: u32, color: ColorRGB) {
+        if !self.framebuffer.is_in_bounds(x, y) {
+            return;
+        }
+
+        let samples = self.sample_count();
+        let first_sample = (y * self.framebuffer.get_width() + x) * samples;
+
+        for sample in 0..samples {
+            if coverage & (1 << sample) != 0 {
+                self.sample_colors[first_sample + sample] = color;
+            }
+        }
+    }
+
+    /// Blends a color onto all samples of a pixel selected by `coverage`
+    pub fn blend_samples(
+        &mut self,
+        x: usize,
+        y: usize,
+        coverage: u32,
+        color: ColorRGB,
+        alpha: f64,
+        mode: BlendMode,
+    ) {
+        if !self.framebuffer.is_in_bounds(x, y) {
+            return;
+        }
+
+        let samples = self.sample_count();
+        let first_sample = (y * self.framebuffer.get_width() + x) * samples;
+
+        for sample in 0..samples {
+            if coverage & (1 << sample) != 0 {
+                let dst = &mut self.sample_colors[first_sample + sample];
+                *dst = dst.blend(color, alpha, mode);
+            }
+        }
+    }
+
+    /// Splits the target into its depth, stencil and primitive buffers and its color planes,
+    /// so all of them can be written while rasterizing
+    pub fn depth_stencil_and_color(
+        &mut self,
+    ) -> (&mut [f64], &mut [u8], &mut [u32], ColorBand<'_>) {
+        let width = self.framebuffer.get_width();
+        let sample_count = self.sample_count();
+
+        let color = ColorBand {
+            pixels: &mut self.framebuffer.buffer,
+            samples: &mut self.sample_colors,
+            width,
+            sample_count,
+            origin_y: 0,
+        };
+
+        (
+            &mut self.z_buffer,
+            &mut self.stencil_buffer,
+            &mut self.primitive_buffer,
+            color,
+        )
+    }
+
+    /// Averages the samples of every pixel inside `bounds` back into the framebuffer
+    pub fn resolve_samples(&mut self, bounds: (i32, i32, i32, i32)) {
+        let samples = self.sample_count();
+        for pixels in self.pixel_rows(bounds) {
+            for (pixel, pixel_samples) in self.framebuffer.buffer[pixels.start * 4..pixels.end * 4]
+                .chunks_exact_mut(4)
+                .zip(
+                    self.sample_colors[pixels.start * samples..pixels.end * samples]
+                        .chunks_exact(samples),
+                )
+            {
+                let mut sum = [0u32; 4];
+                for color in pixel_samples {
+                    sum[0] += color.get_r() as u32;
+                    sum[1] += color.get_g() as u32;
+                    sum[2] += color.get_b() as u32;
+                    sum[3] += color.get_a() as u32;
+                }
+                for channel in 0..4 {
+                    pixel[channel] = (sum[channel] / samples as u32) as u8;
+                }
+            }
+        }
+    }
+
+    /// Returns the image in display resolution, downsampling it first when SSAA is enabled
+    pub fn resolve(&mut self) -> &FrameBuffer {
+        let factor = self.anti_aliasing.ssaa_factor();
+        if factor == 1 {
+            return &self.framebuffer;
+        }
+
+        let block = (factor * factor) as u32;
+        for y in 0..self.display_height {
+            for x in 0..self.display_width {
+                let mut sum = [0u32; 4];
+                for sub_y in 0..factor {
+                    for sub_x in 0..factor {
+                        let index = self
+                            .framebuffer
+                            .get_index(x * factor + sub_x, y * factor + sub_y);
+                        for (channel, value) in sum.iter_mut().enumerate() {
+                            *value += self.framebuffer.buffer[index + channel] as u32;
+                        }
+                    }
+                }
+
+                let index = self.resolved.get_index(x, y);
+                for (channel, value) in sum.iter().enumerate() {
+                    self.resolved.buffer[index + channel] = (value / block) as u8;
+                }
+            }
+        }
+
+        &self.resolved
+    }
+}
+
+/// Mutable view of a range of rows of the color planes of a `RenderTarget`.
+/// With MSAA colors go into the samples, otherwise straight into the framebuffer.
+pub struct ColorBand<'a> {
+    pixels: &'a mut [u8],        // RGBA framebuffer rows
+    samples: &'a mut [ColorRGB], // MSAA sample rows, empty without MSAA
+    width: usize,
+    sample_count: usize,
+    origin_y: usize, // target row of the first row in the band
+}
+
+impl<'a> ColorBand<'a> {
+    /// Writes a color into the samples of a pixel selected by `coverage`,
+    /// `x` and `y` are target coordinates
+    pub fn write(&mut self, x: usize, y: usize, coverage: u32, color: ColorRGB) {
+        let pixel = (y - self.origin_y) * self.width + x;
+
+        if self.sample_count > 1 {
+            let first_sample = pixel * self.sample_count;
+            for sample in 0..self.sample_count {
+                if coverage & (1 << sample) != 0 {
+                    self.samples[first_sample + sample] = color;
+                }
+            }
+        } else {
+            self.pixels[pixel * 4..pixel * 4 + 4].copy_from_slice(&[
+                color.get_r(),
+                color.get_g(),
+                color.get_b(),
+                color.get_a(),
+            ]);
+        }
+    }
+
+    /// Blends a color onto the samples of a pixel selected by `coverage`,
+    /// `x` and `y` are target coordinates
+    pub fn blend(
+        &mut self,
+        x: usize,
+        y: usize,
+        coverage: u32,
+        color: ColorRGB,
+        alpha: f64,
+        mode: BlendMode,
+    ) {
+        let pixel = (y - self.origin_y) * self.width + x;
+
+        if self.sample_count > 1 {
+            let first_sample = pixel * self.sample_count;
+            for sample in 0..self.sample_count {
+                if coverage & (1 << sample) != 0 {
+                    let dst = &mut self.samples[first_sample + sample];
+                    *dst = dst.blend(color, alpha, mode);
+                }
+            }
+        } else {
+            let rgba = &mut self.pixels[pixel * 4..pixel * 4 + 4];
+            let blended =
+                ColorRGB::from_rgba(rgba[0], rgba[1], rgba[2], rgba[3]).blend(color, alpha, mode);
+            rgba[0] = blended.get_r();
+            rgba[1] = blended.get_g();
+            rgba[2] = blended.get_b();
+        }
+    }
+
+    /// Splits the band into bands of `rows` rows each
+    pub fn split_rows(&mut self, rows: usize) -> Vec<ColorBand<'_>> {
+        // without MSAA `samples` is empty and every band gets an empty slice
+        let mut sample_rows = self
+            .samples
+            .chunks_mut((self.width * rows * self.sample_count).max(1));
+
+        self.pixels
+            .chunks_mut((self.width * rows * 4).max(1))
+            .enumerate()
+            .map(|(band, pixels)| ColorBand {
+                pixels,
+                samples: sample_rows.next().unwrap_or_default(),
+                width: self.width,
+                sample_count: self.sample_count,
+                origin_y: self.origin_y + band * rows,
+            })
+            .collect()
+    }
 }
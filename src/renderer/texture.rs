@@ -0,0 +1,422 @@
+use crate::image::{self, Image, ImageError};
+
+/// How a texture is read between texel centers and across mip levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterMode {
+    Nearest,  // closest texel of the base level
+    Bilinear, // weighted average of the four closest texels of the base level
+    #[default]
+    Trilinear, // bilinear samples of the two closest mip levels, blended by the LOD
+    Anisotropic, // several trilinear probes along the longer axis of the pixel footprint
+}
+
+impl FilterMode {
+    pub const ALL: [FilterMode; 4] = [
+        FilterMode::Nearest,
+        FilterMode::Bilinear,
+        FilterMode::Trilinear,
+        FilterMode::Anisotropic,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FilterMode::Nearest => "Nearest",
+            FilterMode::Bilinear => "Bilinear",
+            FilterMode::Trilinear => "Trilinear",
+            FilterMode::Anisotropic => "Anisotropic",
+        }
+    }
+}
+
+/// How texel coordinates outside of the texture are mapped back into it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WrapMode {
+    #[default]
+    Repeat, // tile the texture
+    Clamp,  // stretch the border texels
+    Mirror, // tile the texture, flipping every other copy
+}
+
+impl WrapMode {
+    pub const ALL: [WrapMode; 3] = [WrapMode::Repeat, WrapMode::Clamp, WrapMode::Mirror];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WrapMode::Repeat => "Repeat",
+            WrapMode::Clamp => "Clamp",
+            WrapMode::Mirror => "Mirror",
+        }
+    }
+
+    /// Maps a texel coordinate into `0..size`
+    fn apply(&self, coord: i64, size: usize) -> usize {
+        let size = size as i64;
+        match self {
+            WrapMode::Repeat => coord.rem_euclid(size) as usize,
+            WrapMode::Clamp => coord.clamp(0, size - 1) as usize,
+            WrapMode::Mirror => {
+                let period = coord.rem_euclid(2 * size);
+                if period < size {
+                    period as usize
+                } else {
+                    (2 * size - 1 - period) as usize
+                }
+            }
+        }
+    }
+}
+
+/// One image of the mip chain
+#[derive(Debug, Clone)]
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<[f64; 3]>, // 0.0 - 1.0 per channel
+}
+
+impl MipLevel {
+    /// Half sized level, every texel is the average of a 2x2 block of this level
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let texel = |x: usize, y: usize| {
+            self.texels[y.min(self.height - 1) * self.width + x.min(self.width - 1)]
+        };
+
+        let texels = (0..width * height)
+            .map(|index| {
+                let (x, y) = (index % width * 2, index / width * 2);
+                let block = [
+                    texel(x, y),
+                    texel(x + 1, y),
+                    texel(x, y + 1),
+                    texel(x + 1, y + 1),
+                ];
+                [0, 1, 2].map(|i| block.iter().map(|texel| texel[i]).sum::<f64>() / 4.0)
+            })
+            .collect();
+
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+}
+
+/// RGB image sampled by materials, with a mip chain built when it is created.
+/// UV (0, 0) is the bottom left corner of the image like in OBJ files, the texels
+/// are stored top row first.
+#[derive(Debug, Clone)]
+pub struct Texture {
+    levels: Vec<MipLevel>, // base level first, down to 1x1
+    pub filter: FilterMode,
+    pub wrap: WrapMode,
+    pub lod_bias: f64,         // added to the computed level of detail, > 0 blurs
+    pub max_anisotropy: usize, // probes per sample in `Anisotropic` mode
+}
+
+impl Texture {
+    /// Colors of the mip levels in the mip debug view, the last one repeats
+    pub const MIP_COLORS: [[f64; 3]; 8] = [
+        [1.0, 0.0, 0.0],
+        [1.0, 0.5, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, 1.0, 1.0],
+        [0.0, 0.0, 1.0],
+        [1.0, 0.0, 1.0],
+        [0.5, 0.5, 0.5],
+    ];
+
+    pub fn new(width: usize, height: usize, texels: Vec<[f64; 3]>) -> Self {
+        assert!(width > 0 && height > 0, "texture must not be empty");
+        assert_eq!(
+            texels.len(),
+            width * height,
+            "texel count does not match the size"
+        );
+
+        let mut levels = vec![MipLevel {
+            width,
+            height,
+            texels,
+        }];
+        while let Some(last) = levels.last()
+            && (last.width > 1 || last.height > 1)
+        {
+            levels.push(last.downsample());
+        }
+
+        Self {
+            levels,
+            filter: FilterMode::default(),
+            wrap: WrapMode::default(),
+            lod_bias: 0.0,
+            max_anisotropy: 8,
+        }
+    }
+
+    /// Creates a texture from 8 bit RGBA pixels, the alpha channel is dropped
+    pub fn from_rgba8(width: usize, height: usize, pixels: &[u8]) -> Self {
+        let texels = pixels
+            .chunks_exact(4)
+            .map(|pixel| [0, 1, 2].map(|channel| pixel[channel] as f64 / 255.0))
+            .collect();
+        Self::new(width, height, texels)
+    }
+
+    pub fn from_image(image: &Image) -> Self {
+        Self::from_rgba8(image.width, image.height, &image.pixels)
+    }
+
+    /// Loads a PNG, TGA, BMP, PPM or PGM file
+    pub fn load(path: &str) -> Result<Self, ImageError> {
+        Ok(Self::from_image(&image::load(path)?))
+    }
+
+    /// Square checkerboard of `cells` x `cells` fields
+    pub fn checkerboard(size: usize, cells: usize, a: [f64; 3], b: [f64; 3]) -> Self {
+        let cell_size = (size / cells.max(1)).max(1);
+        let texels = (0..size * size)
+            .map(|index| {
+                let (x, y) = (index % size / cell_size, index / size / cell_size);
+                if (x + y) % 2 == 0 { a } else { b }
+            })
+            .collect();
+        Self::new(size, size, texels)
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    pub fn mip_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Texel at integer coordinates of a mip level, wrapped into the level
+    fn texel(&self, level: &MipLevel, x: i64, y: i64) -> [f64; 3] {
+        let x = self.wrap.apply(x, level.width);
+        let y = self.wrap.apply(y, level.height);
+        level.texels[y * level.width + x]
+    }
+
+    fn nearest(&self, level: &MipLevel, uv: [f64; 2]) -> [f64; 3] {
+        // texel space, texel centers are at +0.5 and rows go top down
+        let x = uv[0] * level.width as f64;
+        let y = (1.0 - uv[1]) * level.height as f64;
+        self.texel(level, x.floor() as i64, y.floor() as i64)
+    }
+
+    fn bilinear(&self, level: &MipLevel, uv: [f64; 2]) -> [f64; 3] {
+        let x = uv[0] * level.width as f64 - 0.5;
+        let y = (1.0 - uv[1]) * level.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let [c00, c10, c01, c11] = [
+            self.texel(level, x0, y0),
+            self.texel(level, x0 + 1, y0),
+            self.texel(level, x0, y0 + 1),
+            self.texel(level, x0 + 1, y0 + 1),
+        ];
+        [0, 1, 2].map(|i| {
+            let top = c00[i] + (c10[i] - c00[i]) * tx;
+            let bottom = c01[i] + (c11[i] - c01[i]) * tx;
+            top + (bottom - top) * ty
+        })
+    }
+
+    /// Bilinear samples of the two levels around `lod`, blended linearly
+    fn trilinear(&self, uv: [f64; 2], lod: f64) -> [f64; 3] {
+        let lod = lod.clamp(0.0, (self.levels.len() - 1) as f64);
+        let lower = lod.floor() as usize;
+        let upper = (lower + 1).min(self.levels.len() - 1);
+        let t = lod - lower as f64;
+
+        let a = self.bilinear(&self.levels[lower], uv);
+        if t == 0.0 || upper == lower {
+            return a;
+        }
+        let b = self.bilinear(&self.levels[upper], uv);
+        [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t)
+    }
+
+    /// Screen-space derivatives of the uv converted to base level texels,
+    /// longer axis first
+    fn footprint(&self, uv_ddx: [f64; 2], uv_ddy: [f64; 2]) -> ([f64; 2], f64, f64) {
+        let (width, height) = (self.get_width() as f64, self.get_height() as f64);
+        let x_length = (uv_ddx[0] * width).hypot(uv_ddx[1] * height);
+        let y_length = (uv_ddy[0] * width).hypot(uv_ddy[1] * height);
+
+        if x_length >= y_length {
+            (uv_ddx, x_length, y_length)
+        } else {
+            (uv_ddy, y_length, x_length)
+        }
+    }
+
+    /// Number of probes and level of detail of the anisotropic filter
+    fn anisotropic_lod(&self, major: f64, minor: f64) -> (usize, f64) {
+        let probes = (major / minor.max(f64::EPSILON))
+            .ceil()
+            .clamp(1.0, self.max_anisotropy.max(1) as f64) as usize;
+        (
+            probes,
+            (major / probes as f64).max(f64::EPSILON).log2() + self.lod_bias,
+        )
+    }
+
+    /// Mip level the filter reads for a pixel footprint, fractional between levels.
+    /// `Nearest` and `Bilinear` always read the base level.
+    pub fn lod(&self, uv_ddx: [f64; 2], uv_ddy: [f64; 2]) -> f64 {
+        let (_, major, minor) = self.footprint(uv_ddx, uv_ddy);
+        let lod = match self.filter {
+            FilterMode::Nearest | FilterMode::Bilinear => return 0.0,
+            FilterMode::Trilinear => major.max(f64::EPSILON).log2() + self.lod_bias,
+            FilterMode::Anisotropic => self.anisotropic_lod(major, minor).1,
+        };
+        lod.clamp(0.0, (self.levels.len() - 1) as f64)
+    }
+
+    /// Color at a texture coordinate using the texture's filter and wrap mode
+    ///
+    /// ### Arguments
+    ///
+    /// * `uv` - Texture coordinate
+    /// * `uv_ddx`, `uv_ddy` - Change of the texture coordinate per pixel in x and y,
+    ///   selects the mip level
+    pub fn sample(&self, uv: [f64; 2], uv_ddx: [f64; 2], uv_ddy: [f64; 2]) -> [f64; 3] {
+        match self.filter {
+            FilterMode::Nearest => self.nearest(&self.levels[0], uv),
+            FilterMode::Bilinear => self.bilinear(&self.levels[0], uv),
+            FilterMode::Trilinear => self.trilinear(uv, self.lod(uv_ddx, uv_ddy)),
+            FilterMode::Anisotropic => {
+                // probes spread evenly along the major axis of the footprint,
+                // each covering a square part of it
+                let (axis, major, minor) = self.footprint(uv_ddx, uv_ddy);
+                let (probes, lod) = self.anisotropic_lod(major, minor);
+
+                let mut color = [0.0; 3];
+                for probe in 0..probes {
+                    let t = (probe as f64 + 0.5) / probes as f64 - 0.5;
+                    let probe_uv = [uv[0] + axis[0] * t, uv[1] + axis[1] * t];
+                    let probe_color = self.trilinear(probe_uv, lod);
+                    for i in 0..3 {
+                        color[i] += probe_color[i] / probes as f64;
+                    }
+                }
+                color
+            }
+        }
+    }
+
+    /// Color of a mip level in the mip debug view, blended between levels
+    pub fn mip_color(lod: f64) -> [f64; 3] {
+        let last = Self::MIP_COLORS.len() - 1;
+        let lod = lod.clamp(0.0, last as f64);
+        let lower = lod.floor() as usize;
+        let upper = (lower + 1).min(last);
+        let t = lod - lower as f64;
+
+        let (a, b) = (Self::MIP_COLORS[lower], Self::MIP_COLORS[upper]);
+        [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> Texture {
+        // 2x2: top row black, white; bottom row red, green
+        Texture::new(
+            2,
+            2,
+            vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 1.0, 1.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ],
+        )
+    }
+
+    #[test]
+    fn nearest_sampling_wraps() {
+        let mut texture = gradient();
+        texture.filter = FilterMode::Nearest;
+        let d = [0.0; 2];
+
+        // uv origin is the bottom left texel
+        assert_eq!(texture.sample([0.25, 0.25], d, d), [1.0, 0.0, 0.0]);
+        assert_eq!(texture.sample([0.75, 0.75], d, d), [1.0, 1.0, 1.0]);
+
+        texture.wrap = WrapMode::Repeat;
+        assert_eq!(texture.sample([1.25, 0.25], d, d), [1.0, 0.0, 0.0]);
+        texture.wrap = WrapMode::Clamp;
+        assert_eq!(texture.sample([1.25, 0.25], d, d), [0.0, 1.0, 0.0]);
+        texture.wrap = WrapMode::Mirror;
+        assert_eq!(texture.sample([1.25, 0.25], d, d), [0.0, 1.0, 0.0]);
+        assert_eq!(texture.sample([-0.25, 0.25], d, d), [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn bilinear_sampling_blends_neighbors() {
+        let mut texture = gradient();
+        texture.filter = FilterMode::Bilinear;
+        texture.wrap = WrapMode::Clamp;
+        let d = [0.0; 2];
+
+        // texel centers return the texel, the center of the texture the average
+        assert_eq!(texture.sample([0.25, 0.25], d, d), [1.0, 0.0, 0.0]);
+        assert_eq!(texture.sample([0.5, 0.5], d, d), [0.5, 0.5, 0.25]);
+    }
+
+    #[test]
+    fn mip_chain_averages_down_to_one_texel() {
+        let texture = Texture::checkerboard(8, 8, [1.0; 3], [0.0; 3]);
+        assert_eq!(texture.mip_count(), 4);
+        assert_eq!(texture.levels[1].texels[0], [0.5; 3]);
+        assert_eq!(texture.levels[3].texels, vec![[0.5; 3]]);
+
+        // odd sizes round down and keep going until 1x1
+        let texture = Texture::new(5, 3, vec![[1.0; 3]; 15]);
+        let sizes: Vec<_> = texture.levels.iter().map(|l| (l.width, l.height)).collect();
+        assert_eq!(sizes, vec![(5, 3), (2, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn lod_follows_the_pixel_footprint() {
+        let mut texture = Texture::checkerboard(64, 8, [1.0; 3], [0.0; 3]);
+        let texel = 1.0 / 64.0;
+
+        // one texel per pixel reads the base level, four texels level 2
+        assert_eq!(texture.lod([texel, 0.0], [0.0, texel]), 0.0);
+        assert_eq!(texture.lod([4.0 * texel, 0.0], [0.0, 4.0 * texel]), 2.0);
+
+        texture.lod_bias = 1.0;
+        assert_eq!(texture.lod([4.0 * texel, 0.0], [0.0, 4.0 * texel]), 3.0);
+        texture.lod_bias = 0.0;
+
+        // a grazing footprint 8 texels long and 1 wide: trilinear blurs by its length,
+        // anisotropic takes 4 probes and stays sharper
+        let (ddx, ddy) = ([8.0 * texel, 0.0], [0.0, texel]);
+        assert_eq!(texture.lod(ddx, ddy), 3.0);
+        texture.filter = FilterMode::Anisotropic;
+        texture.max_anisotropy = 4;
+        assert_eq!(texture.lod(ddx, ddy), 1.0);
+
+        // far away the checkerboard averages to gray
+        texture.filter = FilterMode::Trilinear;
+        let far = [64.0 * texel, 0.0];
+        let color = texture.sample([0.3, 0.6], far, [0.0, 64.0 * texel]);
+        assert!(color.iter().all(|c| (c - 0.5).abs() < 1e-9));
+    }
+}
@@ -0,0 +1,66 @@
+/// Edge length of a square screen tile in pixels
+pub const TILE_SIZE: usize = 32;
+
+/// Splits a render target into fixed-size tiles for binned rasterization.
+/// Tiles on the right and bottom border may be smaller than `TILE_SIZE`.
+#[derive(Debug, Clone, Copy)]
+pub struct TileGrid {
+    pub tiles_x: usize,
+    pub tiles_y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl TileGrid {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            tiles_x: width.div_ceil(TILE_SIZE),
+            tiles_y: height.div_ceil(TILE_SIZE),
+            width,
+            height,
+        }
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.tiles_x * self.tiles_y
+    }
+
+    /// Pixel rectangle `(min_x, min_y, max_x, max_y)` of a tile, max exclusive
+    pub fn tile_bounds(&self, tile_x: usize, tile_y: usize) -> (i32, i32, i32, i32) {
+        let min_x = tile_x * TILE_SIZE;
+        let min_y = tile_y * TILE_SIZE;
+        (
+            min_x as i32,
+            min_y as i32,
+            (min_x + TILE_SIZE).min(self.width) as i32,
+            (min_y + TILE_SIZE).min(self.height) as i32,
+        )
+    }
+
+    /// Calls `f` with the index of every tile overlapped by a clamped pixel rectangle
+    pub fn for_each_overlapping_tile<F>(&self, bounds: (i32, i32, i32, i32), mut f: F)
+    where
+        F: FnMut(usize),
+    {
+        let (min_x, min_y, max_x, max_y) = bounds;
+        if min_x >= max_x || min_y >= max_y {
+            return;
+        }
+
+        let first_x = min_x as usize / TILE_SIZE;
+        let first_y = min_y as usize / TILE_SIZE;
+        let last_x = (max_x as usize - 1) / TILE_SIZE;
+        let last_y = (max_y as usize - 1) / TILE_SIZE;
+
+        for tile_y in first_y..=last_y {
+            for tile_x in first_x..=last_x {
+                f(tile_y * self.tiles_x + tile_x);
+            }
+        }
+    }
+}
+
+/// Intersection of two pixel rectangles, may be empty
+pub fn intersect_bounds(a: (i32, i32, i32, i32), b: (i32, i32, i32, i32)) -> (i32, i32, i32, i32) {
+    (a.0.max(b.0), a.1.max(b.1), a.2.min(b.2), a.3.min(b.3))
+}
@@ -0,0 +1,122 @@
+use crate::renderer::{BlendMode, Fragment};
+use crate::scene::{MaterialHandle, MaterialLibrary};
+
+/// How transparent surfaces are ordered before they are composited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransparencyMode {
+    #[default]
+    Sorted, // transparent draw commands back to front, fragments blended in submission order
+    OrderIndependent, // per pixel fragment lists sorted by depth, independent of submission order
+}
+
+impl TransparencyMode {
+    pub const ALL: [TransparencyMode; 2] =
+        [TransparencyMode::Sorted, TransparencyMode::OrderIndependent];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TransparencyMode::Sorted => "Sorted",
+            TransparencyMode::OrderIndependent => "Order independent",
+        }
+    }
+}
+
+/// Builds the order in which the fragment buffer is composited for order independent
+/// transparency: opaque surface fragments in submission order, then the transparent
+/// fragments of every pixel sorted far to near, then debug fragments on top.
+///
+/// Transparent fragments were only tested against the depth at the time they were
+/// rasterized, so samples hidden by an opaque surface drawn later are removed from
+/// their coverage here, `z_buffer` holds the final opaque depth.
+pub fn fragment_list_order(
+    fragments: &mut [Fragment],
+    materials: &MaterialLibrary,
+    z_buffer: &[f64],
+    width: usize,
+    sample_count: usize,
+) -> Vec<usize> {
+    let mut opaque = Vec::new();
+    let mut transparent = Vec::new();
+    let mut debug = Vec::new();
+
+    for (index, fragment) in fragments.iter_mut().enumerate() {
+        if fragment.material_id == Fragment::UNLIT {
+            debug.push(index);
+            continue;
+        }
+
+        let blend_mode = materials
+            .get(MaterialHandle::from_index(fragment.material_id))
+            .blend_mode;
+        if fragment.alpha >= 1.0 && blend_mode == BlendMode::Over {
+            opaque.push(index);
+            continue;
+        }
+
+        let first_sample = (fragment.y as usize * width + fragment.x as usize) * sample_count;
+        for sample in 0..sample_count {
+            if fragment.z >= z_buffer[first_sample + sample] {
+                fragment.coverage &= !(1 << sample);
+            }
+        }
+        if fragment.coverage != 0 {
+            transparent.push(index);
+        }
+    }
+
+    // per pixel lists, farthest first; the sort is stable, equal depths keep submission order
+    transparent.sort_by(|&a, &b| {
+        let (a, b) = (&fragments[a], &fragments[b]);
+        (a.y, a.x)
+            .cmp(&(b.y, b.x))
+            .then_with(|| b.z.total_cmp(&a.z))
+    });
+
+    opaque.extend(transparent);
+    opaque.extend(debug);
+    opaque
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::Material;
+
+    fn fragment(x: i32, z: f64, alpha: f64, material_id: usize) -> Fragment {
+        Fragment {
+            x,
+            y: 0,
+            z,
+            w: 1.0,
+            coverage: 1,
+            alpha,
+            color: [0.0; 3],
+            normal: [0.0; 3],
+            view_position: [0.0; 3],
+            uv: [0.0; 2],
+            uv_ddx: [0.0; 2],
+            uv_ddy: [0.0; 2],
+            material_id,
+        }
+    }
+
+    #[test]
+    fn transparent_fragments_are_sorted_far_to_near_per_pixel() {
+        let mut materials = MaterialLibrary::new();
+        materials.add("opaque", Material::new(0.1, 0.5, 1.0, 50.0));
+        let mut fragments = vec![
+            fragment(0, 0.1, 0.5, 0),               // near glass
+            fragment(1, 0.3, 0.5, 0),               // other pixel
+            fragment(0, 0.2, 1.0, 0),               // opaque, submitted late
+            fragment(0, 0.3, 0.5, 0),               // far glass, behind the opaque surface
+            fragment(0, 0.15, 0.5, 0),              // middle glass
+            fragment(0, 0.0, 1.0, Fragment::UNLIT), // wireframe
+        ];
+        let z_buffer = [0.2, f64::INFINITY];
+
+        let order = fragment_list_order(&mut fragments, &materials, &z_buffer, 2, 1);
+
+        assert_eq!(order, vec![2, 4, 0, 1, 5]);
+        assert_eq!(fragments[3].coverage, 0);
+    }
+}
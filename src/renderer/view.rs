@@ -1,26 +1,104 @@
-use crate::renderer::{RenderTarget, Viewport};
+use crate::renderer::{Rect, RenderStats, RenderTarget, Viewport};
 
+/// A camera rendering into a region of a `RenderTarget` that is passed in when rendering,
+/// several views can share one target (picture-in-picture, split or quad view)
 pub struct RenderView {
     pub name: String,
     pub camera_node_name: String,
-    pub viewport: Viewport,
-    pub target: RenderTarget,
-    pub texture_handle: Option<egui::TextureHandle>,
+    pub stats: RenderStats, // counters of the last `Renderer::render_view` of this view
+    region: Option<Rect>,   // display area the viewport maps to, whole target if unset
+    scissor: Option<Rect>,  // display area passes may write to, whole region if unset
 }
 
 impl RenderView {
-    pub fn new(name: &str, camera_node_name: &str, width: usize, height: usize) -> Self {
+    pub fn new(name: &str, camera_node_name: &str) -> Self {
         Self {
             name: name.to_string(),
             camera_node_name: camera_node_name.to_string(),
-            viewport: Viewport::new(width, height),
-            target: RenderTarget::new(width, height),
-            texture_handle: None,
+            stats: RenderStats::default(),
+            region: None,
+            scissor: None,
         }
     }
 
-    pub fn resize(&mut self, width: usize, height: usize) {
-        self.viewport = Viewport::new(width, height);
-        self.target.resize(width, height);
+    /// Renders into a sub-rectangle of the target (display coordinates),
+    /// e.g. an inset for picture-in-picture. `None` uses the whole target.
+    pub fn set_region(&mut self, region: Option<Rect>) {
+        self.region = region;
+    }
+
+    pub fn get_region(&self) -> Option<Rect> {
+        self.region
+    }
+
+    /// Restricts all writes to a rectangle (display coordinates), pixels outside of it
+    /// keep their color, depth and stencil. `None` writes the whole region.
+    pub fn set_scissor(&mut self, scissor: Option<Rect>) {
+        self.scissor = scissor;
+    }
+
+    pub fn get_scissor(&self) -> Option<Rect> {
+        self.scissor
+    }
+
+    /// Maps NDC onto the region of `target` at its render resolution (SSAA)
+    pub fn viewport(&self, target: &RenderTarget) -> Viewport {
+        let factor = target.get_anti_aliasing().ssaa_factor();
+        let target_rect = Rect::new(
+            0,
+            0,
+            target.framebuffer.get_width(),
+            target.framebuffer.get_height(),
+        );
+        let rect = match self.region {
+            Some(region) => region.scaled(factor).intersect(&target_rect),
+            None => target_rect,
+        };
+        Viewport::from_rect(rect)
+    }
+
+    /// Pixels of the render target the passes may write to: the viewport
+    /// rectangle clipped by the scissor rectangle, in render resolution
+    pub fn scissor_bounds(&self, target: &RenderTarget) -> (i32, i32, i32, i32) {
+        let factor = target.get_anti_aliasing().ssaa_factor();
+        let rect = self.viewport(target).get_rect();
+        match self.scissor {
+            Some(scissor) => rect.intersect(&scissor.scaled(factor)).bounds(),
+            None => rect.bounds(),
+        }
+    }
+
+    /// Display size of the area the view renders into, for the camera aspect ratio
+    pub fn get_size(&self, target: &RenderTarget) -> (usize, usize) {
+        match self.region {
+            Some(region) => (region.width, region.height),
+            None => (target.get_display_width(), target.get_display_height()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::AntiAliasing;
+
+    #[test]
+    fn views_share_a_target() {
+        let mut target = RenderTarget::new(200, 100);
+        target.set_anti_aliasing(AntiAliasing::Ssaa2x);
+
+        let main = RenderView::new("main", "main_camera");
+        let mut inset = RenderView::new("inset", "secondary_camera");
+        inset.set_region(Some(Rect::new(150, 75, 50, 25)));
+        inset.set_scissor(Some(Rect::new(0, 0, 160, 100)));
+
+        assert_eq!(main.viewport(&target).get_rect(), Rect::new(0, 0, 400, 200));
+        assert_eq!(main.get_size(&target), (200, 100));
+        assert_eq!(
+            inset.viewport(&target).get_rect(),
+            Rect::new(300, 150, 100, 50)
+        );
+        assert_eq!(inset.scissor_bounds(&target), (300, 150, 320, 200));
+        assert_eq!(inset.get_size(&target), (50, 25));
     }
 }
@@ -1,39 +1,121 @@
 use crate::math::Mat4x4;
 
+/// Axis aligned pixel rectangle, `x`/`y` is the top left corner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Same rectangle at a `factor` times higher resolution (SSAA)
+    pub fn scaled(&self, factor: usize) -> Rect {
+        Rect::new(
+            self.x * factor,
+            self.y * factor,
+            self.width * factor,
+            self.height * factor,
+        )
+    }
+
+    /// Overlap of both rectangles, empty (zero sized) if they don't overlap
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let max_x = (self.x + self.width).min(other.x + other.width);
+        let max_y = (self.y + self.height).min(other.y + other.height);
+
+        Rect::new(x, y, max_x.saturating_sub(x), max_y.saturating_sub(y))
+    }
+
+    /// `(min_x, min_y, max_x, max_y)` with exclusive max, as used by the rasterizer
+    pub fn bounds(&self) -> (i32, i32, i32, i32) {
+        (
+            self.x as i32,
+            self.y as i32,
+            (self.x + self.width) as i32,
+            (self.y + self.height) as i32,
+        )
+    }
+}
+
+/// Maps NDC onto a rectangle of the target
 pub struct Viewport {
-    width: usize,
-    height: usize,
+    rect: Rect,
     transform: Mat4x4,
 }
 
 impl Viewport {
     pub fn new(screen_width: usize, screen_height: usize) -> Self {
+        Self::from_rect(Rect::new(0, 0, screen_width, screen_height))
+    }
+
+    pub fn from_rect(rect: Rect) -> Self {
+        let (half_width, half_height) = (rect.width as f64 / 2.0, rect.height as f64 / 2.0);
         let transform = Mat4x4 {
             #[rustfmt::skip]
             mat: [
-                [screen_width as f64 / 2.0, 0.0, 0.0, screen_width as f64 / 2.0],
-                [0.0, -(screen_height as f64) / 2.0, 0.0, screen_height as f64 / 2.0],
+                [half_width, 0.0, 0.0, rect.x as f64 + half_width],
+                [0.0, -half_height, 0.0, rect.y as f64 + half_height],
                 [0.0, 0.0, 1.0, 0.0],
                 [0.0, 0.0, 0.0, 1.0],
             ],
         };
 
-        Self {
-            width: screen_width,
-            height: screen_height,
-            transform,
-        }
+        Self { rect, transform }
     }
 
     pub fn get_matrix(&self) -> Mat4x4 {
         self.transform
     }
 
+    pub fn get_rect(&self) -> Rect {
+        self.rect
+    }
+
     pub fn get_width(&self) -> usize {
-        self.width
+        self.rect.width
     }
 
     pub fn get_height(&self) -> usize {
-        self.height
+        self.rect.height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Point3D;
+
+    #[test]
+    fn viewport_maps_ndc_onto_its_rect() {
+        let viewport = Viewport::from_rect(Rect::new(100, 50, 40, 20));
+
+        let top_left = viewport.get_matrix() * Point3D::new(-1.0, 1.0, 0.0);
+        let bottom_right = viewport.get_matrix() * Point3D::new(1.0, -1.0, 0.0);
+        assert_eq!((top_left.x, top_left.y), (100.0, 50.0));
+        assert_eq!((bottom_right.x, bottom_right.y), (140.0, 70.0));
+    }
+
+    #[test]
+    fn rects_intersect() {
+        let a = Rect::new(0, 0, 100, 50);
+        assert_eq!(
+            a.intersect(&Rect::new(80, 40, 50, 50)),
+            Rect::new(80, 40, 20, 10)
+        );
+        assert_eq!(a.intersect(&Rect::new(200, 0, 10, 10)).width, 0);
+        assert_eq!(Rect::new(80, 40, 20, 10).bounds(), (80, 40, 100, 50));
     }
 }
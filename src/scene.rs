@@ -1,15 +1,22 @@
 #![allow(dead_code)]
 
+pub mod bounds;
 pub mod camera;
 pub mod geometry;
 pub mod light;
+pub mod materials; // named materials referenced by handle
+pub mod mtl; // Wavefront material libraries
 pub mod primitives;
+#[allow(clippy::module_inception)]
 mod scene;
 mod scene_node;
 
+pub use bounds::BoundingSphere;
 pub use camera::Camera;
 pub use geometry::Mesh;
 pub use light::PointLight;
+pub use materials::{MaterialHandle, MaterialLibrary};
+pub use mtl::{MtlMaterial, parse_mtl};
 #[allow(unused_imports)]
 pub use primitives::{Triangle, Vertex};
 pub use scene::Scene;
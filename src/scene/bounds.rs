@@ -0,0 +1,75 @@
+use crate::math::{Mat4x4, Point3D, Vector3D};
+use crate::scene::Vertex;
+
+/// Sphere enclosing a mesh, used to cull whole draw commands against the view frustum
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point3D,
+    pub radius: f64,
+}
+
+impl Default for BoundingSphere {
+    fn default() -> Self {
+        Self {
+            center: Point3D::new(0.0, 0.0, 0.0),
+            radius: 0.0,
+        }
+    }
+}
+
+impl BoundingSphere {
+    pub fn new(center: Point3D, radius: f64) -> Self {
+        Self { center, radius }
+    }
+
+    /// Builds a sphere around the axis aligned bounding box of the vertices.
+    /// Not minimal, but cheap and stable while the mesh is edited.
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        if vertices.is_empty() {
+            return Self::default();
+        }
+
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+
+        for vertex in vertices {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(vertex.position[axis]);
+                max[axis] = max[axis].max(vertex.position[axis]);
+            }
+        }
+
+        let center = Point3D::new(
+            (min[0] + max[0]) / 2.0,
+            (min[1] + max[1]) / 2.0,
+            (min[2] + max[2]) / 2.0,
+        );
+
+        let radius = vertices
+            .iter()
+            .map(|vertex| (vertex.position_to_point() - center).length())
+            .fold(0.0, f64::max);
+
+        Self { center, radius }
+    }
+
+    /// Transforms the sphere into another space. The radius is scaled by the
+    /// largest axis scale of the matrix so the sphere stays conservative.
+    pub fn transform(&self, transform: &Mat4x4) -> BoundingSphere {
+        let center = transform.mul_point(self.center);
+
+        let max_scale = [
+            Vector3D::new(1.0, 0.0, 0.0),
+            Vector3D::new(0.0, 1.0, 0.0),
+            Vector3D::new(0.0, 0.0, 1.0),
+        ]
+        .iter()
+        .map(|axis| transform.mul_vec(*axis).length())
+        .fold(0.0, f64::max);
+
+        BoundingSphere {
+            center,
+            radius: self.radius * max_scale,
+        }
+    }
+}
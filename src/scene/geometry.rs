@@ -1,17 +1,36 @@
+use crate::image;
 use crate::math::{Mat4x4, Point3D, Vector3D};
-use crate::scene::Vertex;
+use crate::renderer::Material;
+use crate::scene::{
+    BoundingSphere, MaterialHandle, MaterialLibrary, MtlMaterial, Vertex, parse_mtl,
+};
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fs;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 static MESH_ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
+/// Triangles of a mesh that share one material, see `Mesh::sort_by_material`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialRange {
+    pub material: MaterialHandle,    // same value as in triangle_materials
+    pub first_triangle_index: usize, // where the range begins in triangle_indices
+    pub triangle_index_count: usize, // 3 per triangle
+    pub first_vertex: usize,         // the triangles only use vertices of this range
+    pub vertex_count: usize,
+    pub bounds: BoundingSphere, // model space bounding sphere of the range's vertices
+}
+
 #[derive(Debug, Clone)]
 pub struct Mesh {
     pub id: usize,
     pub vertices: Vec<Vertex>,
     pub triangle_indices: Vec<u32>, // triple of indices represent a triangle [1,2,3,4,5,6] -> triangle between vertex 1,2,3 and 4,5,6
-    pub material_indices: Vec<u32>, // each index in this array represents one triangle in triangle_indices
+    pub triangle_materials: Vec<MaterialHandle>, // each entry in this array represents one triangle in triangle_indices
     pub vertex_triangle_adj_list: Vec<Vec<usize>>, // 1:[721, 733, 744] //vertex_index:[triangle_index, triangle_index, triangle_index]
+    pub bounds: BoundingSphere, // model space bounding sphere, used for frustum culling
+    pub material_ranges: Vec<MaterialRange>, // contiguous per-material triangles, built by sort_by_material
 }
 
 impl Mesh {
@@ -21,8 +40,19 @@ impl Mesh {
             id,
             vertices: Vec::new(),
             triangle_indices: Vec::new(),
-            material_indices: Vec::new(),
+            triangle_materials: Vec::new(),
             vertex_triangle_adj_list: Vec::new(),
+            bounds: BoundingSphere::default(),
+            material_ranges: Vec::new(),
+        }
+    }
+
+    pub fn calculate_bounds(&mut self) {
+        self.bounds = BoundingSphere::from_vertices(&self.vertices);
+        for range in &mut self.material_ranges {
+            range.bounds = BoundingSphere::from_vertices(
+                &self.vertices[range.first_vertex..range.first_vertex + range.vertex_count],
+            );
         }
     }
 
@@ -38,7 +68,83 @@ impl Mesh {
         }
     }
 
-    pub fn add_triangle(&mut self, indices: [u32; 3], material_id: u32) {
+    /// Reorders triangles and vertices so that every material covers one contiguous range
+    /// of `triangle_indices` and one of `vertices`, recorded in `material_ranges`.
+    ///
+    /// ### Notes
+    ///
+    /// * Triangles keep their relative order within a material
+    /// * Vertices shared by triangles of different materials are duplicated,
+    ///   vertices not used by any triangle are dropped
+    /// * The copies on a material seam have separate triangles, normals recalculated
+    ///   afterwards (e.g. by `transform`) no longer average across the seam
+    pub fn sort_by_material(&mut self) {
+        let mut triangle_order: Vec<usize> = (0..self.triangle_materials.len()).collect();
+        triangle_order.sort_by_key(|&triangle| self.triangle_materials[triangle]);
+
+        let mut vertices: Vec<Vertex> = Vec::with_capacity(self.vertices.len());
+        let mut triangle_indices: Vec<u32> = Vec::with_capacity(self.triangle_indices.len());
+        let mut triangle_materials: Vec<MaterialHandle> =
+            Vec::with_capacity(self.triangle_materials.len());
+        let mut material_ranges: Vec<MaterialRange> = Vec::new();
+
+        // new index of each old vertex within the current range
+        let mut remapped: Vec<Option<u32>> = vec![None; self.vertices.len()];
+
+        for triangle in triangle_order {
+            let material = self.triangle_materials[triangle];
+
+            if material_ranges
+                .last()
+                .is_none_or(|range| range.material != material)
+            {
+                material_ranges.push(MaterialRange {
+                    material,
+                    first_triangle_index: triangle_indices.len(),
+                    triangle_index_count: 0,
+                    first_vertex: vertices.len(),
+                    vertex_count: 0,
+                    bounds: BoundingSphere::default(),
+                });
+                remapped.fill(None);
+            }
+
+            for &index in &self.triangle_indices[triangle * 3..triangle * 3 + 3] {
+                let new_index = *remapped[index as usize].get_or_insert_with(|| {
+                    vertices.push(self.vertices[index as usize]);
+                    vertices.len() as u32 - 1
+                });
+                triangle_indices.push(new_index);
+            }
+            triangle_materials.push(material);
+
+            let range = material_ranges.last_mut().unwrap();
+            range.triangle_index_count += 3;
+            range.vertex_count = vertices.len() - range.first_vertex;
+        }
+
+        self.vertices = vertices;
+        self.triangle_indices = triangle_indices;
+        self.triangle_materials = triangle_materials;
+        self.material_ranges = material_ranges;
+        self.build_adj_list();
+        self.calculate_bounds();
+    }
+
+    /// The mesh with its triangles grouped by material: the mesh itself, or a sorted copy
+    /// if triangles were added after `sort_by_material` and `material_ranges` is empty
+    pub fn sorted_by_material(&self) -> Cow<'_, Mesh> {
+        if !self.material_ranges.is_empty() || self.triangle_indices.is_empty() {
+            return Cow::Borrowed(self);
+        }
+
+        let mut mesh = self.clone();
+        mesh.sort_by_material();
+        Cow::Owned(mesh)
+    }
+
+    /// Adds a triangle, clears `material_ranges` as they no longer cover every triangle
+    pub fn add_triangle(&mut self, indices: [u32; 3], material: MaterialHandle) {
         for &index in &indices {
             if index as usize >= self.vertices.len() {
                 panic!(
@@ -50,7 +156,8 @@ impl Mesh {
         }
 
         self.triangle_indices.extend_from_slice(&indices); // extends_from_slice instead of append to not remove items from indices array
-        self.material_indices.push(material_id);
+        self.triangle_materials.push(material);
+        self.material_ranges.clear();
     }
 
     pub fn transform(&mut self, transform: Mat4x4) {
@@ -64,6 +171,7 @@ impl Mesh {
         }
 
         self.calculate_vertex_normals();
+        self.calculate_bounds();
     }
 
     pub fn calculate_vertex_normals(&mut self) {
@@ -131,24 +239,68 @@ impl Mesh {
         }
     }
 
-    pub fn load_obj(obj_path: &str, material_id: u32, color: [f64; 3]) -> Result<Self, String> {
+    pub fn load_obj(
+        obj_path: &str,
+        materials: &mut MaterialLibrary,
+        default_material: MaterialHandle,
+    ) -> Result<Self, String> {
         let contents = fs::read_to_string(obj_path)
             .map_err(|e| format!("Failed to read OBJ file '{}': {}", obj_path, e))?;
-        Self::from_obj_str(&contents, obj_path, material_id, color)
+        Self::from_obj_str(
+            &contents,
+            obj_path,
+            materials,
+            default_material,
+            |mtl_path| {
+                fs::read_to_string(mtl_path)
+                    .map_err(|e| format!("Failed to read MTL file '{}': {}", mtl_path, e))
+            },
+        )
     }
 
-    pub fn from_obj_str(contents: &str, obj_name: &str, material_id: u32, color: [f64; 3]) -> Result<Self, String> {
+    /// Parses an OBJ file
+    ///
+    /// ### Arguments
+    ///
+    /// * `obj_name` - Path of the file, `mtllib` files are relative to it
+    /// * `materials` - Library the MTL materials used by faces are added to, named
+    ///   `"{mtl_path}:{name}"`; OBJ files sharing an MTL file share its materials
+    /// * `default_material` - Material of faces without an MTL material
+    /// * `read_mtl` - Reads a material library, e.g. from disk or from embedded files
+    ///
+    /// ### Notes
+    ///
+    /// * Vertex colors (`v x y z r g b`) are kept, other vertices are white
+    /// * Triangles are sorted by material, see `sort_by_material`
+    /// * Missing or malformed material libraries are reported and skipped
+    pub fn from_obj_str(
+        contents: &str,
+        obj_name: &str,
+        materials: &mut MaterialLibrary,
+        default_material: MaterialHandle,
+        read_mtl: impl Fn(&str) -> Result<String, String>,
+    ) -> Result<Self, String> {
         let mut mesh = Mesh::new();
 
+        let mut library: Vec<(String, MtlMaterial)> = Vec::new(); // materials of all mtllib files, with their path
+        let mut current_material: Option<usize> = None; // index into the library
+
         let mut vertices = Vec::new();
+        let mut vertex_colors = Vec::new();
         let mut vertex_uv_cords = Vec::new();
         let mut vertex_normals = Vec::new();
         let mut raw_faces = Vec::new();
 
+        // missing libraries and unknown material names are reported once each
+        let mut skipped_libraries: HashSet<String> = HashSet::new();
+        let mut unknown_materials: HashSet<&str> = HashSet::new();
+
         for (line_idx, line) in contents.lines().enumerate() {
             let line = line.trim();
-            if line.starts_with("v ") {
-                for s in line[2..].split_whitespace().take(3) {
+            if let Some(rest) = line.strip_prefix("v ") {
+                // x, y, z and an optional r, g, b vertex color
+                let mut values = Vec::new();
+                for s in rest.split_whitespace().take(6) {
                     let val = s.parse::<f64>().map_err(|e| {
                         format!(
                             "Line {}: failed to parse vertex coordinate '{}': {}",
@@ -157,11 +309,18 @@ impl Mesh {
                             e
                         )
                     })?;
-                    vertices.push(val);
+                    values.push(val);
                 }
-            } else if line.starts_with("vt ") {
-                for s in line[3..].split_whitespace().take(3) {
-                    let val = s.parse::<f64>().map_err(|e| {
+                vertices.extend(values.iter().take(3));
+                match values[..] {
+                    [_, _, _, r, g, b] => vertex_colors.extend([r, g, b]),
+                    _ => vertex_colors.extend([1.0, 1.0, 1.0]),
+                }
+            } else if let Some(rest) = line.strip_prefix("vt ") {
+                // u and v, the optional w is ignored and a missing v defaults to 0
+                let mut uv = [0.0, 0.0];
+                for (i, s) in rest.split_whitespace().take(2).enumerate() {
+                    uv[i] = s.parse::<f64>().map_err(|e| {
                         format!(
                             "Line {}: failed to parse texture coordinate '{}': {}",
                             line_idx + 1,
@@ -169,10 +328,10 @@ impl Mesh {
                             e
                         )
                     })?;
-                    vertex_uv_cords.push(val);
                 }
-            } else if line.starts_with("vn ") {
-                for s in line[3..].split_whitespace().take(3) {
+                vertex_uv_cords.extend(uv);
+            } else if let Some(rest) = line.strip_prefix("vn ") {
+                for s in rest.split_whitespace().take(3) {
                     let val = s.parse::<f64>().map_err(|e| {
                         format!(
                             "Line {}: failed to parse normal coordinate '{}': {}",
@@ -183,9 +342,33 @@ impl Mesh {
                     })?;
                     vertex_normals.push(val);
                 }
-            } else if line.starts_with("f ") {
+            } else if let Some(rest) = line.strip_prefix("mtllib ") {
+                for mtl_file in rest.split_whitespace() {
+                    let mtl_path = image::resolve_path(obj_name, mtl_file);
+                    match read_mtl(&mtl_path).and_then(|mtl| parse_mtl(&mtl, &mtl_path)) {
+                        Ok(materials) => library.extend(
+                            materials
+                                .into_iter()
+                                .map(|material| (mtl_path.clone(), material)),
+                        ),
+                        Err(e) => {
+                            if skipped_libraries.insert(mtl_path.clone()) {
+                                log::warn!("Skipping material library: {}", e);
+                            }
+                        }
+                    }
+                }
+            } else if let Some(rest) = line.strip_prefix("usemtl ") {
+                let name = rest.trim();
+                current_material = library
+                    .iter()
+                    .position(|(_, material)| material.name == name);
+                if current_material.is_none() && unknown_materials.insert(name) {
+                    log::warn!("Line {}: unknown material '{}'", line_idx + 1, name);
+                }
+            } else if let Some(rest) = line.strip_prefix("f ") {
                 let mut face = Vec::new();
-                for vertex_str in line[2..].split_whitespace() {
+                for vertex_str in rest.split_whitespace() {
                     let parts: Vec<&str> = vertex_str.split('/').collect();
                     let get_index = |i: usize| -> Option<u32> {
                         parts
@@ -195,13 +378,36 @@ impl Mesh {
                     };
                     face.push([get_index(0), get_index(1), get_index(2)]);
                 }
-                raw_faces.push(face);
+                raw_faces.push((face, current_material));
+            }
+        }
+
+        // library material of every triangle
+        let face_materials: Vec<Option<usize>> = raw_faces
+            .iter()
+            .flat_map(|(face, material)| {
+                std::iter::repeat_n(*material, face.len().saturating_sub(2))
+            })
+            .collect();
+
+        // add the library materials the faces use to the material library, in order of first use,
+        // materials already added from the same MTL file are reused
+        let mut handles: Vec<Option<MaterialHandle>> = vec![None; library.len()];
+        for &material in face_materials.iter().flatten() {
+            if handles[material].is_none() {
+                let (mtl_path, mtl) = &library[material];
+                let name = format!("{}:{}", mtl_path, mtl.name);
+                handles[material] = Some(
+                    materials
+                        .find(&name)
+                        .unwrap_or_else(|| materials.add(&name, Material::from_mtl(mtl))),
+                );
             }
         }
 
         let faces: Vec<[Option<u32>; 3]> = raw_faces
             .iter()
-            .flat_map(|face| {
+            .flat_map(|(face, _)| {
                 let mut triangle_buffer = Vec::new();
 
                 if face.len() >= 3 {
@@ -221,9 +427,14 @@ impl Mesh {
             })
             .collect();
 
-        for face in faces.chunks_exact(3) {
+        for (face, face_material) in faces.chunks_exact(3).zip(&face_materials) {
             let start_index = mesh.vertices.len() as u32;
 
+            let face_material = match face_material {
+                Some(material) => handles[*material].unwrap(),
+                None => default_material,
+            };
+
             let indices = [start_index, start_index + 1, start_index + 2];
 
             for vertex in face {
@@ -246,6 +457,28 @@ impl Mesh {
                     vertices[pos_stride + 1],
                     vertices[pos_stride + 2],
                 ];
+                let color: [f64; 3] = [
+                    vertex_colors[pos_stride],
+                    vertex_colors[pos_stride + 1],
+                    vertex_colors[pos_stride + 2],
+                ];
+
+                let uv: [f64; 2] = match vertex[1] {
+                    Some(vt_idx_obj) => {
+                        let uv_stride = (vt_idx_obj as usize)
+                            .checked_sub(1)
+                            .map(|vt_idx| vt_idx * 2)
+                            .filter(|&uv_stride| uv_stride + 1 < vertex_uv_cords.len())
+                            .ok_or_else(|| {
+                                format!(
+                                    "Error: Texture coordinate index {} is out of bounds",
+                                    vt_idx_obj
+                                )
+                            })?;
+                        [vertex_uv_cords[uv_stride], vertex_uv_cords[uv_stride + 1]]
+                    }
+                    None => [0.0, 0.0],
+                };
 
                 let normal_stride = vn_idx * 3;
                 let normal: [f64; 3] = if normal_stride + 2 < vertex_normals.len() {
@@ -260,26 +493,165 @@ impl Mesh {
 
                 let vertex = Vertex {
                     position,
-                    uv: [0.0, 0.0],
+                    uv,
                     normal,
                     color,
+                    w: 1.0,
+                    view_position: [0.0, 0.0, 0.0],
                 };
 
                 mesh.vertices.push(vertex);
             }
 
-            mesh.add_triangle(indices, material_id);
+            mesh.add_triangle(indices, face_material);
         }
 
-        println!("obj: {:?}", obj_name);
-        println!("vertices {:?}", vertices.len() / 3);
-        println!("vertex normals {:?}", vertex_normals.len() / 3);
-        println!("vertex uv cords {:?}", vertex_uv_cords.len() / 3);
-        println!("raw faces {:?}", raw_faces.len());
-        println!("triangulated faces {:?}\n", faces.len() / 3);
-
-        mesh.build_adj_list();
+        log::debug!(
+            "{obj_name}: {} vertices, {} vertex normals, {} uv cords, {} raw faces, \
+             {} triangulated faces, {} materials",
+            vertices.len() / 3,
+            vertex_normals.len() / 3,
+            vertex_uv_cords.len() / 2,
+            raw_faces.len(),
+            faces.len() / 3,
+            handles.iter().flatten().count()
+        );
+
+        mesh.sort_by_material();
         Ok(mesh)
     }
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_by_material_builds_contiguous_ranges() {
+        // a quad and a triangle sharing vertices, materials interleaved
+        let mut mesh = Mesh::new();
+        for x in 0..5 {
+            let position = [x as f64, (x % 2) as f64, 0.0];
+            mesh.vertices
+                .push(Vertex::new(position, [0.0; 2], [0.0; 3], [1.0; 3]));
+        }
+        let [first_material, second_material] = [3, 5].map(MaterialHandle::from_index);
+        mesh.add_triangle([0, 1, 2], second_material);
+        mesh.add_triangle([2, 3, 4], first_material);
+        mesh.add_triangle([0, 2, 3], second_material);
+        mesh.sort_by_material();
+
+        assert_eq!(
+            mesh.triangle_materials,
+            [first_material, second_material, second_material]
+        );
+        assert_eq!(mesh.material_ranges.len(), 2);
+
+        let [first, second] = [mesh.material_ranges[0], mesh.material_ranges[1]];
+        assert_eq!(
+            (first.material, second.material),
+            (first_material, second_material)
+        );
+        assert_eq!(
+            (first.first_triangle_index, first.triangle_index_count),
+            (0, 3)
+        );
+        assert_eq!(
+            (second.first_triangle_index, second.triangle_index_count),
+            (3, 6)
+        );
+
+        // vertices 2 and 3 are used by both materials and are duplicated
+        assert_eq!((first.first_vertex, first.vertex_count), (0, 3));
+        assert_eq!((second.first_vertex, second.vertex_count), (3, 4));
+        assert_eq!(mesh.vertices.len(), 7);
+        assert_eq!(mesh.triangle_indices, [0, 1, 2, 3, 4, 5, 3, 5, 6]);
+        for range in &mesh.material_ranges {
+            let indices = &mesh.triangle_indices[range.first_triangle_index
+                ..range.first_triangle_index + range.triangle_index_count];
+            assert!(indices.iter().all(|&index| {
+                (range.first_vertex..range.first_vertex + range.vertex_count)
+                    .contains(&(index as usize))
+            }));
+        }
+
+        // the original triangles keep their vertex positions
+        let position = |index: u32| mesh.vertices[index as usize].position[0];
+        let triangles: Vec<[f64; 3]> = mesh
+            .triangle_indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                [
+                    position(triangle[0]),
+                    position(triangle[1]),
+                    position(triangle[2]),
+                ]
+            })
+            .collect();
+        assert_eq!(
+            triangles,
+            [[2.0, 3.0, 4.0], [0.0, 1.0, 2.0], [0.0, 2.0, 3.0]]
+        );
+        assert_eq!(
+            first.bounds,
+            BoundingSphere::from_vertices(&mesh.vertices[0..3])
+        );
+    }
+
+    fn parse_triangle(face: &str) -> Result<Mesh, String> {
+        let obj = format!("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\n{face}");
+        let mut materials = MaterialLibrary::new();
+        let default = materials.add("default", Material::new(0.1, 0.9, 0.0, 1.0));
+        Mesh::from_obj_str(&obj, "a.obj", &mut materials, default, |_| {
+            Err("no MTL files".to_string())
+        })
+    }
+
+    #[test]
+    fn texture_coordinate_index_zero_is_rejected() {
+        let mesh = parse_triangle("f 1/1 2/2 3/3").unwrap();
+        assert_eq!(mesh.vertices[1].uv, [1.0, 0.0]);
+        assert_eq!(
+            parse_triangle("f 1/0 2/2 3/3").unwrap_err(),
+            "Error: Texture coordinate index 0 is out of bounds"
+        );
+    }
+
+    #[test]
+    fn texture_coordinate_index_past_the_end_is_rejected() {
+        assert_eq!(
+            parse_triangle("f 1/1 2/2 3/4").unwrap_err(),
+            "Error: Texture coordinate index 4 is out of bounds"
+        );
+    }
+
+    #[test]
+    fn unsorted_meshes_draw_one_range_per_material() {
+        let mut mesh = Mesh::new();
+        for x in 0..4 {
+            let position = [x as f64, (x % 2) as f64, 0.0];
+            mesh.vertices
+                .push(Vertex::new(position, [0.0; 2], [0.0; 3], [1.0; 3]));
+        }
+        assert!(mesh.sorted_by_material().material_ranges.is_empty());
+
+        let [first_material, second_material] = [1, 2].map(MaterialHandle::from_index);
+        mesh.add_triangle([0, 1, 2], second_material);
+        mesh.add_triangle([1, 2, 3], second_material);
+        mesh.sort_by_material();
+        assert!(matches!(mesh.sorted_by_material(), Cow::Borrowed(_)));
+
+        // adding a triangle invalidates the sorted ranges, drawing sorts a copy
+        mesh.add_triangle([0, 2, 3], first_material);
+        assert!(mesh.material_ranges.is_empty());
+
+        let sorted = mesh.sorted_by_material();
+        let materials: Vec<_> = sorted
+            .material_ranges
+            .iter()
+            .map(|range| (range.material, range.triangle_index_count))
+            .collect();
+        assert_eq!(materials, [(first_material, 3), (second_material, 6)]);
+        assert_eq!(mesh.triangle_materials[0], second_material);
+    }
 }
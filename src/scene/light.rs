@@ -62,7 +62,7 @@ impl PointLight {
         PointLight::new(new_light_pos, light.get_color(), light.get_intensity())
     }
 
-    pub fn to_world(&self, world_transform: &Mat4x4) -> PointLight {
+    pub fn to_world(self, world_transform: &Mat4x4) -> PointLight {
         let world_pos = world_transform.mul_point(self.position);
         PointLight {
             position: world_pos,
@@ -0,0 +1,153 @@
+use crate::renderer::{Material, ShadingMode};
+
+/// Typed index of a material in a `MaterialLibrary`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialHandle(u32);
+
+impl MaterialHandle {
+    /// Position of the material in its library, also stored in fragments
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub(crate) fn from_index(index: usize) -> Self {
+        Self(index as u32)
+    }
+}
+
+/// Named materials of a scene, meshes and draw commands refer to them by handle.
+/// Handles stay valid for the lifetime of the library, materials are never removed.
+#[derive(Clone)]
+pub struct MaterialLibrary {
+    materials: Vec<Material>,
+    names: Vec<String>,
+    fallback: Material, // used for handles that are not part of the library
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        Self {
+            materials: Vec::new(),
+            names: Vec::new(),
+            fallback: Material {
+                shading: ShadingMode::Phong,
+                ..Material::new(0.2, 0.7, 0.0, 1.0)
+            },
+        }
+    }
+
+    /// Adds a material, a material with the same name is replaced and keeps its handle
+    pub fn add(&mut self, name: &str, material: Material) -> MaterialHandle {
+        if let Some(handle) = self.find(name) {
+            self.materials[handle.index()] = material;
+            return handle;
+        }
+
+        self.materials.push(material);
+        self.names.push(name.to_string());
+        MaterialHandle::from_index(self.materials.len() - 1)
+    }
+
+    pub fn find(&self, name: &str) -> Option<MaterialHandle> {
+        self.names
+            .iter()
+            .position(|material_name| material_name == name)
+            .map(MaterialHandle::from_index)
+    }
+
+    /// Material of a handle, the fallback material if the handle is unknown
+    pub fn get(&self, handle: MaterialHandle) -> &Material {
+        self.materials.get(handle.index()).unwrap_or(&self.fallback)
+    }
+
+    pub fn get_mut(&mut self, handle: MaterialHandle) -> Option<&mut Material> {
+        self.materials.get_mut(handle.index())
+    }
+
+    pub fn contains(&self, handle: MaterialHandle) -> bool {
+        handle.index() < self.materials.len()
+    }
+
+    pub fn name(&self, handle: MaterialHandle) -> Option<&str> {
+        self.names.get(handle.index()).map(String::as_str)
+    }
+
+    pub fn fallback(&self) -> &Material {
+        &self.fallback
+    }
+
+    pub fn fallback_mut(&mut self) -> &mut Material {
+        &mut self.fallback
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MaterialHandle, &str, &Material)> {
+        self.names
+            .iter()
+            .zip(&self.materials)
+            .enumerate()
+            .map(|(index, (name, material))| {
+                (MaterialHandle::from_index(index), name.as_str(), material)
+            })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (MaterialHandle, &str, &mut Material)> {
+        self.names
+            .iter()
+            .zip(&mut self.materials)
+            .enumerate()
+            .map(|(index, (name, material))| {
+                (MaterialHandle::from_index(index), name.as_str(), material)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn materials_are_found_by_handle_and_name() {
+        let mut library = MaterialLibrary::new();
+        let matte = library.add("matte", Material::new(0.1, 0.9, 0.0, 1.0));
+        let glossy = library.add("glossy", Material::new(0.1, 0.5, 1.0, 50.0));
+
+        assert_ne!(matte, glossy);
+        assert_eq!(library.len(), 2);
+        assert_eq!(library.find("glossy"), Some(glossy));
+        assert_eq!(library.find("chrome"), None);
+        assert_eq!(library.name(matte), Some("matte"));
+        assert_eq!(library.get(glossy).shininess, 50.0);
+
+        // editing in place and replacing by name keep the handle
+        library.get_mut(matte).unwrap().opacity = 0.5;
+        assert_eq!(library.get(matte).opacity, 0.5);
+        let replaced = library.add("matte", Material::new(0.3, 0.6, 0.0, 1.0));
+        assert_eq!(replaced, matte);
+        assert_eq!(library.len(), 2);
+        assert_eq!(library.get(matte).ambient, 0.3);
+
+        let names: Vec<_> = library.iter().map(|(_, name, _)| name).collect();
+        assert_eq!(names, ["matte", "glossy"]);
+    }
+
+    #[test]
+    fn unknown_handles_use_the_fallback_material() {
+        let mut library = MaterialLibrary::new();
+        let missing = MaterialHandle::from_index(3);
+
+        assert!(!library.contains(missing));
+        assert!(library.get_mut(missing).is_none());
+        assert_eq!(library.get(missing).shininess, library.fallback().shininess);
+
+        library.fallback_mut().diffuse = 0.25;
+        assert_eq!(library.get(missing).diffuse, 0.25);
+    }
+}
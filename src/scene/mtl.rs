@@ -0,0 +1,261 @@
+use crate::image;
+
+/// Material of a Wavefront MTL library, as written in the file
+#[derive(Debug, Clone, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    pub ambient: [f64; 3],           // Ka
+    pub diffuse: [f64; 3],           // Kd
+    pub specular: [f64; 3],          // Ks
+    pub emissive: [f64; 3],          // Ke
+    pub shininess: f64,              // Ns
+    pub opacity: f64,                // d, or 1 - Tr
+    pub illum: u32,                  // 0 constant color, 1 without and 2 with highlights
+    pub diffuse_map: Option<String>, // map_Kd, resolved against the MTL file's directory
+    pub bump_map: Option<String>,    // map_Bump, not used by the shading yet
+}
+
+impl MtlMaterial {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ambient: [0.2, 0.2, 0.2],
+            diffuse: [0.8, 0.8, 0.8],
+            specular: [0.0, 0.0, 0.0],
+            emissive: [0.0, 0.0, 0.0],
+            shininess: 1.0,
+            opacity: 1.0,
+            illum: 2,
+            diffuse_map: None,
+            bump_map: None,
+        }
+    }
+}
+
+/// Parses the materials of an MTL file.
+/// Texture paths are resolved relative to `mtl_name`, unknown statements are ignored.
+pub fn parse_mtl(contents: &str, mtl_name: &str) -> Result<Vec<MtlMaterial>, String> {
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+
+    for (line_idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        let Some((keyword, rest)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let rest = rest.trim();
+
+        if keyword == "newmtl" {
+            materials.push(MtlMaterial::new(rest));
+            continue;
+        }
+        if keyword.starts_with('#') {
+            continue;
+        }
+
+        let Some(material) = materials.last_mut() else {
+            return Err(format!(
+                "{}: line {}: '{}' before the first newmtl",
+                mtl_name,
+                line_idx + 1,
+                keyword
+            ));
+        };
+
+        let numbers = || -> Result<Vec<f64>, String> {
+            rest.split_whitespace()
+                .map(|s| {
+                    s.parse::<f64>().map_err(|e| {
+                        format!(
+                            "{}: line {}: failed to parse '{}': {}",
+                            mtl_name,
+                            line_idx + 1,
+                            s,
+                            e
+                        )
+                    })
+                })
+                .collect()
+        };
+        // a single value is a gray color
+        let color = || -> Result<[f64; 3], String> {
+            match numbers()?[..] {
+                [gray] => Ok([gray; 3]),
+                [r, g, b, ..] => Ok([r, g, b]),
+                _ => Err(format!(
+                    "{}: line {}: expected a color",
+                    mtl_name,
+                    line_idx + 1
+                )),
+            }
+        };
+        let number = || -> Result<f64, String> {
+            numbers()?
+                .first()
+                .copied()
+                .ok_or_else(|| format!("{}: line {}: expected a number", mtl_name, line_idx + 1))
+        };
+        // options like `-bm 1.0` come before the file name, which is the last token
+        let texture_path = || {
+            rest.split_whitespace()
+                .last()
+                .map(|path| image::resolve_path(mtl_name, path))
+        };
+
+        match keyword {
+            "Ka" => material.ambient = color()?,
+            "Kd" => material.diffuse = color()?,
+            "Ks" => material.specular = color()?,
+            "Ke" => material.emissive = color()?,
+            "Ns" => material.shininess = number()?,
+            "d" => material.opacity = number()?,
+            "Tr" => material.opacity = 1.0 - number()?,
+            "illum" => material.illum = number()? as u32,
+            "map_Kd" => material.diffuse_map = texture_path(),
+            "map_Bump" | "map_bump" | "bump" => material.bump_map = texture_path(),
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_material_statements() {
+        let contents = "\
+# two materials
+newmtl glass
+Ka 0.4 0.4 0.4
+Kd 0.3
+Ks 0.3 0.3 0.3
+illum 2
+d 0.2100
+Ns 120
+
+newmtl skin
+Kd 1 0.5 0.25
+Ke 0.1 0 0
+Tr 0.25
+illum 1
+map_Kd textures/skin.tga
+map_Bump -bm 0.5 skin_bump.png
+";
+        let materials = parse_mtl(contents, "models/test.mtl").unwrap();
+        assert_eq!(materials.len(), 2);
+
+        let glass = &materials[0];
+        assert_eq!(glass.name, "glass");
+        assert_eq!(glass.ambient, [0.4; 3]);
+        assert_eq!(glass.diffuse, [0.3; 3]);
+        assert_eq!(glass.opacity, 0.21);
+        assert_eq!(glass.shininess, 120.0);
+        assert_eq!(glass.diffuse_map, None);
+
+        let skin = &materials[1];
+        assert_eq!(skin.diffuse, [1.0, 0.5, 0.25]);
+        assert_eq!(skin.emissive, [0.1, 0.0, 0.0]);
+        assert_eq!(skin.opacity, 0.75);
+        assert_eq!(skin.illum, 1);
+        assert_eq!(
+            skin.diffuse_map.as_deref(),
+            Some("models/textures/skin.tga")
+        );
+        assert_eq!(skin.bump_map.as_deref(), Some("models/skin_bump.png"));
+
+        assert!(parse_mtl("Kd 1 1 1", "a.mtl").is_err());
+        assert!(parse_mtl("newmtl a\nNs shiny", "a.mtl").is_err());
+    }
+
+    #[test]
+    fn obj_faces_index_their_mtl_materials() {
+        use crate::renderer::Material;
+        use crate::scene::{MaterialLibrary, Mesh};
+
+        let obj = "\
+mtllib parts.mtl
+v 0 0 0
+v 1 0 0 0.5 0.25 1
+v 1 1 0
+v 0 1 0
+f 1 2 3
+usemtl blue
+f 1 2 3 4
+usemtl red
+f 1 3 4
+usemtl missing
+f 1 2 4
+";
+        let mtl = "newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\n";
+        let read_mtl = |path: &str| {
+            assert_eq!(path, "models/parts.mtl");
+            Ok(mtl.to_string())
+        };
+        let mut materials = MaterialLibrary::new();
+        let default = materials.add("default", Material::new(0.1, 0.9, 0.0, 1.0));
+        let mesh =
+            Mesh::from_obj_str(obj, "models/parts.obj", &mut materials, default, read_mtl).unwrap();
+
+        // used MTL materials are added in order of first use, triangles are grouped by material
+        let names: Vec<_> = materials.iter().map(|(_, name, _)| name).collect();
+        assert_eq!(
+            names,
+            ["default", "models/parts.mtl:blue", "models/parts.mtl:red"]
+        );
+        let [blue, red] = ["blue", "red"]
+            .map(|name| materials.find(&format!("models/parts.mtl:{name}")).unwrap());
+        assert_eq!(mesh.triangle_materials, [default, default, blue, blue, red]);
+        assert_eq!(materials.get(blue).base_color, [0.0, 0.0, 1.0]);
+        assert_eq!(materials.get(red).base_color, [1.0, 0.0, 0.0]);
+
+        // vertex colors are only set by the OBJ file
+        assert_eq!(mesh.vertices[0].color, [1.0; 3]);
+        assert_eq!(mesh.vertices[1].color, [0.5, 0.25, 1.0]);
+        assert_eq!(mesh.vertices[12].color, [1.0; 3]);
+
+        // without material libraries every face keeps the given material
+        let mesh = Mesh::from_obj_str(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3",
+            "a.obj",
+            &mut materials,
+            red,
+            read_mtl,
+        )
+        .unwrap();
+        assert_eq!(materials.len(), 3);
+        assert_eq!(mesh.triangle_materials, [red]);
+    }
+
+    #[test]
+    fn same_named_materials_of_different_obj_files_are_kept_apart() {
+        use crate::renderer::Material;
+        use crate::scene::{MaterialLibrary, Mesh};
+
+        let read_mtl = |path: &str| match path {
+            "car/body.mtl" => Ok("newmtl paint\nKd 1 0 0\n".to_string()),
+            _ => Ok("newmtl paint\nKd 0 0 1\n".to_string()),
+        };
+        let obj = "mtllib body.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl paint\nf 1 2 3\n";
+        let mut materials = MaterialLibrary::new();
+        let default = materials.add("default", Material::new(0.1, 0.9, 0.0, 1.0));
+
+        let car =
+            Mesh::from_obj_str(obj, "car/car.obj", &mut materials, default, read_mtl).unwrap();
+        let boat =
+            Mesh::from_obj_str(obj, "boat/boat.obj", &mut materials, default, read_mtl).unwrap();
+        let (car_paint, boat_paint) = (car.triangle_materials[0], boat.triangle_materials[0]);
+        assert_ne!(car_paint, boat_paint);
+        assert_eq!(materials.get(car_paint).base_color, [1.0, 0.0, 0.0]);
+        assert_eq!(materials.get(boat_paint).base_color, [0.0, 0.0, 1.0]);
+
+        // a second OBJ using the same MTL file reuses its materials and their edits
+        materials.get_mut(car_paint).unwrap().opacity = 0.5;
+        let trailer =
+            Mesh::from_obj_str(obj, "car/trailer.obj", &mut materials, default, read_mtl).unwrap();
+        assert_eq!(trailer.triangle_materials, [car_paint]);
+        assert_eq!(materials.get(car_paint).opacity, 0.5);
+        assert_eq!(materials.len(), 3);
+    }
+}
@@ -7,6 +7,8 @@ pub struct Vertex {
     pub uv: [f64; 2],
     pub normal: [f64; 3],
     pub color: [f64; 3],
+    pub w: f64, // clip-space w, kept after the homogeneous divide for perspective-correct interpolation
+    pub view_position: [f64; 3], // view-space position, kept for per-fragment lighting
 }
 
 impl Vertex {
@@ -16,6 +18,8 @@ impl Vertex {
             uv,
             normal,
             color,
+            w: 1.0,
+            view_position: [0.0, 0.0, 0.0],
         }
     }
 
@@ -36,6 +40,35 @@ impl Vertex {
         self.normal[0].is_normal() && self.normal[1].is_normal() && self.normal[2].is_normal()
     }
 
+    /// Linearly interpolates all attributes towards `other` by `t` (0.0 = self, 1.0 = other)
+    pub fn lerp(&self, other: &Vertex, t: f64) -> Vertex {
+        let mix = |a: f64, b: f64| a + (b - a) * t;
+        Vertex {
+            position: [
+                mix(self.position[0], other.position[0]),
+                mix(self.position[1], other.position[1]),
+                mix(self.position[2], other.position[2]),
+            ],
+            uv: [mix(self.uv[0], other.uv[0]), mix(self.uv[1], other.uv[1])],
+            normal: [
+                mix(self.normal[0], other.normal[0]),
+                mix(self.normal[1], other.normal[1]),
+                mix(self.normal[2], other.normal[2]),
+            ],
+            color: [
+                mix(self.color[0], other.color[0]),
+                mix(self.color[1], other.color[1]),
+                mix(self.color[2], other.color[2]),
+            ],
+            w: mix(self.w, other.w),
+            view_position: [
+                mix(self.view_position[0], other.view_position[0]),
+                mix(self.view_position[1], other.view_position[1]),
+                mix(self.view_position[2], other.view_position[2]),
+            ],
+        }
+    }
+
     pub fn transform(&mut self, transform_mat: Mat4x4) {
         let transformed_position =
             transform_mat * Point3D::new(self.position[0], self.position[1], self.position[2]);
@@ -1,16 +1,36 @@
-use super::{Camera, Mesh, PointLight, SceneNode, Vertex};
+use super::{Camera, MaterialLibrary, Mesh, PointLight, SceneNode, Vertex};
 use crate::math::{Point3D, Vector3D};
-use crate::renderer::DrawCommand;
 use crate::renderer::color::ColorRGB;
+use crate::renderer::{DrawCommand, Material, ShadingMode};
 
 pub struct Scene {
     pub root_node: SceneNode,
+    pub materials: MaterialLibrary, // materials of all meshes in the scene
 }
 
 impl Scene {
     pub fn new() -> Scene {
         let mut root_node = SceneNode::new("root");
 
+        // materials
+        let mut materials = MaterialLibrary::new();
+        materials.add(
+            "glossy",
+            Material {
+                shading: ShadingMode::Phong,
+                ..Material::new(0.1, 0.5, 1.0, 50.0)
+            },
+        );
+        let satin = materials.add(
+            "satin",
+            Material {
+                shading: ShadingMode::Phong,
+                base_color: [32.0 / 255.0, 176.0 / 255.0, 144.0 / 255.0],
+                ..Material::new(0.2, 0.7, 0.4, 20.0)
+            },
+        );
+        materials.add("matte", Material::new(0.15, 0.7, 0.1, 5.0));
+
         // main camera
         {
             let mut camera: Camera = Camera::new(
@@ -58,7 +78,7 @@ impl Scene {
         #[cfg(not(target_arch = "wasm32"))]
         {
             let obj_path: String = "models/f-16.obj".to_string();
-            mesh_res = Mesh::load_obj(&obj_path, 1, [32.0 / 255.0, 176.0 / 255.0, 144.0 / 255.0]);
+            mesh_res = Mesh::load_obj(&obj_path, &mut materials, satin);
         }
 
         #[cfg(target_arch = "wasm32")]
@@ -66,8 +86,12 @@ impl Scene {
             mesh_res = Mesh::from_obj_str(
                 include_str!("../../models/f-16.obj"),
                 "models/f-16.obj",
-                1,
-                [32.0 / 255.0, 176.0 / 255.0, 144.0 / 255.0],
+                &mut materials,
+                satin,
+                |mtl_path| match mtl_path {
+                    "models/f-16.mtl" => Ok(include_str!("../../models/f-16.mtl").to_string()),
+                    _ => Err(format!("No embedded MTL file '{}'", mtl_path)),
+                },
             );
         }
 
@@ -94,7 +118,10 @@ impl Scene {
 
         root_node.add_child(model_node);
 
-        Scene { root_node }
+        Scene {
+            root_node,
+            materials,
+        }
     }
 
     pub fn find_camera(&self, node_name: &str) -> Option<&Camera> {
@@ -126,11 +153,11 @@ impl Scene {
     pub fn get_camera_by_name(&self, node_name: &str) -> Option<Camera> {
         let mut node_queue = vec![&self.root_node];
         while let Some(node) = node_queue.pop() {
-            if node.name == node_name {
-                if let Some(camera) = &node.camera {
-                    let world_transform = node.get_world_transform();
-                    return Some(camera.to_world(&world_transform));
-                }
+            if node.name == node_name
+                && let Some(camera) = &node.camera
+            {
+                let world_transform = node.get_world_transform();
+                return Some(camera.to_world(&world_transform));
             }
             for child in &node.children {
                 node_queue.push(child);
@@ -169,6 +196,21 @@ impl Scene {
         }
     }
 
+    pub fn update_meshes<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&mut Mesh),
+    {
+        let mut node_queue = vec![&mut self.root_node];
+        while let Some(node) = node_queue.pop() {
+            if let Some(mesh) = &mut node.mesh {
+                f(mesh);
+            }
+            for child in &mut node.children {
+                node_queue.push(child);
+            }
+        }
+    }
+
     pub fn collect(&self) -> (Vec<Vertex>, Vec<u32>, Vec<DrawCommand>) {
         let mut vertex_buffer: Vec<Vertex> = Vec::new();
         let mut triangle_index_buffer: Vec<u32> = Vec::new();
@@ -180,18 +222,24 @@ impl Scene {
         while let Some(node) = node_queue.pop() {
             let world_transform = node.get_world_transform();
 
-            // if node has a mesh add it to "to-be-drawn" objects
+            // if node has a mesh add it to "to-be-drawn" objects, one draw command per material
             if let Some(mesh) = &node.mesh {
+                let mesh = mesh.sorted_by_material();
                 let vertex_offset = vertex_buffer.len(); // Store current vertex buffer length
+                let index_offset = triangle_index_buffer.len(); // Store current index buffer length
 
-                draw_command_buffer.push(DrawCommand {
-                    first_vertex_offset: vertex_buffer.len(), // Start index in vertex buffer (current length before adding new vertices)
-                    vertex_count: mesh.vertices.len(), // How many vertices this mesh contains
-                    first_triangle_index_offset: triangle_index_buffer.len(), // Start index in index buffer (current length before adding new indices)
-                    triangle_index_count: mesh.triangle_indices.len(), // How many indices this mesh contains
-                    material_id: mesh.material_indices[0] as usize, // Use first material ID found in mesh (temporary solution)
-                    transform: world_transform, // Store node's world transform (transformaton to place in world space) for vertex transformation
-                });
+                for range in &mesh.material_ranges {
+                    draw_command_buffer.push(DrawCommand {
+                        first_vertex_offset: vertex_offset + range.first_vertex, // Start index of the range's vertices in the vertex buffer
+                        vertex_count: range.vertex_count, // How many vertices the triangles of this material use
+                        first_triangle_index_offset: index_offset + range.first_triangle_index, // Start index of the range in the index buffer
+                        triangle_index_count: range.triangle_index_count, // How many indices have this material
+                        material: range.material, // Material shared by all triangles of the range
+                        transform: world_transform, // Store node's world transform (transformaton to place in world space) for vertex transformation
+                        bounds: range.bounds.transform(&world_transform), // Bounding sphere in world space for frustum culling
+                        stencil: node.stencil, // Stencil state of the node, disabled unless set
+                    });
+                }
                 vertex_buffer.extend(&mesh.vertices);
                 // Offset indices by vertex_offset before adding them
                 triangle_index_buffer.extend(
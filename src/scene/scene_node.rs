@@ -2,6 +2,7 @@
 
 use super::{Camera, Mesh, PointLight};
 use crate::math::{Mat4x4, Vector3D};
+use crate::renderer::StencilState;
 
 pub struct SceneNode {
     pub name: String,
@@ -14,7 +15,8 @@ pub struct SceneNode {
     pub mesh: Option<Mesh>, // Not all nodes need meshes (empty groups/pivots)
     pub camera: Option<Camera>,
     pub light: Option<PointLight>,
-    pub children: Vec<SceneNode>,     // Vector of child nodes
+    pub stencil: StencilState, // stencil test and ops for the node's mesh
+    pub children: Vec<SceneNode>, // Vector of child nodes
     pub transform_stack: Vec<Mat4x4>, // transformation stack stacks the necessary transformations from root to child for each node
 }
 
@@ -30,6 +32,7 @@ impl SceneNode {
         let mesh: Option<Mesh> = None;
         let camera: Option<Camera> = None;
         let light: Option<PointLight> = None;
+        let stencil = StencilState::DISABLED;
         let children: Vec<SceneNode> = Vec::new();
         let transform_stack: Vec<Mat4x4> = Vec::new();
 
@@ -42,6 +45,7 @@ impl SceneNode {
             mesh,
             camera,
             light,
+            stencil,
             children,
             transform_stack,
         }